@@ -0,0 +1,71 @@
+use docopt::Docopt;
+
+use qpick;
+use qpick::word_vec;
+
+use std::result::Result;
+
+use Error;
+
+const USAGE: &'static str = "
+Builds the ANN graphs of the query vectors of the ./index shards with i2q, saved as ann_file.<shard>.
+
+Usage:
+    qpick ann [options]
+    qpick ann --help
+
+Options:
+    -h, --help                 Show this help message.
+    -s, --start ARG            Shard to begin with.
+    -e, --end ARG              Shard to end with goes together with the --start option.
+    -m, --links ARG            Max number of links of a node per layer [default: 16].
+    -c, --ef-construction ARG  Size of the candidate list while building [default: 100].
+    -T, --type ARG             Type of the stored query vectors: f32, f16 or int8 [default: f32].
+";
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    flag_start: Option<u32>,
+    flag_end: Option<u32>,
+    flag_links: usize,
+    flag_ef_construction: usize,
+    flag_type: String,
+}
+
+pub fn run(argv: Vec<String>) -> Result<(), Error> {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(&argv).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let vec_type = match word_vec::VecType::get(&args.flag_type) {
+        Some(vec_type) => vec_type,
+        None => {
+            println!(
+                "Unknown vectors type: {}, expected one of: {}",
+                args.flag_type,
+                word_vec::VEC_TYPES.join(", ")
+            );
+            return Ok(());
+        }
+    };
+
+    let qpick: qpick::Qpick;
+    if let Some(start_shard) = args.flag_start {
+        if let Some(end_shard) = args.flag_end {
+            assert!(end_shard > start_shard);
+            qpick = qpick::Qpick::from_path_with_shard_range(
+                "./index".to_string(),
+                start_shard..end_shard,
+            );
+        } else {
+            panic!("Missing the end shard value! Run --help for more info!")
+        }
+    } else {
+        qpick = qpick::Qpick::from_path("./index".to_string());
+    }
+
+    let r = qpick.build_ann(args.flag_links, args.flag_ef_construction, vec_type);
+    println!("{:?}", r);
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use docopt::Docopt;
+
+use qpick;
+
+use std::collections::HashSet;
+use std::fs::{read_to_string, File};
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::result::Result;
+use std::time::Instant;
+
+use Error;
+
+const USAGE: &'static str = "
+Compares searching with the union map of all shards (created by qpick merge) to searching with
+the maps of the shards: mapped files, resident memory and queries per second (Linux only).
+
+Usage:
+    qpick bench [options] <queries> <count>
+    qpick bench --help
+
+Options:
+    -s, --start ARG    Shard to begin with.
+    -e, --end ARG      Shard to end with goes together with the --start option.
+    -h, --help         Arg queries is a file with a query per line.
+";
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    arg_queries: String,
+    arg_count: u32,
+    flag_start: Option<u32>,
+    flag_end: Option<u32>,
+}
+
+// files mapped into the memory of the process
+fn mapped_files() -> usize {
+    read_to_string("/proc/self/maps")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .filter(|path| path.starts_with("/"))
+        .collect::<HashSet<&str>>()
+        .len()
+}
+
+// resident memory of the process in KB
+fn resident_kb() -> i64 {
+    read_to_string("/proc/self/status")
+        .unwrap_or_default()
+        .lines()
+        .find(|line| line.starts_with("VmRSS:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<i64>().ok())
+        .unwrap_or(0)
+}
+
+fn bench(queries: &Vec<String>, count: u32, use_union_map: bool, shard_range: Option<Range<u32>>) {
+    let (files, rss) = (mapped_files(), resident_kb());
+
+    let qpick =
+        qpick::Qpick::from_path_with_union_map("./index".to_string(), use_union_map, shard_range);
+    let start = Instant::now();
+    let mut nr_results = 0;
+    for query in queries.iter() {
+        nr_results += qpick.get(query, count, false).len();
+    }
+    let secs = start.elapsed().as_secs_f64();
+
+    println!(
+        "{:<12}{:>14}{:>14.1}{:>14.1}{:>12}",
+        if use_union_map {
+            "union map"
+        } else {
+            "shard maps"
+        },
+        mapped_files() - files,
+        (resident_kb() - rss) as f64 / 1024.0,
+        queries.len() as f64 / secs,
+        nr_results
+    );
+}
+
+pub fn run(argv: Vec<String>) -> Result<(), Error> {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(&argv).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let shard_range = match (args.flag_start, args.flag_end) {
+        (Some(start_shard), Some(end_shard)) if end_shard > start_shard => {
+            Some(start_shard..end_shard)
+        }
+        (None, None) => None,
+        _ => return Err("The --start and --end options need a non-empty shard range!".into()),
+    };
+
+    let mut queries = vec![];
+    for query in BufReader::new(File::open(&args.arg_queries)?).lines() {
+        let query = query?;
+        if !query.trim().is_empty() {
+            queries.push(query.trim().to_string());
+        }
+    }
+
+    println!(
+        "{:<12}{:>14}{:>14}{:>14}{:>12}",
+        "maps", "mapped files", "resident MB", "queries/s", "results"
+    );
+    bench(&queries, args.arg_count, false, shard_range.clone());
+    bench(&queries, args.arg_count, true, shard_range);
+
+    Ok(())
+}
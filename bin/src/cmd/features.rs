@@ -0,0 +1,78 @@
+use docopt::Docopt;
+
+use qpick;
+use qpick::scorer;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::result::Result;
+
+use Error;
+
+const USAGE: &'static str = "
+Dumps the features of the candidates of queries, tab separated, for training ranking models.
+
+Usage:
+    qpick features [options] <queries> <count> [--with-tfidf]
+    qpick features --help
+
+Options:
+    -h, --help         Arg queries is a file with a query per line.
+    -s, --start ARG    Shard to begin with.
+    -e, --end ARG      Shard to end with goes together with the --start option.
+";
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    flag_start: Option<u32>,
+    flag_end: Option<u32>,
+    arg_queries: String,
+    arg_count: u32,
+    flag_with_tfidf: bool,
+}
+
+pub fn run(argv: Vec<String>) -> Result<(), Error> {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(&argv).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let qpick: qpick::Qpick;
+    if let Some(start_shard) = args.flag_start {
+        if let Some(end_shard) = args.flag_end {
+            assert!(end_shard > start_shard);
+            qpick = qpick::Qpick::from_path_with_shard_range(
+                "./index".to_string(),
+                start_shard..end_shard,
+            );
+        } else {
+            panic!("Missing the end shard value! Run --help for more info!")
+        }
+    } else {
+        qpick = qpick::Qpick::from_path("./index".to_string());
+    }
+
+    println!(
+        "query\tcandidate\tquery_id\t{}",
+        scorer::FEATURES.join("\t")
+    );
+    for query in BufReader::new(File::open(&args.arg_queries)?).lines() {
+        let query = query?;
+        let query = query.trim();
+        if query.is_empty() {
+            continue;
+        }
+
+        for (r, features) in qpick.get_features(query, args.arg_count, args.flag_with_tfidf) {
+            let features: Vec<String> = features.iter().map(|f| f.to_string()).collect();
+            println!(
+                "{}\t{}\t{}\t{}",
+                query,
+                r.query.unwrap_or("".to_string()),
+                r.query_id,
+                features.join("\t")
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -10,22 +10,36 @@ const USAGE: &'static str = "
 Get vector ids and scores for ANN.
 
 Usage:
-    qpick get [options] <query> <count> [--with-tfidf]
+    qpick get [options] <query> <count> [--with-tfidf] [--prefix]
     qpick get --help
 
 Options:
     -h, --help         Arg query is a query string.
     -s, --start ARG    Shard to begin with.
     -e, --end ARG      Shard to end with goes together with the --start option.
+    -o, --offset ARG   Return a page of <count> results starting at the offset.
+    -c, --cursor ARG   Return a page of <count> results following the one that returned the cursor.
+    -p, --prefix       Treat the last word of the query as a prefix (typeahead).
+    -f, --fuzzy ARG    Correct unknown words within ARG edits (1 or 2) before searching.
+    -w, --stopwords ARG  Use the named stopwords list from the config.
+    -S, --scorer ARG   Re-rank the candidates with the named scorer: default, keyword or model.
+
+All options can be combined, except --offset with --cursor.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
     flag_start: Option<u32>,
     flag_end: Option<u32>,
+    flag_offset: Option<u32>,
+    flag_cursor: Option<String>,
+    flag_fuzzy: Option<u32>,
+    flag_stopwords: Option<String>,
+    flag_scorer: Option<String>,
     arg_query: String,
     arg_count: u32,
     flag_with_tfidf: bool,
+    flag_prefix: bool,
 }
 
 pub fn run(argv: Vec<String>) -> Result<(), Error> {
@@ -33,6 +47,10 @@ pub fn run(argv: Vec<String>) -> Result<(), Error> {
         .and_then(|d| d.argv(&argv).deserialize())
         .unwrap_or_else(|e| e.exit());
 
+    if args.flag_offset.is_some() && args.flag_cursor.is_some() {
+        return Err("The --offset and --cursor options can't be used together!".into());
+    }
+
     let qpick: qpick::Qpick;
 
     println!("{:?}", args);
@@ -50,7 +68,64 @@ pub fn run(argv: Vec<String>) -> Result<(), Error> {
         qpick = qpick::Qpick::from_path("./index".to_string());
     }
 
-    let r = qpick.get(&args.arg_query, args.arg_count, args.flag_with_tfidf);
+    let scorer = match args.flag_scorer {
+        Some(ref name) => Some(qpick.load_scorer(name)?),
+        None => None,
+    };
+
+    let options = qpick::SearchOptions {
+        prefix: args.flag_prefix,
+        max_edits: args.flag_fuzzy,
+        stopwords: args.flag_stopwords.as_ref().map(|s| s.as_str()),
+        scorer: scorer.as_ref().map(|s| &**s),
+    };
+
+    if let Some(max_edits) = args.flag_fuzzy {
+        println!(
+            "corrections: {:?}",
+            qpick.get_corrections(&args.arg_query, max_edits)
+        );
+    }
+
+    let mut cursor = None;
+    let r = if let Some(ref c) = args.flag_cursor {
+        match qpick.get_page_with_cursor_and_options(
+            &args.arg_query,
+            c,
+            args.arg_count,
+            args.flag_with_tfidf,
+            &options,
+        ) {
+            Some(page) => {
+                cursor = page.cursor;
+                page.results
+            }
+            None => {
+                return Err(format!(
+                    "Invalid cursor {:?} for the query {:?}, it is malformed or issued for another query!",
+                    c, &args.arg_query
+                )
+                .into())
+            }
+        }
+    } else if let Some(offset) = args.flag_offset {
+        let page = qpick.get_page_with_options(
+            &args.arg_query,
+            offset,
+            args.arg_count,
+            args.flag_with_tfidf,
+            &options,
+        );
+        cursor = page.cursor;
+        page.results
+    } else {
+        qpick.search(
+            &args.arg_query,
+            args.arg_count,
+            args.flag_with_tfidf,
+            &options,
+        )
+    };
 
     let v: Vec<(u64, Option<f32>, f32, String)> = r
         .iter()
@@ -66,6 +141,9 @@ pub fn run(argv: Vec<String>) -> Result<(), Error> {
         .collect();
 
     println!("{:?}", v);
+    if args.flag_offset.is_some() || args.flag_cursor.is_some() {
+        println!("cursor: {:?}", cursor);
+    }
 
     Ok(())
 }
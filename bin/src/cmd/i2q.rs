@@ -1,22 +1,35 @@
 use docopt::Docopt;
+use std::path::Path;
 use Error;
 
 use qpick::stringvec;
 
 const USAGE: &'static str = "
-Creates an index-to-query mapping
+Creates an index-to-query mapping, dumps the queries of an i2q file
+
 Usage:
+    qpick i2q dump [options] <file>
     qpick i2q [options] <path> <output-dir>
     qpick i2q --help
+
 Options:
     -h, --help  path: is an input directory.
                 ouput-dir: where to save i2q index.
+                file: i2q file to print as tab separated query ids and queries.
+    -b, --block-size ARG  Strings per lz4 compressed block, uncompressed if 0 [default: 0].
+    -f, --from ARG        Index of the first query to dump [default: 0].
+    -t, --to ARG          Index after the last query to dump, all queries if not set.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
+    cmd_dump: bool,
+    arg_file: String,
     arg_path: String,
     arg_output_dir: String,
+    flag_block_size: usize,
+    flag_from: usize,
+    flag_to: Option<usize>,
 }
 
 pub fn run(argv: Vec<String>) -> Result<(), Error> {
@@ -24,7 +37,17 @@ pub fn run(argv: Vec<String>) -> Result<(), Error> {
         .and_then(|d| d.argv(&argv).deserialize())
         .unwrap_or_else(|e| e.exit());
 
-    let r = stringvec::compile(&args.arg_path, &args.arg_output_dir);
+    if args.cmd_dump {
+        let str_vec = stringvec::StrVec::load(Path::new(&args.arg_file));
+        let to = args.flag_to.unwrap_or(str_vec.len());
+        for (idx, query) in (args.flag_from..).zip(str_vec.range(args.flag_from..to)) {
+            println!("{}\t{}", idx, query);
+        }
+
+        return Ok(());
+    }
+
+    let r = stringvec::compile(&args.arg_path, &args.arg_output_dir, args.flag_block_size);
     println!("{:?}", r);
 
     Ok(())
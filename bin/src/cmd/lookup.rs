@@ -0,0 +1,54 @@
+use docopt::Docopt;
+
+use qpick;
+
+use std::result::Result;
+
+use Error;
+
+const USAGE: &'static str = "
+Prints the query texts of query ids, tab separated, an empty text if the id is not found.
+
+Usage:
+    qpick lookup [options] <ids>...
+    qpick lookup --help
+
+Options:
+    -h, --help         Arg ids are global query ids.
+    -s, --start ARG    Shard to begin with.
+    -e, --end ARG      Shard to end with goes together with the --start option.
+";
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    flag_start: Option<u32>,
+    flag_end: Option<u32>,
+    arg_ids: Vec<u64>,
+}
+
+pub fn run(argv: Vec<String>) -> Result<(), Error> {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(&argv).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let qpick: qpick::Qpick;
+    if let Some(start_shard) = args.flag_start {
+        if let Some(end_shard) = args.flag_end {
+            assert!(end_shard > start_shard);
+            qpick = qpick::Qpick::from_path_with_shard_range(
+                "./index".to_string(),
+                start_shard..end_shard,
+            );
+        } else {
+            panic!("Missing the end shard value! Run --help for more info!")
+        }
+    } else {
+        qpick = qpick::Qpick::from_path("./index".to_string());
+    }
+
+    for (query_id, query) in args.arg_ids.iter().zip(qpick.get_queries(&args.arg_ids)) {
+        println!("{}\t{}", query_id, query.unwrap_or_default());
+    }
+
+    Ok(())
+}
@@ -1,6 +1,12 @@
+pub mod ann;
+pub mod bench;
 pub mod dists;
+pub mod features;
 pub mod get;
 pub mod i2q;
 pub mod index;
+pub mod lookup;
 pub mod merge;
 pub mod shard;
+pub mod synonyms;
+pub mod vectors;
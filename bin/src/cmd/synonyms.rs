@@ -0,0 +1,55 @@
+use docopt::Docopt;
+use std::path::Path;
+use Error;
+
+use qpick::analyzer;
+use qpick::synonyms;
+
+const USAGE: &'static str = "
+Compiles a synonyms text file into an fst synonyms dictionary
+
+Usage:
+    qpick synonyms [options] <input> <output>
+    qpick synonyms --help
+
+Options:
+    -a, --analyzer ARG        Analyzer used to normalize phrases, the one set in config.json [default: default].
+    -v, --config-version ARG  Index config.json version, 2 or higher for Unicode normalization [default: 1].
+    -h, --help                input: synonyms text file, one phrase and its alternatives per line.
+                              output: fst file to create, set it as synonyms_file in config.json.
+";
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    arg_input: String,
+    arg_output: String,
+    flag_analyzer: String,
+    flag_config_version: u64,
+}
+
+pub fn run(argv: Vec<String>) -> Result<(), Error> {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(&argv).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let analyzer = match analyzer::get(&args.flag_analyzer, args.flag_config_version) {
+        Some(analyzer) => analyzer,
+        None => {
+            println!(
+                "Unknown analyzer: {}, expected one of: {}",
+                args.flag_analyzer,
+                analyzer::ANALYZERS.join(", ")
+            );
+            return Ok(());
+        }
+    };
+
+    let r = synonyms::compile(
+        Path::new(&args.arg_input),
+        Path::new(&args.arg_output),
+        &*analyzer,
+    );
+    println!("{:?}", r);
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use docopt::Docopt;
+use std::path::Path;
+use Error;
+
+use qpick::analyzer;
+use qpick::vectors;
+use qpick::word_vec;
+
+const USAGE: &'static str = "
+Imports word2vec, GloVe or fastText word vectors as words_file and word_vecs_file of an index,
+converts word vectors to f32, f16 or int8 vectors, imports subword vectors of a fastText .bin model
+
+Usage:
+    qpick vectors import [options] <format> <input> <words-output> <vectors-output>
+    qpick vectors convert [options] <input> <vectors-output>
+    qpick vectors subwords [options] <input> <vectors-output>
+    qpick vectors --help
+
+Options:
+    -T, --type ARG              Type of the output vectors: f32, f16 or int8 [default: f32].
+    -n, --normalize             Normalize the vectors to unit length.
+    -t, --terms-relevance ARG   Import only the words in the terms relevance fst, reports coverage.
+    -a, --analyzer ARG          Normalize the words with the analyzer set in config.json.
+    -v, --config-version ARG    Index config.json version, 2 or higher for Unicode normalization [default: 1].
+    -d, --dim ARG               Dimension of an input vectors file without a header [default: 0].
+    -h, --help                  format: word2vec-bin, word2vec, fasttext (.vec) or glove.
+                                input: word vectors file to import or to convert, fastText .bin for subwords.
+                                words-output: words file to create, set it as words_file in config.json.
+                                vectors-output: vectors file to create, set it as word_vecs_file.
+";
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    cmd_import: bool,
+    cmd_convert: bool,
+    cmd_subwords: bool,
+    arg_format: String,
+    arg_input: String,
+    arg_words_output: String,
+    arg_vectors_output: String,
+    flag_type: String,
+    flag_normalize: bool,
+    flag_terms_relevance: Option<String>,
+    flag_analyzer: Option<String>,
+    flag_config_version: u64,
+    flag_dim: usize,
+}
+
+pub fn run(argv: Vec<String>) -> Result<(), Error> {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(&argv).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let vec_type = match word_vec::VecType::get(&args.flag_type) {
+        Some(vec_type) => vec_type,
+        None => {
+            println!(
+                "Unknown word vectors type: {}, expected one of: {}",
+                args.flag_type,
+                word_vec::VEC_TYPES.join(", ")
+            );
+            return Ok(());
+        }
+    };
+
+    if args.cmd_convert {
+        let r = vectors::convert(
+            Path::new(&args.arg_input),
+            Path::new(&args.arg_vectors_output),
+            vec_type,
+            args.flag_dim,
+        );
+        println!("{:?}", r);
+
+        return Ok(());
+    }
+
+    if args.cmd_subwords {
+        let stats = vectors::import_subwords(
+            Path::new(&args.arg_input),
+            Path::new(&args.arg_vectors_output),
+            vec_type,
+        )?;
+        println!("{:?}", stats);
+
+        return Ok(());
+    }
+
+    let format = match vectors::Format::get(&args.arg_format) {
+        Some(format) => format,
+        None => {
+            println!(
+                "Unknown word vectors format: {}, expected one of: {}",
+                args.arg_format,
+                vectors::FORMATS.join(", ")
+            );
+            return Ok(());
+        }
+    };
+
+    let analyzer = match args.flag_analyzer {
+        Some(ref name) => match analyzer::get(name, args.flag_config_version) {
+            Some(analyzer) => Some(analyzer),
+            None => {
+                println!(
+                    "Unknown analyzer: {}, expected one of: {}",
+                    name,
+                    analyzer::ANALYZERS.join(", ")
+                );
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let stats = vectors::import(
+        Path::new(&args.arg_input),
+        format,
+        Path::new(&args.arg_words_output),
+        Path::new(&args.arg_vectors_output),
+        vec_type,
+        args.flag_normalize,
+        args.flag_terms_relevance.as_ref().map(|p| Path::new(p)),
+        analyzer.as_ref().map(|a| &**a),
+    )?;
+
+    println!("{:?}", stats);
+    if let Some(coverage) = stats.coverage() {
+        println!(
+            "coverage: {:.2}% of {} terms",
+            100.0 * coverage,
+            stats.terms
+        );
+    }
+
+    Ok(())
+}
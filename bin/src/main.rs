@@ -37,12 +37,18 @@ struct Args {
 
 #[derive(Debug, Deserialize)]
 enum Command {
+    Ann,
+    Bench,
     I2q,
     Get,
     Shard,
     Index,
+    Lookup,
     Merge,
     Dists,
+    Features,
+    Synonyms,
+    Vectors,
 }
 
 impl Command {
@@ -51,12 +57,18 @@ impl Command {
 
         let argv: Vec<String> = env::args().collect();
         match self {
+            Ann => cmd::ann::run(argv),
+            Bench => cmd::bench::run(argv),
             Get => cmd::get::run(argv),
             I2q => cmd::i2q::run(argv),
             Shard => cmd::shard::run(argv),
             Index => cmd::index::run(argv),
+            Lookup => cmd::lookup::run(argv),
             Merge => cmd::merge::run(argv),
             Dists => cmd::dists::run(argv),
+            Features => cmd::features::run(argv),
+            Synonyms => cmd::synonyms::run(argv),
+            Vectors => cmd::vectors::run(argv),
         }
     }
 }
@@ -20,3 +20,9 @@ url = 'http://localhost:6007/rpc'
 query = 'changing mac os menu bar'
 args = {'Q': query, 'C': 100, 'TFIDF': 1}
 print(rpc_call(url, "QPickRPCService.Get", args).get('result', []))
+
+# paginated results, pass the returned cursor to get the next page
+args = {'Q': query, 'Offset': 0, 'Limit': 10, 'Cursor': '', 'TFIDF': 1}
+page = json.loads(rpc_call(url, "QPickRPCService.GetPage", args).get('result', '{}'))
+args['Cursor'] = page.get('cursor') or ''
+print(page.get('results', []))
@@ -6,6 +6,9 @@ Qpick* qpick_init(char*);
 Qpick* qpick_init_with_shard_range(char*, uint32_t, uint32_t);
 void qpick_free(Qpick*);
 char* qpick_get_as_string(Qpick*, char*, uint32_t, uint8_t);
+char* qpick_get_page_as_string(Qpick*, char*, uint32_t, uint32_t, char*, uint8_t);
+char* qpick_get_queries_as_string(Qpick*, char*);
+char* qpick_lookup_ids_as_string(Qpick*, char*);
 void string_free(char*);
 
 /**
@@ -89,6 +89,43 @@ pub extern "C" fn qpick_get_as_string(
     CString::new(s).unwrap().into_raw()
 }
 
+// `cursor` is an empty string for the first page
+#[no_mangle]
+pub extern "C" fn qpick_get_page_as_string(
+    ptr: *mut Qpick,
+    query: *mut libc::c_char,
+    offset: libc::uint32_t,
+    limit: libc::uint32_t,
+    cursor: *mut libc::c_char,
+    with_tfidf: libc::uint8_t,
+) -> *const libc::c_char {
+    let query = cstr_to_str(query);
+    let cursor = cstr_to_str(cursor);
+    let cursor = if cursor.is_empty() { None } else { Some(cursor) };
+    let s = ref_from_ptr!(ptr).get_page_as_string(query, offset, limit, cursor, with_tfidf != 0);
+    CString::new(s).unwrap().into_raw()
+}
+
+// Query texts of newline separated query ids as a JSON list, null for the ids not found
+#[no_mangle]
+pub extern "C" fn qpick_get_queries_as_string(
+    ptr: *mut Qpick,
+    query_ids: *mut libc::c_char,
+) -> *mut libc::c_char {
+    let query_ids = cstr_to_str(query_ids);
+    str_to_cstr(&ref_from_ptr!(ptr).get_queries_as_string(query_ids))
+}
+
+// Query ids of newline separated queries as a JSON list, null for the queries not indexed
+#[no_mangle]
+pub extern "C" fn qpick_lookup_ids_as_string(
+    ptr: *mut Qpick,
+    queries: *mut libc::c_char,
+) -> *mut libc::c_char {
+    let queries = cstr_to_str(queries);
+    str_to_cstr(&ref_from_ptr!(ptr).lookup_ids_as_string(queries))
+}
+
 // Get a mutable reference from a raw pointer
 macro_rules! mutref_from_ptr {
     ($p: ident) => {
@@ -13,6 +13,7 @@ import (
 	"github.com/gorilla/rpc/json"
 	"log"
 	"net/http"
+	"strings"
 )
 
 const (
@@ -32,6 +33,20 @@ type Args struct {
 	TFIDF uint8
 }
 
+//Holds arguments for a paginated RPC call, Cursor is empty for the first page
+type PageArgs struct {
+	Q      string
+	Offset uint32
+	Limit  uint32
+	Cursor string
+	TFIDF  uint8
+}
+
+//Holds the query ids or the queries of a batch lookup
+type ListArgs struct {
+	L []string
+}
+
 //Represents service QPickRPCService with method Multiply
 type QPickRPCService int
 
@@ -45,6 +60,25 @@ func (t *QPickRPCService) Get(r *http.Request, args *Args, result *Result) error
 	return nil
 }
 
+func (t *QPickRPCService) GetPage(r *http.Request, args *PageArgs, result *Result) error {
+	var res = C.qpick_get_page_as_string(qpick, C.CString(args.Q), C.uint32_t(args.Offset),
+		C.uint32_t(args.Limit), C.CString(args.Cursor), C.uint8_t(args.TFIDF))
+	*result = Result(C.GoString(res))
+	return nil
+}
+
+func (t *QPickRPCService) GetQueries(r *http.Request, args *ListArgs, result *Result) error {
+	var res = C.qpick_get_queries_as_string(qpick, C.CString(strings.Join(args.L, "\n")))
+	*result = Result(C.GoString(res))
+	return nil
+}
+
+func (t *QPickRPCService) LookupIds(r *http.Request, args *ListArgs, result *Result) error {
+	var res = C.qpick_lookup_ids_as_string(qpick, C.CString(strings.Join(args.L, "\n")))
+	*result = Result(C.GoString(res))
+	return nil
+}
+
 func init() {
 	port = flag.String("port", PORT_DEFAULT, "Main port for serving requests; defaults to 8888 if unspecified")
 	indexPath = flag.String("index", "", "REQUIRED. Directory path of the qpick index")
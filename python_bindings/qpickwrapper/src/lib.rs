@@ -127,11 +127,19 @@ pub extern "C" fn qpick_index(
 }
 
 #[no_mangle]
-pub extern "C" fn qpick_compile_i2q(file_path: *mut libc::c_char, output_dir: *mut libc::c_char) {
+pub extern "C" fn qpick_compile_i2q(
+    file_path: *mut libc::c_char,
+    output_dir: *mut libc::c_char,
+    block_size: libc::uint32_t,
+) {
     let file_path = cstr_to_str(file_path);
     let output_dir = cstr_to_str(output_dir);
 
-    let r = stringvec::compile(&file_path.to_string(), &output_dir.to_string());
+    let r = stringvec::compile(
+        &file_path.to_string(),
+        &output_dir.to_string(),
+        block_size as usize,
+    );
     println!("{:?}", r);
 }
 // end shard, index and i2q bindings
@@ -202,6 +210,23 @@ pub extern "C" fn qpick_search_iter_next(ptr: *mut qpick::SearchResults) -> *mut
     }
 }
 
+// Returns the continuation cursor of a page of search results, null if there is no next page
+#[no_mangle]
+pub extern "C" fn qpick_search_results_cursor(ptr: *mut qpick::SearchResults) -> *mut libc::c_char {
+    match ref_from_ptr!(ptr).cursor {
+        Some(ref cursor) => str_to_cstr(cursor),
+        None => ::std::ptr::null_mut(),
+    }
+}
+
+// Returns corrections applied by the fuzzy search as a json list of [word, correction] pairs
+#[no_mangle]
+pub extern "C" fn qpick_search_results_corrections(
+    ptr: *mut qpick::SearchResults,
+) -> *mut libc::c_char {
+    str_to_cstr(&ref_from_ptr!(ptr).corrections_as_string())
+}
+
 make_free_fn!(qpick_search_results_free, *mut qpick::SearchResults);
 make_free_fn!(qpick_search_item_free, *mut QpickSearchItem);
 make_free_fn!(qpick_distance_free, *mut QpickDistance);
@@ -250,6 +275,63 @@ pub extern "C" fn qpick_get(
     to_raw_ptr(res)
 }
 
+#[no_mangle]
+pub extern "C" fn qpick_get_fuzzy(
+    ptr: *mut Qpick,
+    query: *mut libc::c_char,
+    count: libc::uint32_t,
+    with_tfidf: libc::uint8_t,
+    max_edits: libc::uint32_t,
+) -> *mut qpick::SearchResults {
+    let query = cstr_to_str(query);
+    let res = ref_from_ptr!(ptr).get_fuzzy_results(query, count, with_tfidf != 0, max_edits);
+
+    to_raw_ptr(res)
+}
+
+// the last word of the query is treated as a prefix
+#[no_mangle]
+pub extern "C" fn qpick_complete(
+    ptr: *mut Qpick,
+    query: *mut libc::c_char,
+    count: libc::uint32_t,
+    with_tfidf: libc::uint8_t,
+) -> *mut qpick::SearchResults {
+    let query = cstr_to_str(query);
+    let res = ref_from_ptr!(ptr).get_completion_results(query, count, with_tfidf != 0);
+
+    to_raw_ptr(res)
+}
+
+#[no_mangle]
+pub extern "C" fn qpick_get_page(
+    ptr: *mut Qpick,
+    query: *mut libc::c_char,
+    offset: libc::uint32_t,
+    limit: libc::uint32_t,
+    with_tfidf: libc::uint8_t,
+) -> *mut qpick::SearchResults {
+    let query = cstr_to_str(query);
+    let res = ref_from_ptr!(ptr).get_page_results(query, offset, limit, with_tfidf != 0);
+
+    to_raw_ptr(res)
+}
+
+#[no_mangle]
+pub extern "C" fn qpick_get_page_with_cursor(
+    ptr: *mut Qpick,
+    query: *mut libc::c_char,
+    cursor: *mut libc::c_char,
+    limit: libc::uint32_t,
+    with_tfidf: libc::uint8_t,
+) -> *mut qpick::SearchResults {
+    let query = cstr_to_str(query);
+    let cursor = cstr_to_str(cursor);
+    let res = ref_from_ptr!(ptr).get_cursor_results(query, cursor, limit, with_tfidf != 0);
+
+    to_raw_ptr(res)
+}
+
 #[no_mangle]
 pub extern "C" fn qpick_get_distances(
     ptr: *mut Qpick,
@@ -258,3 +340,32 @@ pub extern "C" fn qpick_get_distances(
 ) -> *mut qpick::DistResults {
     to_raw_ptr(ref_from_ptr!(ptr).get_dist_results(cstr_to_str(query), ref_from_ptr!(queries)))
 }
+
+// Returns the query text of a query id, null if the query is not found
+#[no_mangle]
+pub extern "C" fn qpick_get_query(ptr: *mut Qpick, query_id: libc::uint64_t) -> *mut libc::c_char {
+    match ref_from_ptr!(ptr).get_query(query_id) {
+        Some(query) => str_to_cstr(&query),
+        None => ::std::ptr::null_mut(),
+    }
+}
+
+// Query texts of newline separated query ids as a JSON list, null for the ids not found
+#[no_mangle]
+pub extern "C" fn qpick_get_queries_as_string(
+    ptr: *mut Qpick,
+    query_ids: *mut libc::c_char,
+) -> *mut libc::c_char {
+    let query_ids = cstr_to_str(query_ids);
+    str_to_cstr(&ref_from_ptr!(ptr).get_queries_as_string(query_ids))
+}
+
+// Query ids of newline separated queries as a JSON list, null for the queries not indexed
+#[no_mangle]
+pub extern "C" fn qpick_lookup_ids_as_string(
+    ptr: *mut Qpick,
+    queries: *mut libc::c_char,
+) -> *mut libc::c_char {
+    let queries = cstr_to_str(queries);
+    str_to_cstr(&ref_from_ptr!(ptr).lookup_ids_as_string(queries))
+}
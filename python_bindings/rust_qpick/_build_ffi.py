@@ -28,12 +28,22 @@ ffi.cdef("""
     typedef struct SearchResults SearchResults;
 
     SearchResults* qpick_get(Qpick*, char*, uint32_t, uint8_t);
+    SearchResults* qpick_get_fuzzy(Qpick*, char*, uint32_t, uint8_t, uint32_t);
+    SearchResults* qpick_complete(Qpick*, char*, uint32_t, uint8_t);
+    SearchResults* qpick_get_page(Qpick*, char*, uint32_t, uint32_t, uint8_t);
+    SearchResults* qpick_get_page_with_cursor(Qpick*, char*, char*, uint32_t, uint8_t);
     QpickSearchItem* qpick_search_iter_next(SearchResults*);
+    char* qpick_search_results_cursor(SearchResults*);
+    char* qpick_search_results_corrections(SearchResults*);
 
     void qpick_search_results_free(SearchResults*);
     void qpick_search_item_free(QpickSearchItem*);
     void qpick_distance_free(QpickDistance*);
 
+    char* qpick_get_query(Qpick*, uint64_t);
+    char* qpick_get_queries_as_string(Qpick*, char*);
+    char* qpick_lookup_ids_as_string(Qpick*, char*);
+
     /**
        string vec
     **/
@@ -62,7 +72,7 @@ ffi.cdef("""
         shard, index, compile_i2q API
     **/
     void qpick_shard(char*, uint32_t, char*, StringVec*, uint8_t);
-    void qpick_compile_i2q(char*, char*);
+    void qpick_compile_i2q(char*, char*, uint32_t);
     void qpick_index(char*, uint32_t, uint32_t, char*);
 
 """)
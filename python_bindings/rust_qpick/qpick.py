@@ -1,4 +1,5 @@
 import os
+import json
 from .lib import ffi, lib
 
 class QpickResults(object):
@@ -117,6 +118,69 @@ class Qpick(object):
                                 lib.qpick_search_item_free,
                                 lib.qpick_distance_free)
 
+    # qpick.get_fuzzy('chnage mac os menu bar', max_edits=2)
+    def get_fuzzy(self, query, count=100, with_tfidf=False, max_edits=2):
+        """Returns search results and a list of applied (word, correction) pairs."""
+        if type(query) == str:
+            query = query.encode('utf-8')
+        with_tfidf = int(with_tfidf)
+        res_ptr = lib.qpick_get_fuzzy(self._ptr, query, count, with_tfidf, max_edits)
+
+        c = lib.qpick_search_results_corrections(res_ptr)
+        corrections = [tuple(wc) for wc in json.loads(ffi.string(c).decode('utf8'))]
+        lib.string_free(c)
+
+        results = QpickSearchResults(res_ptr,
+                                lib.qpick_search_iter_next,
+                                lib.qpick_search_results_free,
+                                lib.qpick_search_item_free,
+                                lib.qpick_distance_free)
+
+        return (list(results), corrections)
+
+    # qpick.complete('how to chan'), the last word is a prefix
+    def complete(self, query, count=10, with_tfidf=False):
+        if type(query) == str:
+            query = query.encode('utf-8')
+        with_tfidf = int(with_tfidf)
+        res_ptr = lib.qpick_complete(self._ptr, query, count, with_tfidf)
+
+        return QpickSearchResults(res_ptr,
+                                lib.qpick_search_iter_next,
+                                lib.qpick_search_results_free,
+                                lib.qpick_search_item_free,
+                                lib.qpick_distance_free)
+
+    # qpick.get_page('a', 10, offset=20) or qpick.get_page('a', 10, cursor=cursor)
+    def get_page(self, query, limit=10, offset=0, cursor=None, with_tfidf=False):
+        """Returns a page of results and a cursor for the next page (None on the last page)."""
+        if type(query) == str:
+            query = query.encode('utf-8')
+        with_tfidf = int(with_tfidf)
+
+        if cursor is not None:
+            if type(cursor) == str:
+                cursor = cursor.encode('utf-8')
+            res_ptr = lib.qpick_get_page_with_cursor(self._ptr, query, cursor, limit, with_tfidf)
+        else:
+            res_ptr = lib.qpick_get_page(self._ptr, query, offset, limit, with_tfidf)
+
+        next_cursor = lib.qpick_search_results_cursor(res_ptr)
+        if next_cursor == ffi.NULL:
+            next_cursor = None
+        else:
+            c = next_cursor
+            next_cursor = ffi.string(c).decode('utf8')
+            lib.string_free(c)
+
+        results = QpickSearchResults(res_ptr,
+                                lib.qpick_search_iter_next,
+                                lib.qpick_search_results_free,
+                                lib.qpick_search_item_free,
+                                lib.qpick_distance_free)
+
+        return (list(results), next_cursor)
+
     # qpick.get_distances('q', ['a', 'b', 'c'])
     def get_distances(self, query, candidates):
         qvec = lib.string_vec_init()
@@ -138,6 +202,40 @@ class Qpick(object):
                                 lib.qpick_dist_item_free,
                                 lib.qpick_distance_free)
 
+    # qpick.get_query(12), None if the query id is not found
+    def get_query(self, query_id):
+        c = lib.qpick_get_query(self._ptr, query_id)
+        if c == ffi.NULL:
+            return None
+
+        query = ffi.string(c).decode('utf8')
+        lib.string_free(c)
+
+        return query
+
+    # qpick.get_queries([12, 15]), None for the query ids that are not found
+    def get_queries(self, query_ids):
+        query_ids = '\n'.join(str(query_id) for query_id in query_ids).encode('utf-8')
+        c = lib.qpick_get_queries_as_string(self._ptr, query_ids)
+        queries = json.loads(ffi.string(c).decode('utf8'))
+        lib.string_free(c)
+
+        return queries
+
+    # qpick.lookup_ids(['changing mac menu bar', 'a']), None for the queries not indexed
+    def lookup_ids(self, queries):
+        queries = '\n'.join(q.decode('utf-8') if type(q) == bytes else q for q in queries)
+        queries = queries.encode('utf-8')
+        c = lib.qpick_lookup_ids_as_string(self._ptr, queries)
+        query_ids = json.loads(ffi.string(c).decode('utf8'))
+        lib.string_free(c)
+
+        return query_ids
+
+    # qpick.lookup_id('changing mac menu bar'), None if the query is not indexed
+    def lookup_id(self, query):
+        return self.lookup_ids([query])[0]
+
 
 def shard(file_path, nr_shards, output_dir, prefixes=[], create_i2q=True):
     if type(file_path) == str:
@@ -157,14 +255,14 @@ def shard(file_path, nr_shards, output_dir, prefixes=[], create_i2q=True):
 
     lib.qpick_shard(file_path, nr_shards, output_dir, pref_vec_ptr, create_i2q)
 
-def compile_i2q(file_path, output_dir):
+def compile_i2q(file_path, output_dir, block_size=0):
     if type(file_path) == str:
         file_path = file_path.encode()
 
     if type(output_dir) == str:
         output_dir = output_dir.encode()
 
-    lib.qpick_compile_i2q(file_path, output_dir)
+    lib.qpick_compile_i2q(file_path, output_dir, block_size)
 
 
 def index(input_dir, first_shard, last_shard, output_dir):
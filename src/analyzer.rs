@@ -0,0 +1,468 @@
+/*
+ Language analyzers, normalize queries into lowercase words separated by spaces.
+
+ The analyzer is selected by name in config.json ("analyzer", "default" if not set)
+ and the same one has to be used for sharding (index time) and by Qpick (search time),
+ synonyms are normalized with it too:
+
+   default, de  lowercase, German umlauts folding, ASCII punctuation, digits splitting
+   fr           French accent folding, elisions (l', d', qu' ...) dropped
+   tr           Turkish casing (I -> ı, İ -> i)
+   cjk          Han and kana segmentation, every Han character is a word
+
+ Indexes with config.json "version" 2 or higher normalize Unicode around the analyzer
+ (UnicodeAnalyzer): NFKC, Unicode punctuation and whitespace before it, case folding and
+ diacritics stripping after it. Older indexes keep the analyzer as is.
+
+ With a stemmer set in config.json ("stemmer": "en" or "de") the analyzer stems words too
+ (StemmingAnalyzer), see stemmer.rs.
+*/
+
+use regex::Regex;
+use std::borrow::Cow;
+
+use config::Config;
+use stemmer;
+use stemmer::Stemmer;
+use util::{BRED, BYELL, ECOL};
+
+use ngrams::{
+    case_fold, separate_digits, strip_diacritics, u8_find_and_replace, u8_normalize_umlauts,
+    unicode_find_and_replace,
+};
+
+pub const DEFAULT_ANALYZER: &str = "default";
+pub const ANALYZERS: [&str; 5] = ["default", "de", "fr", "tr", "cjk"];
+pub const UNICODE_NORMALIZATION_VERSION: u64 = 2; // first config version with Unicode normalization
+
+pub trait Analyzer: Send + Sync {
+    // name the analyzer is selected by in config.json
+    fn name(&self) -> &'static str;
+
+    // normalizes a query into lowercase words separated by spaces
+    fn normalize(&self, query: &str) -> String;
+
+    // stem of a normalized word, ngrams are built from stems, no stemming by default
+    fn stem(&self, word: &str) -> String {
+        word.to_string()
+    }
+}
+
+// returns the analyzer by its name, config_version is the index config.json version
+pub fn get(name: &str, config_version: u64) -> Option<Box<dyn Analyzer>> {
+    let analyzer: Box<dyn Analyzer> = match name {
+        "default" | "de" => Box::new(DefaultAnalyzer),
+        "fr" => Box::new(FrenchAnalyzer),
+        "tr" => Box::new(TurkishAnalyzer),
+        "cjk" => Box::new(CjkAnalyzer),
+        _ => return None,
+    };
+
+    if config_version >= UNICODE_NORMALIZATION_VERSION {
+        return Some(Box::new(UnicodeAnalyzer::new(analyzer)));
+    }
+
+    Some(analyzer)
+}
+
+// analyzer and stemmer set in the index config.json
+pub fn from_config(c: &Config) -> Box<dyn Analyzer> {
+    let analyzer = match get(&c.analyzer, c.version) {
+        Some(analyzer) => analyzer,
+        None => panic!([
+            BYELL,
+            "Unknown analyzer: ",
+            ECOL,
+            BRED,
+            &c.analyzer,
+            ECOL,
+            BYELL,
+            ", expected one of: ",
+            ECOL,
+            &ANALYZERS.join(", "),
+        ]
+        .join("")),
+    };
+
+    if c.stemmer.is_empty() {
+        return analyzer;
+    }
+
+    match stemmer::get(&c.stemmer) {
+        Some(stemmer) => Box::new(StemmingAnalyzer::new(analyzer, stemmer)),
+        None => panic!([
+            BYELL,
+            "Unknown stemmer: ",
+            ECOL,
+            BRED,
+            &c.stemmer,
+            ECOL,
+            BYELL,
+            ", expected one of: ",
+            ECOL,
+            &stemmer::STEMMERS.join(", "),
+        ]
+        .join("")),
+    }
+}
+
+// Replaces non ASCII punctuation and symbols (« » ¿ 。、「」 etc.) with a space
+#[inline]
+pub fn unicode_punctuation<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    let input = input.into();
+    let is_punct = |c: char| !c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace();
+    if !input.chars().any(is_punct) {
+        return input;
+    }
+
+    Cow::Owned(
+        input
+            .chars()
+            .map(|c| if is_punct(c) { ' ' } else { c })
+            .collect(),
+    )
+}
+
+// Folds French accented letters and ligatures to ASCII
+#[inline]
+pub fn fold_french_accents<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new("[àâäéèêëîïôöùûüÿçœæ]").unwrap();
+    }
+    let input = input.into();
+    if !REGEX.is_match(&input) {
+        return input;
+    }
+
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            'à' | 'â' | 'ä' => output.push('a'),
+            'é' | 'è' | 'ê' | 'ë' => output.push('e'),
+            'î' | 'ï' => output.push('i'),
+            'ô' | 'ö' => output.push('o'),
+            'ù' | 'û' | 'ü' => output.push('u'),
+            'ÿ' => output.push('y'),
+            'ç' => output.push('c'),
+            'œ' => output.push_str("oe"),
+            'æ' => output.push_str("ae"),
+            _ => output.push(c),
+        }
+    }
+
+    Cow::Owned(output)
+}
+
+// Drops French elisions: l'homme -> homme, qu'il -> il, aujourd'hui is kept as aujourdhui
+#[inline]
+pub fn drop_french_elisions<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    lazy_static! {
+        static ref REGEX: Regex =
+            Regex::new(r"\b(?:l|d|j|m|n|s|t|c|qu|jusqu|lorsqu|puisqu)['’]").unwrap();
+    }
+    let input = input.into();
+    if !REGEX.is_match(&input) {
+        return input;
+    }
+
+    Cow::Owned(REGEX.replace_all(&input, " ").replace("’", ""))
+}
+
+// Lowercases with Turkish casing rules, dotted and dotless i are different letters
+#[inline]
+pub fn turkish_lowercase(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            'I' => output.push('ı'),
+            'İ' => output.push('i'),
+            _ => output.extend(c.to_lowercase()),
+        }
+    }
+
+    output
+}
+
+#[inline]
+fn is_han(c: char) -> bool {
+    match c as u32 {
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => true,
+        _ => false,
+    }
+}
+
+#[inline]
+fn is_kana(c: char) -> bool {
+    match c as u32 {
+        0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => true,
+        _ => false,
+    }
+}
+
+// Segments CJK text: every Han character is a word, runs of kana are words,
+// words of other scripts (Hangul, Latin etc.) are separated from them.
+#[inline]
+pub fn segment_cjk<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    let input = input.into();
+    if !input.chars().any(|c| is_han(c) || is_kana(c)) {
+        return input;
+    }
+
+    let mut output = String::with_capacity(2 * input.len());
+    let mut prev_kana = false;
+    for c in input.chars() {
+        if is_han(c) {
+            output.push(' ');
+            output.push(c);
+            output.push(' ');
+            prev_kana = false;
+        } else if is_kana(c) {
+            if !prev_kana {
+                output.push(' ');
+            }
+            output.push(c);
+            prev_kana = true;
+        } else {
+            if prev_kana {
+                output.push(' ');
+            }
+            output.push(c);
+            prev_kana = false;
+        }
+    }
+
+    Cow::Owned(output)
+}
+
+// The original qpick normalization, German umlauts are folded
+pub struct DefaultAnalyzer;
+
+impl Analyzer for DefaultAnalyzer {
+    fn name(&self) -> &'static str {
+        "default"
+    }
+
+    #[inline]
+    fn normalize(&self, query: &str) -> String {
+        separate_digits(u8_normalize_umlauts(
+            u8_find_and_replace(query).to_lowercase(),
+        ))
+        .trim()
+        .to_string()
+    }
+}
+
+pub struct FrenchAnalyzer;
+
+impl Analyzer for FrenchAnalyzer {
+    fn name(&self) -> &'static str {
+        "fr"
+    }
+
+    #[inline]
+    fn normalize(&self, query: &str) -> String {
+        let query = drop_french_elisions(query.to_lowercase());
+        separate_digits(unicode_punctuation(fold_french_accents(
+            u8_find_and_replace(query),
+        )))
+        .trim()
+        .to_string()
+    }
+}
+
+pub struct TurkishAnalyzer;
+
+impl Analyzer for TurkishAnalyzer {
+    fn name(&self) -> &'static str {
+        "tr"
+    }
+
+    #[inline]
+    fn normalize(&self, query: &str) -> String {
+        separate_digits(unicode_punctuation(turkish_lowercase(
+            &u8_find_and_replace(query),
+        )))
+        .trim()
+        .to_string()
+    }
+}
+
+pub struct CjkAnalyzer;
+
+impl Analyzer for CjkAnalyzer {
+    fn name(&self) -> &'static str {
+        "cjk"
+    }
+
+    #[inline]
+    fn normalize(&self, query: &str) -> String {
+        separate_digits(segment_cjk(unicode_punctuation(
+            u8_find_and_replace(query).to_lowercase(),
+        )))
+        .trim()
+        .to_string()
+    }
+}
+
+// Wraps a language analyzer with Unicode normalization
+pub struct UnicodeAnalyzer {
+    analyzer: Box<dyn Analyzer>,
+}
+
+impl UnicodeAnalyzer {
+    pub fn new(analyzer: Box<dyn Analyzer>) -> Self {
+        UnicodeAnalyzer { analyzer: analyzer }
+    }
+}
+
+impl Analyzer for UnicodeAnalyzer {
+    fn name(&self) -> &'static str {
+        self.analyzer.name()
+    }
+
+    #[inline]
+    fn normalize(&self, query: &str) -> String {
+        let query = self.analyzer.normalize(&unicode_find_and_replace(query));
+        strip_diacritics(case_fold(query)).to_string()
+    }
+
+    #[inline]
+    fn stem(&self, word: &str) -> String {
+        self.analyzer.stem(word)
+    }
+}
+
+// Wraps a language analyzer with a stemmer
+pub struct StemmingAnalyzer {
+    analyzer: Box<dyn Analyzer>,
+    stemmer: Box<dyn Stemmer>,
+}
+
+impl StemmingAnalyzer {
+    pub fn new(analyzer: Box<dyn Analyzer>, stemmer: Box<dyn Stemmer>) -> Self {
+        StemmingAnalyzer {
+            analyzer: analyzer,
+            stemmer: stemmer,
+        }
+    }
+}
+
+impl Analyzer for StemmingAnalyzer {
+    fn name(&self) -> &'static str {
+        self.analyzer.name()
+    }
+
+    #[inline]
+    fn normalize(&self, query: &str) -> String {
+        self.analyzer.normalize(query)
+    }
+
+    #[inline]
+    fn stem(&self, word: &str) -> String {
+        self.stemmer.stem(word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        for name in ANALYZERS.iter() {
+            assert!(get(name, 1).is_some(), "analyzer: {}", name);
+            assert!(get(name, UNICODE_NORMALIZATION_VERSION).is_some());
+        }
+        assert_eq!(get(DEFAULT_ANALYZER, 1).unwrap().name(), "default");
+        assert!(get("klingon", 1).is_none());
+
+        // old indexes keep their normalization
+        let q = "Ｃａｆé—“crème”";
+        assert_eq!(
+            get(DEFAULT_ANALYZER, 1).unwrap().normalize(q),
+            "ｃａｆé—“crème”"
+        );
+        assert_eq!(
+            get(DEFAULT_ANALYZER, UNICODE_NORMALIZATION_VERSION)
+                .unwrap()
+                .normalize(q),
+            "cafe  creme"
+        );
+    }
+
+    #[test]
+    fn test_default_analyzer() {
+        let q = "München Gödel Gießen Bären laptop-ersatzteile24";
+        assert_eq!(
+            DefaultAnalyzer.normalize(q),
+            "muenchen goedel giessen baeren laptop ersatzteile 24"
+        );
+    }
+
+    #[test]
+    fn test_french_analyzer() {
+        let a = FrenchAnalyzer;
+        assert_eq!(a.normalize("L'Été à Noël"), "ete a noel");
+        assert_eq!(
+            a.normalize("qu’il fait beau, n'est-ce pas?"),
+            "il fait beau  est ce pas"
+        );
+        assert_eq!(
+            a.normalize("Œuvres complètes « Molière »"),
+            "oeuvres completes   moliere"
+        );
+        assert_eq!(a.normalize("aujourd'hui"), "aujourdhui");
+    }
+
+    #[test]
+    fn test_turkish_analyzer() {
+        let a = TurkishAnalyzer;
+        assert_eq!(a.normalize("İSTANBUL"), "istanbul");
+        assert_eq!(a.normalize("ISPARTA Işık"), "ısparta ışık");
+        assert_eq!(DefaultAnalyzer.normalize("ISPARTA"), "isparta");
+    }
+
+    #[test]
+    fn test_cjk_analyzer() {
+        let a = CjkAnalyzer;
+        let words = |q: &str| {
+            a.normalize(q)
+                .split(" ")
+                .filter(|w| !w.is_empty())
+                .map(|w| w.to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(words("iPhone充电器"), vec!["iphone", "充", "电", "器"]);
+        assert_eq!(
+            words("東京タワーの高さ"),
+            vec!["東", "京", "タワーの", "高", "さ"]
+        );
+        assert_eq!(words("서울 맛집。"), vec!["서울", "맛집"]);
+    }
+
+    #[test]
+    fn test_unicode_analyzer() {
+        let a = UnicodeAnalyzer::new(Box::new(DefaultAnalyzer));
+        assert_eq!(
+            a.normalize("Ｍüｎｃｈｅｎ\u{00A0}Straße"),
+            "muenchen strasse"
+        );
+        assert_eq!(a.normalize("don’t stop—believin’"), "dont stop believin");
+        assert_eq!(a.normalize("Crème Brûlée ﬁlm"), "creme brulee film");
+        assert_eq!(
+            a.normalize("soft\u{00AD}ware zero\u{200B}width"),
+            "software zerowidth"
+        );
+        assert_eq!(a.normalize("ΣΟΦΟΣ Ёлка"), "σοφοσ елка");
+        assert_eq!(a.normalize("“quoted” «text»"), "quoted   text");
+
+        let a = UnicodeAnalyzer::new(Box::new(FrenchAnalyzer));
+        assert_eq!(a.normalize("Qu’il était l’été"), "il etait  ete");
+
+        let a = UnicodeAnalyzer::new(Box::new(TurkishAnalyzer));
+        assert_eq!(a.normalize("IŞIK İzmir"), "ısık izmir");
+
+        let a = UnicodeAnalyzer::new(Box::new(CjkAnalyzer));
+        assert_eq!(a.normalize("ｶﾞｲﾄﾞ"), "ガイド");
+    }
+}
@@ -0,0 +1,469 @@
+/*
+    Approximate nearest neighbour search over the query vectors of an i2q shard, an HNSW graph
+    (hierarchical navigable small world, Malkov & Yashunin). A query vector is the combined,
+    normalized vector of its words, the distance is the cosine distance.
+
+    The graph file, little endian:
+        the magic bytes followed by the vectors type, dim, number of nodes, M, max level and
+        the entry point node as u32
+        the shard query id of each node, u32
+        the vector of each node of the vectors type (see word_vec)
+        2 * M links, node ids as u32, of each node on the layer 0, padded with NO_LINK
+        M links of each node for each of its levels above 0, in the order of nodes
+        the level of each node, u8
+*/
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use fnv::FnvHashSet;
+use memmap::Mmap;
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+use util;
+use word_vec::{self, VecType};
+
+pub const M: usize = 16; // max number of links of a node per layer, 2 * M on the layer 0
+pub const EF_CONSTRUCTION: usize = 100; // size of the dynamic candidate list while building
+pub const EF_SEARCH: usize = 64; // size of the dynamic candidate list while searching
+pub const MAX_LEVEL: usize = 16;
+
+const SEED: u64 = 7; // levels are random, but the graph of the same queries is always the same
+
+pub const MAGIC: &[u8; 3] = b"QAN";
+const HEADER_SIZE: usize = 24;
+const NO_LINK: u32 = ::std::u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate(f32, usize);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    #[inline]
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    #[inline]
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .unwrap_or(Ordering::Equal)
+            .then(self.1.cmp(&other.1))
+    }
+}
+
+// Best first search of the ef nearest nodes on a layer, starting from the entry points.
+// Returns (distance, node) sorted by the distance.
+fn search_layer(
+    entry_points: &[Candidate],
+    ef: usize,
+    dist: &dyn Fn(usize) -> f32,
+    links: &dyn Fn(usize, &mut Vec<usize>),
+) -> Vec<Candidate> {
+    let mut visited: FnvHashSet<usize> = entry_points.iter().map(|c| c.1).collect();
+    let mut candidates: BinaryHeap<Reverse<Candidate>> =
+        entry_points.iter().map(|c| Reverse(*c)).collect();
+    let mut nearest: BinaryHeap<Candidate> = entry_points.iter().cloned().collect();
+
+    let mut neighbours: Vec<usize> = vec![];
+    while let Some(Reverse(c)) = candidates.pop() {
+        if nearest.len() >= ef && c.0 > nearest.peek().unwrap().0 {
+            break;
+        }
+
+        neighbours.clear();
+        links(c.1, &mut neighbours);
+        for n in neighbours.iter() {
+            if !visited.insert(*n) {
+                continue;
+            }
+
+            let d = dist(*n);
+            if nearest.len() < ef || d < nearest.peek().unwrap().0 {
+                candidates.push(Reverse(Candidate(d, *n)));
+                nearest.push(Candidate(d, *n));
+                if nearest.len() > ef {
+                    nearest.pop();
+                }
+            }
+        }
+    }
+
+    nearest.into_sorted_vec()
+}
+
+#[inline]
+fn max_links(level: usize, m: usize) -> usize {
+    if level == 0 {
+        2 * m
+    } else {
+        m
+    }
+}
+
+pub struct Builder {
+    m: usize,
+    ef_construction: usize,
+    level_mult: f64,
+    rng: XorShiftRng,
+    ids: Vec<u32>,
+    vecs: Vec<Vec<f32>>,
+    links: Vec<Vec<Vec<usize>>>, // node -> level -> linked nodes
+    entry_point: Option<usize>,
+    max_level: usize,
+}
+
+impl Builder {
+    pub fn new(m: usize, ef_construction: usize) -> Self {
+        let m = util::max(m, 2);
+        Builder {
+            m: m,
+            ef_construction: util::max(ef_construction, m),
+            level_mult: 1.0 / (m as f64).ln(),
+            rng: XorShiftRng::seed_from_u64(SEED),
+            ids: vec![],
+            vecs: vec![],
+            links: vec![],
+            entry_point: None,
+            max_level: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    fn dist(&self, vec: &[f32], node: usize) -> f32 {
+        1.0 - word_vec::dot(vec, &self.vecs[node])
+    }
+
+    fn search_layer(
+        &self,
+        vec: &[f32],
+        entry_points: &[Candidate],
+        ef: usize,
+        level: usize,
+    ) -> Vec<Candidate> {
+        search_layer(
+            entry_points,
+            ef,
+            &|node| self.dist(vec, node),
+            &|node, neighbours| {
+                if let Some(links) = self.links[node].get(level) {
+                    neighbours.extend(links.iter());
+                }
+            },
+        )
+    }
+
+    // adds a normalized query vector
+    pub fn add(&mut self, shard_query_id: u32, vec: Vec<f32>) {
+        let node = self.ids.len();
+        let r: f64 = self.rng.gen_range(::std::f64::EPSILON, 1.0);
+        let level = util::min((-r.ln() * self.level_mult) as usize, MAX_LEVEL);
+
+        self.ids.push(shard_query_id);
+        self.vecs.push(vec);
+        self.links.push(vec![vec![]; level + 1]);
+
+        let entry_point = match self.entry_point {
+            Some(entry_point) => entry_point,
+            None => {
+                self.entry_point = Some(node);
+                self.max_level = level;
+                return;
+            }
+        };
+
+        let vec = self.vecs[node].clone();
+        let mut entry_points = vec![Candidate(self.dist(&vec, entry_point), entry_point)];
+        for l in (level + 1..self.max_level + 1).rev() {
+            entry_points = self.search_layer(&vec, &entry_points, 1, l);
+        }
+
+        for l in (0..util::min(level, self.max_level) + 1).rev() {
+            let nearest = self.search_layer(&vec, &entry_points, self.ef_construction, l);
+            let neighbours: Vec<usize> = nearest.iter().take(self.m).map(|c| c.1).collect();
+
+            for n in neighbours.iter() {
+                self.links[*n][l].push(node);
+                if self.links[*n][l].len() > max_links(l, self.m) {
+                    // keep the nearest links of the neighbour
+                    let n_vec = &self.vecs[*n];
+                    let mut n_links: Vec<Candidate> = self.links[*n][l]
+                        .iter()
+                        .map(|c| Candidate(self.dist(n_vec, *c), *c))
+                        .collect();
+                    n_links.sort();
+                    n_links.truncate(max_links(l, self.m));
+                    self.links[*n][l] = n_links.into_iter().map(|c| c.1).collect();
+                }
+            }
+
+            self.links[node][l] = neighbours;
+            entry_points = nearest;
+        }
+
+        if level > self.max_level {
+            self.entry_point = Some(node);
+            self.max_level = level;
+        }
+    }
+
+    pub fn write(&self, path: &Path, vec_type: VecType) -> Result<(), Error> {
+        let dim = self.vecs.first().map(|v| v.len()).unwrap_or(0);
+        let mut w = BufWriter::new(File::create(path)?);
+
+        w.write_all(MAGIC)?;
+        w.write_u8(vec_type as u8)?;
+        for v in [
+            dim,
+            self.len(),
+            self.m,
+            self.max_level,
+            self.entry_point.unwrap_or(0),
+        ]
+        .iter()
+        {
+            w.write_u32::<LittleEndian>(*v as u32)?;
+        }
+
+        for id in self.ids.iter() {
+            w.write_u32::<LittleEndian>(*id)?;
+        }
+        for vec in self.vecs.iter() {
+            word_vec::write_vec(&mut w, vec, vec_type)?;
+        }
+
+        for node_links in self.links.iter() {
+            for l in 0..node_links.len() {
+                let nr_links = max_links(l, self.m);
+                for i in 0..nr_links {
+                    let link = node_links[l].get(i).map(|n| *n as u32);
+                    w.write_u32::<LittleEndian>(link.unwrap_or(NO_LINK))?;
+                }
+            }
+        }
+
+        for node_links in self.links.iter() {
+            w.write_u8((node_links.len() - 1) as u8)?;
+        }
+        w.flush()
+    }
+}
+
+pub struct Ann {
+    data: Mmap,
+    dim: usize,
+    vec_type: VecType,
+    nr_nodes: usize,
+    m: usize,
+    max_level: usize,
+    entry_point: usize,
+    vecs_addr: usize,
+    links_addr: Vec<usize>, // address of the links of each node, the layer 0 first
+}
+
+#[inline]
+fn invalid_data(path: &Path, msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{:?}: {}", path, msg))
+}
+
+impl Ann {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let data = unsafe { Mmap::map(&file)? };
+        if data.len() < HEADER_SIZE || &data[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data(path, "not an ANN graph file"));
+        }
+
+        let vec_type = match VecType::from_byte(data[MAGIC.len()]) {
+            Some(vec_type) => vec_type,
+            None => return Err(invalid_data(path, "unknown vectors type")),
+        };
+        let header: Vec<usize> = data[4..HEADER_SIZE]
+            .chunks(4)
+            .map(|b| LittleEndian::read_u32(b) as usize)
+            .collect();
+        let (dim, nr_nodes, m, max_level, entry_point) =
+            (header[0], header[1], header[2], header[3], header[4]);
+
+        let vecs_addr = HEADER_SIZE + 4 * nr_nodes;
+        let mut addr = vecs_addr + nr_nodes * vec_type.vec_size(dim);
+        let levels_addr = data.len().saturating_sub(nr_nodes);
+        if levels_addr < addr {
+            return Err(invalid_data(path, "truncated ANN graph file"));
+        }
+
+        let mut links_addr = Vec::with_capacity(nr_nodes);
+        for level in data[levels_addr..].iter() {
+            links_addr.push(addr);
+            addr += 4 * (2 * m + *level as usize * m);
+        }
+        if addr != levels_addr {
+            return Err(invalid_data(path, "invalid ANN graph links"));
+        }
+
+        Ok(Ann {
+            data: data,
+            dim: dim,
+            vec_type: vec_type,
+            nr_nodes: nr_nodes,
+            m: m,
+            max_level: max_level,
+            entry_point: entry_point,
+            vecs_addr: vecs_addr,
+            links_addr: links_addr,
+        })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nr_nodes
+    }
+
+    #[inline]
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    #[inline]
+    fn shard_query_id(&self, node: usize) -> u32 {
+        LittleEndian::read_u32(&self.data[HEADER_SIZE + 4 * node..])
+    }
+
+    #[inline]
+    fn dist(&self, vec: &[f32], node: usize) -> f32 {
+        let vecs = &self.data[self.vecs_addr..];
+        1.0 - word_vec::word_vec_at(vecs, self.vec_type, self.dim, node).dot(vec)
+    }
+
+    #[inline]
+    fn links(&self, node: usize, level: usize, neighbours: &mut Vec<usize>) {
+        let node_level = self.data[self.data.len() - self.nr_nodes + node] as usize;
+        if level > node_level {
+            return;
+        }
+
+        let (addr, nr_links) = if level == 0 {
+            (self.links_addr[node], 2 * self.m)
+        } else {
+            (
+                self.links_addr[node] + 4 * (2 * self.m + (level - 1) * self.m),
+                self.m,
+            )
+        };
+
+        for i in 0..nr_links {
+            let link = LittleEndian::read_u32(&self.data[addr + 4 * i..]);
+            if link == NO_LINK {
+                break;
+            }
+            neighbours.push(link as usize);
+        }
+    }
+
+    // Returns up to k (shard query id, cosine distance) nearest to the normalized query vector,
+    // ef is the size of the candidate list, at least k.
+    pub fn search(&self, vec: &[f32], k: usize, ef: usize) -> Vec<(u32, f32)> {
+        if self.nr_nodes == 0 || vec.len() != self.dim {
+            return vec![];
+        }
+
+        let dist = |node: usize| self.dist(vec, node);
+        let mut entry_points = vec![Candidate(dist(self.entry_point), self.entry_point)];
+        for l in (1..self.max_level + 1).rev() {
+            entry_points = search_layer(&entry_points, 1, &dist, &|node, neighbours| {
+                self.links(node, l, neighbours)
+            });
+        }
+
+        search_layer(
+            &entry_points,
+            util::max(ef, k),
+            &dist,
+            &|node, neighbours| self.links(node, 0, neighbours),
+        )
+        .into_iter()
+        .take(k)
+        .map(|c| (self.shard_query_id(c.1), c.0))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    fn random_vecs(num: usize, dim: usize) -> Vec<Vec<f32>> {
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        (0..num)
+            .map(|_| {
+                let mut v: Vec<f32> = (0..dim).map(|_| rng.gen::<f32>() - 0.5).collect();
+                word_vec::normalize(&mut v);
+                v
+            })
+            .collect()
+    }
+
+    fn exact_nearest(vecs: &Vec<Vec<f32>>, vec: &[f32], k: usize) -> Vec<u32> {
+        let mut dists: Vec<Candidate> = vecs
+            .iter()
+            .enumerate()
+            .map(|(i, v)| Candidate(1.0 - word_vec::dot(vec, v), i))
+            .collect();
+        dists.sort();
+        dists.iter().take(k).map(|c| 10 * c.1 as u32).collect()
+    }
+
+    #[test]
+    fn test_build_search() {
+        let (num, dim, k) = (1000, 16, 10);
+        let vecs = random_vecs(num, dim);
+
+        let mut builder = Builder::new(8, 64);
+        for (i, v) in vecs.iter().enumerate() {
+            builder.add(10 * i as u32, v.clone());
+        }
+        assert_eq!(builder.len(), num);
+
+        for &vec_type in [VecType::F32, VecType::F16, VecType::Int8].iter() {
+            let path = temp_dir().join(format!("test_ann_{:?}", vec_type));
+            builder.write(&path, vec_type).unwrap();
+            let ann = Ann::load(&path).unwrap();
+            assert_eq!(ann.len(), num);
+            assert_eq!(ann.dim(), dim);
+
+            // a node finds itself, the recall of the nearest neighbours is high
+            let mut found = 0;
+            for (i, v) in vecs.iter().enumerate().take(100) {
+                let results = ann.search(v, k, EF_SEARCH);
+                assert_eq!(results.len(), k);
+                assert_eq!(results[0].0, 10 * i as u32);
+                assert!(results[0].1.abs() < 1e-2);
+
+                let exact = exact_nearest(&vecs, v, k);
+                found += results.iter().filter(|r| exact.contains(&r.0)).count();
+            }
+            assert!(
+                found as f32 / (100 * k) as f32 > 0.9,
+                "{:?}: {}",
+                vec_type,
+                found
+            );
+        }
+
+        assert!(Ann::load(&temp_dir().join("test_ann_missing")).is_err());
+    }
+}
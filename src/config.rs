@@ -4,23 +4,122 @@ use std::fs::File;
 use std::io::BufReader;
 use std::io::Error;
 use std::io::Read;
+use std::io::Write;
+
+use analyzer;
+use ann;
+use ngrams;
+use scorer;
+use subwords;
+use toponyms;
+use util::{BRED, BYELL, ECOL};
+
+// config values the ngrams of the shards depend on, recorded while sharding
+static BUILD_FILE: &'static str = "build.json";
 
 pub struct Config {
+    pub version: u64,       // index format version, 1 if not set
     pub id_size: usize,     // query id size in bytes (4 for id + 1 for relevance)
     pub bucket_size: usize, // max number of query ids in a ngram bucket
     pub nr_shards: usize,
     pub shard_size: usize, // number of ids in the shard
     pub terms_relevance_file: String,
     pub stopwords_file: String,
+    pub stopwords_files: Vec<(String, String)>, // named stopwords lists, per language or per index
     pub toponyms_file: String,
+    pub toponym_synonyms_file: String, // alternative names of toponyms, in the synonyms format
+    pub toponyms_policy: String,       // toponyms that are must-have words: top, all or none
     pub synonyms_file: String,
     pub i2q_file: String,
+    pub i2q_block_size: usize, // strings per compressed i2q block, uncompressed i2q if 0
+    pub q2i_file: String, // prefix of the per shard maps of normalized queries to ids, empty disables
     pub words_file: String,
     pub word_vecs_file: String,
+    pub word_vecs_dim: usize, // word vectors dimension for files without a header, 0 if not set
+    pub subword_vecs_file: String, // character n-gram vectors for words out of the vocabulary
+    pub subword_min_n: usize,
+    pub subword_max_n: usize,
     pub use_word_vectors: bool,
+    pub use_union_map: bool, // search the union map of all shards (qpick merge) instead of map.N
+    pub ann_file: String,    // prefix of the per shard ANN graphs of the query vectors
+    pub ann_count: usize, // nearest queries per shard added to the keyword candidates, 0 disables
+    pub ann_ef: usize,    // size of the ANN search candidate list
+    pub analyzer: String, // language analyzer used for sharding and search
+    pub scorer: String,   // scorer re-ranking the candidates
+    pub model_file: String, // learned model of the model scorer
+    pub stemmer: String,  // stemmer used for sharding and search, no stemming if empty
+    pub words_per_query: usize, // max number of the most relevant query words used for ngrams
+}
+
+#[inline]
+fn warn_build_mismatch(name: &str, built: u64, configured: u64) {
+    println!(
+        "{}",
+        [
+            BYELL,
+            "The shards were built with ",
+            name,
+            ": ",
+            &built.to_string(),
+            ", config.json has ",
+            &configured.to_string(),
+            ", searching with ",
+            ECOL,
+            BRED,
+            &built.to_string(),
+            ECOL,
+        ]
+        .join("")
+    );
 }
 
 impl Config {
+    // Records the config values the ngrams of the shards are built with
+    pub fn write_build_info(&self, path: &str) -> Result<(), Error> {
+        let mut f = File::create(format!("{}/{}", path, BUILD_FILE))?;
+        writeln!(
+            f,
+            "{{\"version\": {}, \"words_per_query\": {}}}",
+            self.version, self.words_per_query
+        )
+    }
+
+    // The shards are searched with the values they were built with, different values in the
+    // config would change the ngrams of queries and silently lose matches. Warns about them.
+    // Shards built without the build file are not checked.
+    pub fn apply_build_info(&mut self, path: &str) {
+        let f = match File::open(format!("{}/{}", path, BUILD_FILE)) {
+            Ok(f) => f,
+            Err(_) => return,
+        };
+        let mut content = String::new();
+        BufReader::new(&f).read_to_string(&mut content).unwrap();
+        let info: Value = match serde_json::from_str(&content) {
+            Ok(info) => info,
+            Err(err) => panic!("Failed to parse {}/{}, err: {:?}", path, BUILD_FILE, err),
+        };
+
+        if let Value::Number(ref version) = info["version"] {
+            let version = version.as_u64().unwrap();
+            if version != self.version {
+                warn_build_mismatch("version", version, self.version);
+                self.version = version;
+            }
+        }
+
+        if let Value::Number(ref words_per_query) = info["words_per_query"] {
+            let words_per_query = words_per_query.as_u64().unwrap();
+            if words_per_query != self.words_per_query as u64 {
+                warn_build_mismatch(
+                    "words_per_query",
+                    words_per_query,
+                    self.words_per_query as u64,
+                );
+                self.words_per_query = words_per_query as usize;
+            }
+        }
+    }
+
     fn load_config_file(path: &str) -> Result<String, Error> {
         let f = File::open(format!("{}/config.json", path))?;
         let mut buf = BufReader::new(&f);
@@ -38,6 +137,11 @@ impl Config {
 
         let config: Value = serde_json::from_str(&config_content).unwrap();
 
+        let version = match config["version"] {
+            Value::Number(ref version) => version.as_u64().unwrap(),
+            _ => 1,
+        };
+
         let nr_shards = match config["nr_shards"] {
             Value::Number(ref nr_shards) => nr_shards.as_u64().unwrap(),
             _ => 64,
@@ -71,11 +175,35 @@ impl Config {
             }
         };
 
+        let toponym_synonyms_file = match config["toponym_synonyms_file"] {
+            Value::String(ref toponym_synonyms_file) => toponym_synonyms_file.as_str(),
+            _ => "",
+        };
+
+        let toponyms_policy = match config["toponyms_policy"] {
+            Value::String(ref toponyms_policy) => toponyms_policy.as_str(),
+            _ => toponyms::DEFAULT_POLICY,
+        };
+
         let stopwords_file = match config["stopwords_file"] {
             Value::String(ref stopwords_file) => stopwords_file.as_str(),
             _ => panic!("Failed to parse stopwords file name from the config!"),
         };
 
+        let stopwords_files = match config["stopwords_files"] {
+            Value::Object(ref stopwords_files) => stopwords_files
+                .iter()
+                .map(|(name, file)| match file {
+                    Value::String(ref file) => (name.to_string(), file.to_string()),
+                    _ => panic!(
+                        "Failed to parse stopwords file name for {} from the config!",
+                        name
+                    ),
+                })
+                .collect(),
+            _ => vec![],
+        };
+
         let synonyms_file = match config["synonyms_file"] {
             Value::String(ref synonyms_file) => synonyms_file.as_str(),
             _ => {
@@ -92,6 +220,16 @@ impl Config {
             }
         };
 
+        let i2q_block_size = match config["i2q_block_size"] {
+            Value::Number(ref i2q_block_size) => i2q_block_size.as_u64().unwrap() as usize,
+            _ => 0,
+        };
+
+        let q2i_file = match config["q2i_file"] {
+            Value::String(ref q2i_file) => q2i_file.as_str(),
+            _ => "q2i",
+        };
+
         let words_file = match config["words_file"] {
             Value::String(ref words_file) => words_file.as_str(),
             _ => "",
@@ -102,12 +240,78 @@ impl Config {
             _ => "",
         };
 
+        let word_vecs_dim = match config["word_vecs_dim"] {
+            Value::Number(ref word_vecs_dim) => word_vecs_dim.as_u64().unwrap() as usize,
+            _ => 0,
+        };
+
+        let subword_vecs_file = match config["subword_vecs_file"] {
+            Value::String(ref subword_vecs_file) => subword_vecs_file.as_str(),
+            _ => "",
+        };
+
+        let subword_min_n = match config["subword_min_n"] {
+            Value::Number(ref subword_min_n) => subword_min_n.as_u64().unwrap() as usize,
+            _ => subwords::MIN_N,
+        };
+
+        let subword_max_n = match config["subword_max_n"] {
+            Value::Number(ref subword_max_n) => subword_max_n.as_u64().unwrap() as usize,
+            _ => subwords::MAX_N,
+        };
+
+        let use_union_map = match config["use_union_map"] {
+            Value::Bool(use_union_map) => use_union_map,
+            _ => false,
+        };
+
         let use_word_vectors = match config["use_word_vectors"] {
             Value::Bool(use_words_vectors) => use_words_vectors,
             _ => panic!("Failed to parse use_words_vectors flag from the config!"),
         };
 
+        let ann_file = match config["ann_file"] {
+            Value::String(ref ann_file) => ann_file.as_str(),
+            _ => "ann",
+        };
+
+        let ann_count = match config["ann_count"] {
+            Value::Number(ref ann_count) => ann_count.as_u64().unwrap() as usize,
+            _ => 20,
+        };
+
+        let ann_ef = match config["ann_ef"] {
+            Value::Number(ref ann_ef) => ann_ef.as_u64().unwrap() as usize,
+            _ => ann::EF_SEARCH,
+        };
+
+        let analyzer = match config["analyzer"] {
+            Value::String(ref analyzer) => analyzer.as_str(),
+            _ => analyzer::DEFAULT_ANALYZER,
+        };
+
+        let scorer = match config["scorer"] {
+            Value::String(ref scorer) => scorer.as_str(),
+            _ => scorer::DEFAULT_SCORER,
+        };
+
+        let model_file = match config["model_file"] {
+            Value::String(ref model_file) => model_file.as_str(),
+            _ => "model.json",
+        };
+
+        let stemmer = match config["stemmer"] {
+            Value::String(ref stemmer) => stemmer.as_str(),
+            _ => "",
+        };
+
+        let words_per_query = match config["words_per_query"] {
+            Value::Number(ref words_per_query) => words_per_query.as_u64().unwrap() as usize,
+            _ => ngrams::WORDS_PER_QUERY,
+        };
+
         Config {
+            version: version,
             id_size: id_size as usize,
             bucket_size: bucket_size as usize,
             nr_shards: nr_shards as usize,
@@ -115,11 +319,59 @@ impl Config {
             terms_relevance_file: terms_relevance_file.to_string(),
             synonyms_file: synonyms_file.to_string(),
             toponyms_file: toponyms_file.to_string(),
+            toponym_synonyms_file: toponym_synonyms_file.to_string(),
+            toponyms_policy: toponyms_policy.to_string(),
             stopwords_file: stopwords_file.to_string(),
+            stopwords_files: stopwords_files,
             i2q_file: i2q_file.to_string(),
+            i2q_block_size: i2q_block_size,
+            q2i_file: q2i_file.to_string(),
             words_file: words_file.to_string(),
             word_vecs_file: word_vecs_file.to_string(),
+            word_vecs_dim: word_vecs_dim,
+            subword_vecs_file: subword_vecs_file.to_string(),
+            subword_min_n: subword_min_n,
+            subword_max_n: subword_max_n,
             use_word_vectors: use_word_vectors,
+            use_union_map: use_union_map,
+            ann_file: ann_file.to_string(),
+            ann_count: ann_count,
+            ann_ef: ann_ef,
+            analyzer: analyzer.to_string(),
+            scorer: scorer.to_string(),
+            model_file: model_file.to_string(),
+            stemmer: stemmer.to_string(),
+            words_per_query: words_per_query,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::create_dir_all;
+
+    #[test]
+    fn test_build_info() {
+        let dir = temp_dir().join("test_build_info");
+        create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap().to_string();
+        let mut f = File::create(dir.join("config.json")).unwrap();
+        f.write_all(
+            b"{\"terms_relevance_file\": \"tr.fst\", \"stopwords_file\": \"stopwords.txt\", \
+              \"use_word_vectors\": false, \"words_per_query\": 10}",
+        )
+        .unwrap();
+
+        let c = Config::init(path.clone());
+        c.write_build_info(&path).unwrap();
+
+        let mut c = Config::init(path.clone());
+        c.words_per_query = 5;
+        c.version = 2;
+        c.apply_build_info(&path);
+        assert_eq!(c.words_per_query, 10);
+        assert_eq!(c.version, 1);
+    }
+}
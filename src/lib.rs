@@ -1,9 +1,12 @@
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "blas")]
 extern crate blas;
 extern crate byteorder;
 extern crate fst;
 extern crate libc;
+extern crate lz4_flex;
+#[cfg(feature = "blas")]
 extern crate openblas_src;
 #[macro_use]
 extern crate serde_derive;
@@ -17,13 +20,17 @@ extern crate rand;
 extern crate rayon;
 extern crate regex;
 extern crate serde_json;
+extern crate unicode_normalization;
 
 use fnv::{FnvHashMap, FnvHashSet};
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::cmp::{Ordering, PartialOrd};
 use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::ops::Range;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::sync::Arc;
 
 use byteorder::{ByteOrder, LittleEndian};
@@ -35,19 +42,37 @@ use fst::Error;
 
 #[macro_use]
 pub mod util;
+pub mod analyzer;
+pub mod ann;
 pub mod builder;
 pub mod config;
 pub mod merge;
+pub mod model;
 pub mod ngrams;
+pub mod operators;
+pub mod page;
+pub mod q2i;
+pub mod scorer;
 pub mod shard;
+pub mod stemmer;
 pub mod stopwords;
 pub mod stringvec;
+pub mod subwords;
 pub mod synonyms;
 pub mod toponyms;
+pub mod vecmath;
+pub mod vectors;
 pub mod word_vec;
 
+use analyzer::Analyzer;
+use operators::Operators;
+use page::PageCursor;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
+use scorer::Scorer;
+use stopwords::Stopwords;
+use synonyms::{Synonym, Synonyms};
+use toponyms::Toponyms;
 use util::{BRED, BYELL, ECOL};
 use word_vec::WordVecs;
 
@@ -117,6 +142,25 @@ fn get_addr_and_len(ngram: &str, map: &fst::Map) -> Option<(u64, u64)> {
     }
 }
 
+// ngrams of a shard, from the map of the shard or from the union map of all shards
+enum NgramMap<'m> {
+    Shard(&'m fst::Map),
+    Union(&'m fst::Map, usize),
+}
+
+impl<'m> NgramMap<'m> {
+    #[inline]
+    fn get_addr_and_len(&self, ngram: &str) -> Option<(u64, u64)> {
+        match *self {
+            NgramMap::Shard(map) => get_addr_and_len(ngram, map),
+            // an ngram is in one shard only, a single lookup of its key
+            NgramMap::Union(map, shard_id) => {
+                get_addr_and_len(&merge::union_key(ngram, shard_id), map)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Distance {
     pub query_id: u64,
@@ -214,6 +258,28 @@ pub struct SearchResult {
 }
 impl_partial_ord!(SearchResult, query_id, dist);
 
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    pub cursor: Option<String>, // continuation cursor for the next page, None on the last one
+}
+
+// Options of a search request, the ones not set fall back to those of the instance.
+// All options can be combined with each other and with pagination.
+#[derive(Clone, Copy, Default)]
+pub struct SearchOptions<'a> {
+    pub prefix: bool,                   // the last query word is a prefix (typeahead)
+    pub max_edits: Option<u32>,         // correct unknown words within max edits (1 or 2)
+    pub stopwords: Option<&'a str>,     // named stopwords list from the config
+    pub scorer: Option<&'a dyn Scorer>, // re-ranks the candidates
+}
+
+#[derive(Debug, Serialize)]
+struct SearchPageString {
+    results: Vec<(u64, Distance, String)>,
+    cursor: Option<String>,
+}
+
 struct ShardResults {
     results: Vec<SearchShardResult>,
 }
@@ -253,7 +319,7 @@ fn _get_idfs(ngrams: &Vec<(String, usize)>, map: &fst::Map) -> FnvHashMap<String
 fn get_shard_results(
     ngrams: &Vec<(String, usize)>,
     trs: &Vec<f32>,
-    map: &fst::Map,
+    map: &NgramMap,
     ifd: &memmap::Mmap,
     id_size: usize,
     shard_num: usize,
@@ -261,7 +327,7 @@ fn get_shard_results(
 ) -> Result<ShardResults, Error> {
     let mut sres: Vec<SearchShardResult> = vec![];
     for (ngram, ngram_idx) in ngrams {
-        if let Some((addr, len)) = get_addr_and_len(ngram, &map) {
+        if let Some((addr, len)) = map.get_addr_and_len(ngram) {
             // returns physical memory address and length of the vector (not a number of bytes)
             let mem_addr = addr as usize * id_size;
 
@@ -289,7 +355,7 @@ fn get_shard_results(
 #[inline]
 fn index_words(
     words: &Vec<String>,
-    synonyms: &FnvHashMap<usize, String>,
+    synonyms: &FnvHashMap<usize, Vec<Synonym>>,
 ) -> (FnvHashMap<String, usize>, FnvHashSet<String>) {
     let mut words_set: FnvHashSet<String> = FnvHashSet::default();
     let mut words_index = words
@@ -302,33 +368,59 @@ fn index_words(
         })
         .collect::<FnvHashMap<String, usize>>();
 
-    for (word_idx, syn) in synonyms {
+    for (word_idx, syns) in synonyms {
         // add synonyms to the index, but not to the words set
-        words_index.insert(syn.to_string(), *word_idx);
+        for syn in syns.iter().filter(|s| !s.is_phrase()) {
+            words_index.insert(syn.phrase.to_string(), *word_idx);
+        }
     }
 
     (words_index, words_set)
 }
 
+// synonym (word or phrase) -> the query words it stands for, space separated
+#[inline]
+fn synonym_words(
+    words: &Vec<String>,
+    synonyms: &FnvHashMap<usize, Vec<Synonym>>,
+) -> FnvHashMap<String, String> {
+    synonyms
+        .iter()
+        .flat_map(|(wid, syns)| {
+            syns.iter().map(move |syn| {
+                let span_end = util::min(*wid + syn.span, words.len());
+                (syn.phrase.to_string(), words[*wid..span_end].join(" "))
+            })
+        })
+        .collect()
+}
+
 pub struct Qpick<'a> {
     path: String,
     config: config::Config,
-    synonyms: Option<FnvHashMap<String, String>>,
-    toponyms: Option<fst::Set>,
-    stopwords: FnvHashSet<String>,
+    analyzer: Box<dyn Analyzer>,
+    synonyms: Option<Synonyms>,
+    toponyms: Option<Toponyms>,
+    stopwords: Arc<Stopwords>,
+    stopwords_lists: FnvHashMap<String, Arc<Stopwords>>,
     terms_relevance: fst::Map,
     shards: Arc<Vec<Shard>>,
+    union_map: Option<fst::Map>, // replaces the maps of the shards if loaded
     shard_range: Range<u32>,
     id_size: usize,
     i2q_loaded: bool,
+    operators_warned: AtomicBool, // ignored operators are reported once
     shard_num: usize,
     word_vecs: Option<WordVecs<'a>>,
+    scorer: Box<dyn Scorer>,
 }
 
 pub struct Shard {
-    map: fst::Map,
+    map: Option<fst::Map>, // None if the union map is loaded
     shard: Mmap,
     i2q: Option<stringvec::StrVec>,
+    q2i: Option<fst::Map>, // normalized query text to shard query id
+    ann: Option<ann::Ann>, // ANN graph of the i2q query vectors
 }
 
 #[derive(Debug)]
@@ -351,23 +443,47 @@ impl DistResults {
 #[derive(Debug)]
 pub struct SearchResults {
     pub items_iter: std::vec::IntoIter<SearchResult>,
+    pub cursor: Option<String>,
+    pub corrections: Vec<(String, String)>, // (misspelled word, correction) for fuzzy search
 }
 
 impl SearchResults {
     pub fn new(items_iter: std::vec::IntoIter<SearchResult>) -> SearchResults {
         SearchResults {
             items_iter: items_iter,
+            cursor: None,
+            corrections: vec![],
+        }
+    }
+
+    pub fn from_page(page: SearchPage) -> SearchResults {
+        SearchResults {
+            items_iter: page.results.into_iter(),
+            cursor: page.cursor,
+            corrections: vec![],
         }
     }
 
+    pub fn corrections_as_string(&self) -> String {
+        serde_json::to_string(&self.corrections).unwrap()
+    }
+
     pub fn next(&mut self) -> Option<SearchResult> {
         <std::vec::IntoIter<SearchResult> as std::iter::Iterator>::next(&mut self.items_iter)
     }
 }
 
 impl<'a> Qpick<'a> {
-    fn new(path: String, shard_range_opt: Option<Range<u32>>) -> Qpick<'a> {
-        let c = config::Config::init(path.clone());
+    fn new(
+        path: String,
+        shard_range_opt: Option<Range<u32>>,
+        use_union_map: Option<bool>,
+    ) -> Qpick<'a> {
+        let mut c = config::Config::init(path.clone());
+        c.apply_build_info(&path);
+        if let Some(use_union_map) = use_union_map {
+            c.use_union_map = use_union_map;
+        }
         let id_size = c.id_size;
         unsafe {
             SHARD_SIZE = Some(c.shard_size);
@@ -376,25 +492,20 @@ impl<'a> Qpick<'a> {
         let shard_num = c.nr_shards;
         let shard_range = shard_range_opt.unwrap_or(0..c.nr_shards as u32);
 
-        let stopwords_path = &format!("{}/{}", path, c.stopwords_file);
-        let stopwords = match stopwords::load(stopwords_path) {
-            Ok(stopwords) => stopwords,
-            Err(_) => panic!([
-                BYELL,
-                "No such file or directory: ",
-                ECOL,
-                BRED,
-                stopwords_path,
-                ECOL
-            ]
-            .join("")),
-        };
+        let analyzer = analyzer::from_config(&c);
+        let scorer = scorer::from_config(&path, &c);
+
+        let stopwords_lists: FnvHashMap<String, Arc<Stopwords>> = stopwords::load_lists(&path, &c)
+            .into_iter()
+            .map(|(name, sw)| (name, Arc::new(sw)))
+            .collect();
+        let stopwords =
+            stopwords_lists[&stopwords::select_name(&stopwords_lists, &*analyzer)].clone();
 
         let synonyms_path = PathBuf::from(&path).join(&c.synonyms_file);
-        let synonyms = synonyms::load(&synonyms_path);
+        let synonyms = synonyms::load(&synonyms_path, &*analyzer);
 
-        let toponyms_path = PathBuf::from(&path).join(&c.toponyms_file);
-        let toponyms = toponyms::load(&toponyms_path);
+        let toponyms = toponyms::from_config(&path, &c, &*analyzer);
 
         let terms_relevance_path = &format!("{}/{}", path, c.terms_relevance_file);
         let terms_relevance = match Map::from_path(terms_relevance_path) {
@@ -410,21 +521,53 @@ impl<'a> Qpick<'a> {
             .join("")),
         };
 
+        // one map for all shards, merged from the maps of the shards with qpick merge
+        let union_map = if c.use_union_map {
+            let union_map_path = merge::union_map_path(&path, c.nr_shards);
+            let union_map_file = match MmapReadOnly::open_path(&union_map_path) {
+                Ok(union_map_file) => union_map_file,
+                Err(_) => panic!([
+                    BYELL,
+                    "No such file or directory: ",
+                    ECOL,
+                    BRED,
+                    &union_map_path,
+                    ECOL
+                ]
+                .join("")),
+            };
+            unsafe {
+                util::advise_ram(union_map_file.as_slice())
+                    .expect(&format!("Advisory failed for map {}", &union_map_path))
+            };
+            match Fst::from_mmap(union_map_file) {
+                Ok(fst) => Some(Map::from(fst)),
+                Err(_) => panic!("Failed to load index map: {}!", &union_map_path),
+            }
+        } else {
+            None
+        };
+        let load_shard_maps = union_map.is_none();
+
         let shard_indexes: Vec<u32> = (shard_range.start..shard_range.end).collect();
         let shards: Vec<(bool, Shard)> = shard_indexes
             .par_iter()
             .map(|i| {
-                let map_path = format!("{}/map.{}", path, i);
-
-                // advice OS on random access to the map file and create Fst object from it
-                let map_file = MmapReadOnly::open_path(&map_path).unwrap();
-                unsafe {
-                    util::advise_ram(map_file.as_slice())
-                        .expect(&format!("Advisory failed for map {}", i))
-                };
-                let map = match Fst::from_mmap(map_file) {
-                    Ok(fst) => Map::from(fst),
-                    Err(_) => panic!("Failed to load index map: {}!", &map_path),
+                let map = if load_shard_maps {
+                    let map_path = format!("{}/map.{}", path, i);
+
+                    // advice OS on random access to the map file and create Fst object from it
+                    let map_file = MmapReadOnly::open_path(&map_path).unwrap();
+                    unsafe {
+                        util::advise_ram(map_file.as_slice())
+                            .expect(&format!("Advisory failed for map {}", i))
+                    };
+                    match Fst::from_mmap(map_file) {
+                        Ok(fst) => Some(Map::from(fst)),
+                        Err(_) => panic!("Failed to load index map: {}!", &map_path),
+                    }
+                } else {
+                    None
                 };
 
                 let shard_name = format!("{}/shard.{}", path, i);
@@ -440,12 +583,42 @@ impl<'a> Qpick<'a> {
                     None
                 };
 
+                let q2i_path = PathBuf::from(&path).join(&format!("{}.{}", c.q2i_file, i));
+                let q2i = if !c.q2i_file.is_empty() && q2i_path.is_file() {
+                    match Map::from_path(&q2i_path) {
+                        Ok(q2i) => Some(q2i),
+                        Err(_) => panic!("Failed to load the query to id map: {:?}!", &q2i_path),
+                    }
+                } else {
+                    None
+                };
+
+                let ann_path = PathBuf::from(&path).join(&format!("{}.{}", c.ann_file, i));
+                let ann = if c.use_word_vectors && ann_path.is_file() {
+                    match ann::Ann::load(&ann_path) {
+                        Ok(ann) => Some(ann),
+                        Err(err) => panic!([
+                            BYELL,
+                            "Failed to load ANN graph: ",
+                            ECOL,
+                            BRED,
+                            &format!("{}", err),
+                            ECOL
+                        ]
+                        .join("")),
+                    }
+                } else {
+                    None
+                };
+
                 (
                     !i2q.is_none(),
                     Shard {
                         shard: shard,
                         map: map,
                         i2q: i2q,
+                        q2i: q2i,
+                        ann: ann,
                     },
                 )
             })
@@ -461,36 +634,132 @@ impl<'a> Qpick<'a> {
             let words_path = PathBuf::from(&c.words_file);
             let word_vecs_path = PathBuf::from(&c.word_vecs_file);
             if words_path.is_file() && word_vecs_path.is_file() {
-                word_vecs = Some(WordVecs::load(&words_path, &word_vecs_path));
+                let mut wv = WordVecs::load(&words_path, &word_vecs_path, c.word_vecs_dim);
+
+                let subword_vecs_path = PathBuf::from(&c.subword_vecs_file);
+                if subword_vecs_path.is_file() {
+                    wv.load_subwords(&subword_vecs_path, c.subword_min_n, c.subword_max_n);
+                }
+
+                word_vecs = Some(wv);
             }
         }
 
         Qpick {
             config: c,
             path: path,
+            analyzer: analyzer,
             synonyms: synonyms,
             toponyms: toponyms,
             stopwords: stopwords,
+            stopwords_lists: stopwords_lists,
             terms_relevance: terms_relevance,
             shards: Arc::new(shards),
+            union_map: union_map,
             shard_range: shard_range,
             id_size: id_size,
             i2q_loaded: i2q_loaded,
+            operators_warned: AtomicBool::new(false),
             shard_num: shard_num,
             word_vecs: word_vecs,
+            scorer: scorer,
         }
     }
 
+    // re-ranks the candidates of all requests with the scorer
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>) {
+        self.scorer = scorer;
+    }
+
+    // scorer by its name for get_with_scorer or SearchOptions, a model scorer loads the
+    // model_file of the index
+    pub fn load_scorer(&self, name: &str) -> Result<Box<dyn Scorer>, std::io::Error> {
+        scorer::load(name, &self.path, &self.config)
+    }
+
+    #[inline]
+    fn score_context(&self) -> scorer::Context<'_> {
+        scorer::Context {
+            word_vecs: self.word_vecs.as_ref(),
+            terms_relevance: &self.terms_relevance,
+            stopwords: &self.stopwords,
+            analyzer: &*self.analyzer,
+        }
+    }
+
+    // shard by its global id, None if it's not in the shard range of the instance
+    #[inline]
+    fn get_shard(&self, shard_id: usize) -> Option<&Shard> {
+        if shard_id < self.shard_range.start as usize || shard_id >= self.shard_range.end as usize {
+            return None;
+        }
+
+        Some(&self.shards[shard_id - self.shard_range.start as usize])
+    }
+
     pub fn i2q_is_loaded(&self) -> bool {
         self.i2q_loaded
     }
 
+    // Query text of a global query id, None if the shard of the id is not loaded,
+    // has no i2q or the id is out of its range
+    pub fn get_query(&self, query_id: u64) -> Option<Cow<'_, str>> {
+        let (shard_query_id, shard_id) = util::query_id_2_shard_id(query_id, self.shard_num);
+        let i2q = self.get_shard(shard_id as usize)?.i2q.as_ref()?;
+
+        i2q.get(shard_query_id as usize)
+    }
+
+    pub fn get_queries(&self, query_ids: &[u64]) -> Vec<Option<Cow<'_, str>>> {
+        query_ids
+            .iter()
+            .map(|query_id| self.get_query(*query_id))
+            .collect()
+    }
+
+    // Query id of an indexed query with the same normalized text, None if there is none or
+    // the shards have no query to id maps. The smallest id of duplicated queries is returned.
+    pub fn lookup_id(&self, query: &str) -> Option<u64> {
+        let key = q2i::key(query, &*self.analyzer);
+        if key.is_empty() {
+            return None;
+        }
+
+        self.shards
+            .iter()
+            .zip(self.shard_range.start..self.shard_range.end)
+            .filter_map(|(shard, shard_id)| {
+                shard
+                    .q2i
+                    .as_ref()
+                    .and_then(|q2i| q2i.get(&key))
+                    .map(|shard_query_id| {
+                        util::shard_id_2_query_id(shard_query_id, shard_id as u8, self.shard_num)
+                    })
+            })
+            .min()
+    }
+
     pub fn from_path(path: String) -> Self {
-        Qpick::new(path, None)
+        Qpick::new(path, None, None)
     }
 
     pub fn from_path_with_shard_range(path: String, shard_range: Range<u32>) -> Self {
-        Qpick::new(path, Some(shard_range))
+        Qpick::new(path, Some(shard_range), None)
+    }
+
+    // loads the union map (qpick merge) or the maps of the shards, regardless of the config,
+    // for the given shard range or all the shards
+    pub fn from_path_with_union_map(
+        path: String,
+        use_union_map: bool,
+        shard_range: Option<Range<u32>>,
+    ) -> Self {
+        Qpick::new(path, shard_range, Some(use_union_map))
+    }
+
+    pub fn union_map_is_loaded(&self) -> bool {
+        self.union_map.is_some()
     }
 
     #[inline]
@@ -518,7 +787,10 @@ impl<'a> Qpick<'a> {
         words: Vec<String>,
         wrs: Vec<f32>,
         must_have: Vec<usize>,
-        synonyms: FnvHashMap<usize, String>,
+        synonyms: FnvHashMap<usize, Vec<Synonym>>,
+        dropped_words: Vec<String>,
+        operators: &Operators,
+        scorer: &dyn Scorer,
         count: Option<usize>,
         with_tfidf: bool,
     ) -> Result<Vec<SearchResult>, Error> {
@@ -526,11 +798,17 @@ impl<'a> Qpick<'a> {
         let shard_results: Vec<ShardResults> = shard_ngrams
             .iter()
             .map(|(shard_id, ngrams)| {
+                // ngrams are sharded only to the shards of the instance
+                let shard = self.get_shard(*shard_id).unwrap();
+                let map = match self.union_map {
+                    Some(ref union_map) => NgramMap::Union(union_map, *shard_id),
+                    None => NgramMap::Shard(shard.map.as_ref().unwrap()),
+                };
                 get_shard_results(
                     ngrams,
                     &trs,
-                    &self.shards[*shard_id].map,
-                    &self.shards[*shard_id].shard,
+                    &map,
+                    &shard.shard,
                     self.id_size,
                     self.shard_num,
                     with_tfidf,
@@ -545,8 +823,13 @@ impl<'a> Qpick<'a> {
         // query_id -> [ngram_rel_0, ngram_rel_1, ..., ngram_rel_n]
         let vec_len = words.len();
         let mut res_data: FnvHashMap<u64, Vec<f32>> = FnvHashMap::default();
+        // query_id -> (number of ngram hits, max ngram relevance)
+        let mut ngram_stats: FnvHashMap<u64, (usize, f32)> = FnvHashMap::default();
         for sh_res in shard_results.iter() {
             for r in sh_res.results.iter() {
+                let stats = ngram_stats.entry(r.query_id).or_insert((0, 0.0));
+                *stats = (stats.0 + 1, util::max(stats.1, r.ngram_rel));
+
                 let ref mut words_rel_vec =
                     *res_data.entry(r.query_id).or_insert(vec![0.0; vec_len]);
 
@@ -583,26 +866,59 @@ impl<'a> Qpick<'a> {
             .collect::<Vec<KeywordMatchResult>>();
         keyword_matches.sort_by(|a, b| a.partial_cmp(&b).unwrap_or(Ordering::Less));
 
-        let (words_index, words_set) = index_words(&words, &synonyms);
+        // queries nearest to the query vector, they don't have to share keywords with the query
+        let mut ann_ids: FnvHashSet<u64> = FnvHashSet::default();
+        let mut ann_matches: Vec<KeywordMatchResult> = vec![];
+        for (query_id, shard_query_id, shard_id) in self.get_ann_matches(&words) {
+            if ids_map.contains_key(&query_id) {
+                continue;
+            }
+            ids_map.insert(query_id, (shard_query_id, shard_id));
+            ann_ids.insert(query_id);
+            ann_matches.push(KeywordMatchResult {
+                query_id: query_id,
+                dist: 1.0,
+            });
+        }
+
+        let (words_index, mut words_set) = index_words(&words, &synonyms);
+        // words dropped from a long query still count as matched or missing
+        words_set.extend(dropped_words);
 
-        let cand_synonyms: FnvHashMap<String, String> = synonyms
-            .iter()
-            .map(|(wid, syn)| (syn.to_string(), words[*wid].to_string()))
-            .collect();
+        let cand_synonyms = synonym_words(&words, &synonyms);
 
+        let score_ctx = self.score_context();
         let mut search_results: Vec<SearchResult> = keyword_matches
             .into_iter()
             .take(util::max(count.unwrap_or(FETCH_MIN), FETCH_MIN))
-            .map(|m| {
+            .chain(ann_matches.into_iter())
+            .filter_map(|m| {
                 let (sh_qid, sh_id) = ids_map.get(&m.query_id).unwrap();
-                let cand_query = self.shards[*sh_id as usize]
-                    .i2q
-                    .as_ref()
-                    .map(|i2q| i2q[*sh_qid as usize].to_string())
+                let cand_query = self
+                    .get_shard(*sh_id as usize)
+                    .and_then(|shard| shard.i2q.as_ref())
+                    .and_then(|i2q| i2q.get(*sh_qid as usize))
+                    .map(|query| query.into_owned())
                     .unwrap_or(String::from(""));
 
                 let (cand_words, match_words, miss_words, excess_words) =
-                    ngrams::match_queries(&cand_query, &words_set, &cand_synonyms);
+                    ngrams::match_queries(&cand_query, &*self.analyzer, &words_set, &cand_synonyms);
+
+                // operators are checked against the candidate text, if i2q is loaded
+                if self.i2q_loaded && !operators.accepts(&cand_words, &miss_words, &*self.analyzer)
+                {
+                    return None;
+                }
+
+                // keyword matches are already filtered by the must-have words
+                if ann_ids.contains(&m.query_id)
+                    && must_have.iter().any(|i| miss_words.contains(&words[*i]))
+                {
+                    return None;
+                }
+
+                let (ngram_hits, max_ngram_rel) =
+                    ngram_stats.get(&m.query_id).cloned().unwrap_or((0, 0.0));
 
                 // check excess words and update keyword score
                 let mut keyword_dist = m.dist;
@@ -612,13 +928,23 @@ impl<'a> Qpick<'a> {
                     }
                 }
 
-                let cosine_dist = self.cosine_diff_distance(
-                    &words,
-                    &cand_words,
-                    &match_words,
-                    &miss_words,
-                    &excess_words,
-                    keyword_dist,
+                let (keyword_dist, cosine_dist) = scorer.score(
+                    &scorer::Candidate {
+                        words: &words,
+                        wrs: &wrs,
+                        query: &cand_query,
+                        cand_words: &cand_words,
+                        match_words: &match_words,
+                        miss_words: &miss_words,
+                        excess_words: &excess_words,
+                        keyword_dist: keyword_dist,
+                        query_id: m.query_id,
+                        must_have: &must_have,
+                        ngram_hits: ngram_hits,
+                        max_ngram_rel: max_ngram_rel,
+                        cosine_memo: Cell::new(None),
+                    },
+                    &score_ctx,
                 );
 
                 let dist = Distance {
@@ -627,11 +953,11 @@ impl<'a> Qpick<'a> {
                     cosine: cosine_dist,
                 };
 
-                SearchResult {
+                Some(SearchResult {
                     query_id: m.query_id,
                     dist: dist,
                     query: Some(cand_query),
-                }
+                })
             })
             .collect();
         search_results.sort_by(|a, b| a.partial_cmp(&b).unwrap_or(Ordering::Less));
@@ -640,193 +966,97 @@ impl<'a> Qpick<'a> {
         Ok(search_results)
     }
 
-    #[inline]
-    fn cosine_diff_distance(
-        &self,
-        words: &Vec<String>,
-        cand_words: &Vec<String>,
-        match_words: &Vec<String>,
-        miss_words: &Vec<String>,
-        excess_words: &Vec<String>,
-        keyword_dist: f32,
-    ) -> Option<f32> {
-        if self.word_vecs.is_none() {
-            return None;
-        }
-
-        let match_len = match_words.len();
-        let (mut rhs_match_vec, nf_match, _nf_match_words) = self
-            .word_vecs
-            .as_ref()
-            .unwrap()
-            .get_combined_vec(&match_words, &self.terms_relevance, &self.stopwords);
-
-        let missing_len = miss_words.len();
-        let excess_len = excess_words.len();
-
-        if miss_words.is_empty() && excess_words.is_empty() {
-            return Some(0.0);
-        }
-
-        let (mut missing_vec, nf_miss, _nf_miss_words) = self
-            .word_vecs
-            .as_ref()
-            .unwrap()
-            .get_combined_vec(&miss_words, &self.terms_relevance, &self.stopwords);
-        let (mut excess_vec, nf_excs, _nf_excs_words) = self
-            .word_vecs
-            .as_ref()
-            .unwrap()
-            .get_combined_vec(&excess_words, &self.terms_relevance, &self.stopwords);
-
-        // either no match words or none of them are found
-        if nf_match == match_len {
-            // no missing words or none of them found OR
-            // no excess words or none of them found
-            if nf_miss == missing_len || nf_excs == excess_len {
-                return None;
-            }
-
-            word_vec::normalize(&mut excess_vec[..]);
-            word_vec::normalize(&mut missing_vec[..]);
-            let cos_dist = word_vec::cosine_distance(&excess_vec, &missing_vec);
-
-            if missing_len > match_len || excess_len > match_len {
-                return Some(util::min(cos_dist * (1.0 + keyword_dist), 1.0));
-            }
-
-            return Some(word_vec::cosine_distance(&excess_vec, &missing_vec));
-        }
-
-        // there are at least some match words at this point
+    // Searches the ANN graphs of the shards with i2q for the queries nearest to the combined
+    // vector of the query words. Returns (query_id, shard_query_id, shard_id).
+    fn get_ann_matches(&self, words: &Vec<String>) -> Vec<(u64, u32, u8)> {
+        let word_vecs = match self.word_vecs {
+            Some(ref word_vecs) if self.config.ann_count > 0 => word_vecs,
+            _ => return vec![],
+        };
 
-        // if both, missing AND excess words are not found, we can't calculate cosine
-        if nf_miss == missing_len && nf_excs == excess_len {
-            return None;
+        let (mut query_vec, nf, _) =
+            word_vecs.get_combined_vec(words, &self.terms_relevance, &self.stopwords);
+        if nf == words.len() {
+            return vec![];
         }
+        word_vec::normalize(&mut query_vec[..]);
 
-        // if no missing words, cosine dist
-        if nf_miss == missing_len {
-            if let Some(cos_dist) = self.cosine_distance(words, &cand_words) {
-                let nf = (nf_excs + nf_match + nf_miss) as f32;
-                let nr_miss = (missing_len + excess_len) as f32;
-
-                if match_len >= 2 && missing_len == 0 && excess_len < 2 && keyword_dist < 0.3 {
-                    return Some(cos_dist * keyword_dist);
-                }
-
-                if match_len >= 2
-                    && match_len > missing_len
-                    && match_len > excess_len
-                    && keyword_dist < 0.45
-                    && nf <= 1.0
+        let mut matches = vec![];
+        for (shard, shard_id) in self
+            .shards
+            .iter()
+            .zip(self.shard_range.start..self.shard_range.end)
+        {
+            if let (Some(ann), Some(_)) = (shard.ann.as_ref(), shard.i2q.as_ref()) {
+                for (shard_query_id, _) in
+                    ann.search(&query_vec, self.config.ann_count, self.config.ann_ef)
                 {
-                    return Some(cos_dist * (keyword_dist / (keyword_dist + cos_dist)));
+                    let query_id = util::shard_id_2_query_id(
+                        shard_query_id as u64,
+                        shard_id as u8,
+                        self.shard_num,
+                    );
+                    matches.push((query_id, shard_query_id, shard_id as u8));
                 }
-
-                return Some(util::min(cos_dist + nf * keyword_dist / nr_miss, 1.0));
             }
-
-            return None;
         }
 
-        if nf_excs == excess_len {
-            if let Some(cos_dist) = self.cosine_distance(words, &cand_words) {
-                let nf = (nf_excs + nf_match + nf_miss) as f32;
-                let nr_miss = (missing_len + excess_len) as f32;
-
-                if match_len >= 2 && missing_len < 2 && excess_len == 0 && keyword_dist < 0.3 {
-                    return Some(cos_dist * keyword_dist);
-                }
-
-                if match_len >= 2
-                    && match_len > excess_len
-                    && match_len > missing_len
-                    && keyword_dist < 0.45
-                    && nf <= 1.0
-                {
-                    return Some(cos_dist * (keyword_dist / (keyword_dist + cos_dist)));
-                }
+        matches
+    }
 
-                return Some(util::min(cos_dist + nf * keyword_dist / nr_miss, 1.0));
+    // Builds the ANN graphs of the query vectors of the loaded shards with i2q, saved as
+    // ann_file.<shard_id> in the index directory. Returns the number of indexed queries.
+    pub fn build_ann(
+        &self,
+        m: usize,
+        ef_construction: usize,
+        vec_type: word_vec::VecType,
+    ) -> Result<usize, std::io::Error> {
+        let word_vecs = match self.word_vecs {
+            Some(ref word_vecs) => word_vecs,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "Word vectors are not loaded, set use_word_vectors in config.json",
+                ))
             }
-
-            return None;
-        }
-
-        let mut lhs_match_vec = rhs_match_vec.clone();
-
-        if missing_len > 0 {
-            word_vec::subtract(&mut lhs_match_vec, &missing_vec);
-        }
-
-        if excess_len > 0 {
-            word_vec::subtract(&mut rhs_match_vec, &excess_vec);
-        }
-
-        word_vec::normalize(&mut missing_vec[..]);
-        word_vec::normalize(&mut excess_vec[..]);
-        let me_cos_dist = word_vec::cosine_distance(&missing_vec, &excess_vec);
-
-        word_vec::normalize(&mut lhs_match_vec[..]);
-        word_vec::normalize(&mut rhs_match_vec[..]);
-
-        let cos_dist = word_vec::cosine_distance(&lhs_match_vec, &rhs_match_vec);
-
-        let thresh: f32;
-        if missing_len > 1 {
-            if excess_len < 2 {
-                thresh = 0.5;
-            } else {
-                thresh = 0.45;
-            };
-        } else {
-            if excess_len < 2 {
-                thresh = 0.55;
-            } else {
-                thresh = 0.65;
-            };
         };
 
-        // missing and excess words and keyword distance is too large
-        if excess_len >= 1 && missing_len >= 1 && match_len <= 2 && keyword_dist > 0.45 {
-            return Some(1.3 * cos_dist + util::max(0.0, me_cos_dist - thresh));
-        }
-
-        // more than one excess, at most one missing, keyword distance is not so big
-        if excess_len <= 2 && missing_len <= 2 && match_len > excess_len && keyword_dist <= 0.45 {
-            return Some(0.75 * cos_dist + util::max(0.0, me_cos_dist - thresh));
-        }
+        let no_words = FnvHashSet::default();
+        let no_synonyms = FnvHashMap::default();
 
-        Some(cos_dist + util::max(0.0, me_cos_dist - thresh))
-    }
+        let mut nr_queries = 0;
+        for (shard, shard_id) in self
+            .shards
+            .iter()
+            .zip(self.shard_range.start..self.shard_range.end)
+        {
+            let i2q = match shard.i2q {
+                Some(ref i2q) => i2q,
+                None => continue,
+            };
 
-    #[inline]
-    fn cosine_distance(&self, query_words: &Vec<String>, cand_words: &Vec<String>) -> Option<f32> {
-        let (mut query_vec, nf, _nf_query_words) = self
-            .word_vecs
-            .as_ref()
-            .unwrap()
-            .get_combined_vec(&query_words, &self.terms_relevance, &self.stopwords);
-
-        if nf == query_words.len() {
-            return None;
-        }
+            let mut builder = ann::Builder::new(m, ef_construction);
+            for (shard_query_id, query) in i2q.iter().enumerate() {
+                let (words, _, _, _) =
+                    ngrams::match_queries(&query, &*self.analyzer, &no_words, &no_synonyms);
+                let (mut vec, nf, _) =
+                    word_vecs.get_combined_vec(&words, &self.terms_relevance, &self.stopwords);
+                if nf == words.len() {
+                    continue;
+                }
 
-        let (mut cand_vec, nf, _nf_cand_words) = self.word_vecs.as_ref().unwrap().get_combined_vec(
-            &cand_words,
-            &self.terms_relevance,
-            &self.stopwords,
-        );
+                word_vec::normalize(&mut vec[..]);
+                builder.add(shard_query_id as u32, vec);
+            }
 
-        if nf == cand_words.len() {
-            return None;
+            let ann_path =
+                PathBuf::from(&self.path).join(&format!("{}.{}", self.config.ann_file, shard_id));
+            builder.write(&ann_path, vec_type)?;
+            nr_queries += builder.len();
         }
 
-        word_vec::normalize(&mut query_vec[..]);
-        word_vec::normalize(&mut cand_vec[..]);
-
-        Some(word_vec::cosine_distance(&query_vec, &cand_vec))
+        Ok(nr_queries)
     }
 
     pub fn get_distances(&self, query: &str, candidates: &Vec<String>) -> Vec<DistanceResult> {
@@ -835,35 +1065,45 @@ impl<'a> Qpick<'a> {
         }
 
         let mut dist_results: Vec<DistanceResult> = vec![];
-        let (_, _, _, words, wrs, _, word_syns) = ngrams::parse(
+        let (_, _, _, words, wrs, must_have, word_syns, dropped_words) = ngrams::parse(
             &query,
+            &*self.analyzer,
             &self.synonyms,
             &self.toponyms,
             &self.stopwords,
             &self.terms_relevance,
+            self.config.words_per_query,
             ngrams::ParseMode::Search,
         );
 
-        let (words_index, words_set) = index_words(&words, &word_syns);
-
-        let cand_synonyms: FnvHashMap<String, String> = word_syns
+        let (words_index, mut words_set) = index_words(&words, &word_syns);
+        words_set.extend(dropped_words);
+        let stems_index: FnvHashMap<String, usize> = words
             .iter()
-            .map(|(wid, syn)| (syn.to_string(), words[*wid].to_string()))
+            .enumerate()
+            .map(|(i, w)| (self.analyzer.stem(w), i))
             .collect();
 
+        let cand_synonyms = synonym_words(&words, &word_syns);
+
         for (cid, cand_query) in candidates.into_iter().enumerate() {
-            let (_, _, _, cand_words, cand_wrs, _, _) = ngrams::parse(
+            let (_, _, _, cand_words, cand_wrs, _, _, _) = ngrams::parse(
                 &cand_query,
+                &*self.analyzer,
                 &self.synonyms,
                 &self.toponyms,
                 &self.stopwords,
                 &self.terms_relevance,
+                self.config.words_per_query,
                 ngrams::ParseMode::Search,
             );
 
             let mut words_rel_vec = vec![0.0; words.len()];
             for (cword_idx, cword) in cand_words.iter().enumerate() {
-                if let Some(word_idx) = words_index.get(cword) {
+                let word_idx = words_index
+                    .get(cword)
+                    .or_else(|| stems_index.get(&self.analyzer.stem(cword)));
+                if let Some(word_idx) = word_idx {
                     words_rel_vec[*word_idx] += util::min(wrs[*word_idx], cand_wrs[cword_idx]);
                 }
             }
@@ -875,15 +1115,25 @@ impl<'a> Qpick<'a> {
             let keyword_dist = util::max(1.0 - sim, 0.0);
 
             let (cand_words, match_words, miss_words, excess_words) =
-                ngrams::match_queries(cand_query, &words_set, &cand_synonyms);
-
-            let cosine_dist = self.cosine_diff_distance(
-                &words,
-                &cand_words,
-                &match_words,
-                &miss_words,
-                &excess_words,
-                keyword_dist,
+                ngrams::match_queries(cand_query, &*self.analyzer, &words_set, &cand_synonyms);
+
+            let (keyword_dist, cosine_dist) = self.scorer.score(
+                &scorer::Candidate {
+                    words: &words,
+                    wrs: &wrs,
+                    query: cand_query,
+                    cand_words: &cand_words,
+                    match_words: &match_words,
+                    miss_words: &miss_words,
+                    excess_words: &excess_words,
+                    keyword_dist: keyword_dist,
+                    query_id: cid as u64,
+                    must_have: &must_have,
+                    ngram_hits: 0,
+                    max_ngram_rel: 0.0,
+                    cosine_memo: Cell::new(None),
+                },
+                &self.score_context(),
             );
 
             dist_results.push(DistanceResult {
@@ -900,18 +1150,230 @@ impl<'a> Qpick<'a> {
     }
 
     pub fn get(&self, query: &str, count: u32, with_tfidf: bool) -> Vec<SearchResult> {
+        self.search(query, count, with_tfidf, &SearchOptions::default())
+    }
+
+    // Searches with any combination of the request options
+    pub fn search(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        options: &SearchOptions,
+    ) -> Vec<SearchResult> {
         if query == "" || count == 0 {
             return vec![];
         }
 
-        let (ngrams, trs, ngrams_ids, words, wrs, must_have, synonyms) = ngrams::parse(
-            &query,
-            &self.synonyms,
-            &self.toponyms,
-            &self.stopwords,
+        if options.prefix {
+            return self.complete_with_options(query, count, with_tfidf, options);
+        }
+
+        let corrections: FnvHashMap<String, String> = match options.max_edits {
+            Some(max_edits) => self.get_corrections(query, max_edits).into_iter().collect(),
+            None => FnvHashMap::default(),
+        };
+
+        self.search_corrected(query, count, with_tfidf, options, &corrections)
+    }
+
+    fn search_corrected(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        options: &SearchOptions,
+        corrections: &FnvHashMap<String, String>,
+    ) -> Vec<SearchResult> {
+        let stopwords = match options.stopwords {
+            Some(name) => match self.stopwords_lists.get(name) {
+                Some(stopwords) => stopwords,
+                None => {
+                    println!(
+                        "{}",
+                        [BYELL, "No such stopwords list: ", ECOL, BRED, name, ECOL].join("")
+                    );
+
+                    return vec![];
+                }
+            },
+            None => &self.stopwords,
+        };
+        let scorer = options.scorer.unwrap_or(&*self.scorer);
+
+        // an indexed query is the best match of itself, the search is skipped for one result.
+        // Only the query without operators is looked up, the match has to satisfy them.
+        let (plain_query, operators) = operators::parse(query, &*self.analyzer);
+        let exact_match = self
+            .lookup_id(&plain_query)
+            .map(|query_id| (query_id, self.get_query(query_id).map(|q| q.into_owned())))
+            .filter(|(_, exact_query)| {
+                let cand_text = exact_query.as_ref().unwrap_or(&plain_query);
+                let cand_words = q2i::key(cand_text, &*self.analyzer)
+                    .split(" ")
+                    .map(|w| w.to_string())
+                    .collect();
+
+                operators.accepts(&cand_words, &vec![], &*self.analyzer)
+            })
+            .map(|(query_id, exact_query)| SearchResult {
+                query_id: query_id,
+                query: exact_query,
+                dist: Distance {
+                    query_id: query_id,
+                    keyword: 0.0,
+                    cosine: self.word_vecs.as_ref().map(|_| 0.0),
+                },
+            });
+        if count == 1 && exact_match.is_some() {
+            return exact_match.into_iter().collect();
+        }
+
+        let mut results =
+            self.get_corrected(query, count, with_tfidf, corrections, stopwords, scorer);
+        if let Some(exact_match) = exact_match {
+            results.retain(|r| r.query_id != exact_match.query_id);
+            results.insert(0, exact_match);
+            results.truncate(count as usize);
+        }
+
+        results
+    }
+
+    // Searches with the given scorer instead of the one of the instance
+    pub fn get_with_scorer(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        scorer: &dyn Scorer,
+    ) -> Vec<SearchResult> {
+        let options = SearchOptions {
+            scorer: Some(scorer),
+            ..SearchOptions::default()
+        };
+
+        self.search(query, count, with_tfidf, &options)
+    }
+
+    // Searches with the scorer of the instance, returns the results with their features
+    // (scorer::FEATURES) for the offline training of learned models
+    pub fn get_features(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+    ) -> Vec<(SearchResult, Vec<f32>)> {
+        let recorder = scorer::FeaturesRecorder::new(&*self.scorer);
+        let results = self.get_with_scorer(query, count, with_tfidf, &recorder);
+        let mut features = recorder.features.into_inner().unwrap();
+
+        results
+            .into_iter()
+            .filter_map(|r| features.remove(&r.query_id).map(|f| (r, f)))
+            .collect()
+    }
+
+    // Searches with a named stopwords list from the config instead of the one selected by the
+    // analyzer. Returns no results if there is no such list.
+    pub fn get_with_stopwords(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        stopwords: &str,
+    ) -> Vec<SearchResult> {
+        let options = SearchOptions {
+            stopwords: Some(stopwords),
+            ..SearchOptions::default()
+        };
+
+        self.search(query, count, with_tfidf, &options)
+    }
+
+    // Corrections of the query words unknown to the terms relevance map to their closest
+    // known words within max_edits (1 or 2)
+    pub fn get_corrections(&self, query: &str, max_edits: u32) -> Vec<(String, String)> {
+        ngrams::get_corrections(
+            query,
+            &*self.analyzer,
             &self.terms_relevance,
-            ngrams::ParseMode::Search,
-        );
+            &self.stopwords,
+            max_edits,
+        )
+    }
+
+    // Typo tolerant search: query words unknown to the terms relevance map are corrected to
+    // their closest known words within max_edits (1 or 2), corrections are searched for as
+    // synonyms of the original words. Returns the results and the applied corrections.
+    pub fn get_fuzzy(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        max_edits: u32,
+    ) -> (Vec<SearchResult>, Vec<(String, String)>) {
+        if query == "" || count == 0 {
+            return (vec![], vec![]);
+        }
+
+        let corrections = self.get_corrections(query, max_edits);
+        let corrections_map: FnvHashMap<String, String> = corrections.iter().cloned().collect();
+
+        (
+            self.search_corrected(
+                query,
+                count,
+                with_tfidf,
+                &SearchOptions::default(),
+                &corrections_map,
+            ),
+            corrections,
+        )
+    }
+
+    fn get_corrected(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        corrections: &FnvHashMap<String, String>,
+        stopwords: &Stopwords,
+        scorer: &dyn Scorer,
+    ) -> Vec<SearchResult> {
+        if query == "" || count == 0 {
+            return vec![];
+        }
+
+        let (query, operators) = operators::parse(query, &*self.analyzer);
+        if operators.needs_text()
+            && !self.i2q_loaded
+            && !self.operators_warned.swap(true, AtomicOrdering::Relaxed)
+        {
+            println!(
+                "{}",
+                [
+                    BYELL,
+                    "Excluded words and phrases are ignored, i2q is not loaded!",
+                    ECOL
+                ]
+                .join("")
+            );
+        }
+        let (ngrams, trs, ngrams_ids, words, wrs, mut must_have, synonyms, dropped_words) =
+            ngrams::parse_with_corrections(
+                &query,
+                &*self.analyzer,
+                &self.synonyms,
+                &self.toponyms,
+                stopwords,
+                &self.terms_relevance,
+                corrections,
+                self.config.words_per_query,
+                ngrams::ParseMode::Search,
+            );
+
+        operators.extend_must_have(&words, &mut must_have, &*self.analyzer);
 
         match self.get_matches(
             ngrams,
@@ -921,6 +1383,9 @@ impl<'a> Qpick<'a> {
             wrs,
             must_have,
             synonyms,
+            dropped_words,
+            &operators,
+            scorer,
             Some(count as usize),
             with_tfidf,
         ) {
@@ -933,6 +1398,59 @@ impl<'a> Qpick<'a> {
         }
     }
 
+    // Typeahead search: the last word of the query is treated as a prefix, expanded to the
+    // most common completions and each completed query is searched for, results are merged.
+    pub fn complete(&self, query: &str, count: u32, with_tfidf: bool) -> Vec<SearchResult> {
+        let options = SearchOptions {
+            prefix: true,
+            ..SearchOptions::default()
+        };
+
+        self.search(query, count, with_tfidf, &options)
+    }
+
+    fn complete_with_options(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        options: &SearchOptions,
+    ) -> Vec<SearchResult> {
+        // completed queries are searched for with the other options
+        let options = SearchOptions {
+            prefix: false,
+            ..*options
+        };
+
+        let (head, prefix) = ngrams::split_prefix(query, &*self.analyzer);
+        let completions =
+            ngrams::complete_prefix(&prefix, &self.terms_relevance, ngrams::MAX_COMPLETIONS);
+        if completions.is_empty() {
+            return self.search(query, count, with_tfidf, &options);
+        }
+
+        let completed_results: Vec<Vec<SearchResult>> = completions
+            .par_iter()
+            .map(|word| self.search(&format!("{} {}", head, word), count, with_tfidf, &options))
+            .collect();
+
+        // keep the closest result for queries found by multiple completions
+        let mut best_results: FnvHashMap<u64, SearchResult> = FnvHashMap::default();
+        for r in completed_results.into_iter().flat_map(|rs| rs.into_iter()) {
+            let best = best_results.entry(r.query_id).or_insert(r.clone());
+            if r < *best {
+                *best = r;
+            }
+        }
+
+        let mut search_results: Vec<SearchResult> =
+            best_results.into_iter().map(|(_, r)| r).collect();
+        search_results.sort_by(|a, b| a.partial_cmp(&b).unwrap_or(Ordering::Less));
+        search_results.truncate(count as usize);
+
+        search_results
+    }
+
     pub fn get_search_results_as_string(
         &self,
         query: &str,
@@ -949,6 +1467,153 @@ impl<'a> Qpick<'a> {
         serde_json::to_string(&res).unwrap()
     }
 
+    // Query texts of newline separated query ids as a JSON list, null for the ids that are
+    // not valid or not found
+    pub fn get_queries_as_string(&self, query_ids: &str) -> String {
+        let queries: Vec<Option<Cow<'_, str>>> = query_ids
+            .lines()
+            .map(|query_id| {
+                query_id
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|query_id| self.get_query(query_id))
+            })
+            .collect();
+
+        serde_json::to_string(&queries).unwrap()
+    }
+
+    // Query ids of newline separated queries as a JSON list, null for the queries not indexed
+    pub fn lookup_ids_as_string(&self, queries: &str) -> String {
+        let query_ids: Vec<Option<u64>> = queries.lines().map(|q| self.lookup_id(q)).collect();
+
+        serde_json::to_string(&query_ids).unwrap()
+    }
+
+    // Returns `limit` results starting at `offset`. All pages of the same query are cut
+    // from the same window of re-scored candidates, so the ordering is stable across pages.
+    // The window is capped at MAX_PAGE_WINDOW, pages past it are empty.
+    pub fn get_page(&self, query: &str, offset: u32, limit: u32, with_tfidf: bool) -> SearchPage {
+        self.get_page_with_options(query, offset, limit, with_tfidf, &SearchOptions::default())
+    }
+
+    pub fn get_page_with_options(
+        &self,
+        query: &str,
+        offset: u32,
+        limit: u32,
+        with_tfidf: bool,
+        options: &SearchOptions,
+    ) -> SearchPage {
+        let offset = offset as usize;
+        let window = util::min(
+            page::MAX_PAGE_WINDOW,
+            util::max(page::PAGE_WINDOW, offset.saturating_add(limit as usize)),
+        );
+        if offset >= window {
+            return SearchPage {
+                results: vec![],
+                cursor: None,
+            };
+        }
+        let cursor = PageCursor::new(query, offset, window);
+
+        self.get_cursor_page(query, cursor, limit, with_tfidf, options)
+    }
+
+    // Returns the page pointed to by a cursor from a previous page, None if the cursor is not valid
+    pub fn get_page_with_cursor(
+        &self,
+        query: &str,
+        cursor: &str,
+        limit: u32,
+        with_tfidf: bool,
+    ) -> Option<SearchPage> {
+        self.get_page_with_cursor_and_options(
+            query,
+            cursor,
+            limit,
+            with_tfidf,
+            &SearchOptions::default(),
+        )
+    }
+
+    // The options have to be the same for all pages of a query to keep the ordering stable
+    pub fn get_page_with_cursor_and_options(
+        &self,
+        query: &str,
+        cursor: &str,
+        limit: u32,
+        with_tfidf: bool,
+        options: &SearchOptions,
+    ) -> Option<SearchPage> {
+        match PageCursor::decode(cursor, query) {
+            Some(cursor) => Some(self.get_cursor_page(query, cursor, limit, with_tfidf, options)),
+            None => None,
+        }
+    }
+
+    fn get_cursor_page(
+        &self,
+        query: &str,
+        cursor: PageCursor,
+        limit: u32,
+        with_tfidf: bool,
+        options: &SearchOptions,
+    ) -> SearchPage {
+        if limit == 0 {
+            return SearchPage {
+                results: vec![],
+                cursor: None,
+            };
+        }
+
+        let results = self.search(query, cursor.window as u32, with_tfidf, options);
+        let next_cursor = cursor
+            .next(limit as usize, results.len())
+            .map(|c| c.encode());
+
+        SearchPage {
+            results: results
+                .into_iter()
+                .skip(cursor.offset)
+                .take(limit as usize)
+                .collect(),
+            cursor: next_cursor,
+        }
+    }
+
+    pub fn get_page_as_string(
+        &self,
+        query: &str,
+        offset: u32,
+        limit: u32,
+        cursor: Option<&str>,
+        with_tfidf: bool,
+    ) -> String {
+        let page = match cursor {
+            Some(cursor) => self
+                .get_page_with_cursor(query, cursor, limit, with_tfidf)
+                .unwrap_or(SearchPage {
+                    results: vec![],
+                    cursor: None,
+                }),
+            None => self.get_page(query, offset, limit, with_tfidf),
+        };
+
+        let res = SearchPageString {
+            results: page
+                .results
+                .into_iter()
+                .map(|r| (r.query_id, r.dist, r.query.unwrap_or("".to_string())))
+                .collect(),
+            cursor: page.cursor,
+        };
+
+        serde_json::to_string(&res).unwrap()
+    }
+
     pub fn merge(&self) -> Result<(), Error> {
         println!("Merging index maps from: {:?}", &self.path);
         merge::merge(&self.path, self.config.nr_shards as usize)
@@ -958,6 +1623,52 @@ impl<'a> Qpick<'a> {
         SearchResults::new(self.get(query, count, with_tfidf).into_iter())
     }
 
+    pub fn get_fuzzy_results(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+        max_edits: u32,
+    ) -> SearchResults {
+        let (results, corrections) = self.get_fuzzy(query, count, with_tfidf, max_edits);
+        let mut search_results = SearchResults::new(results.into_iter());
+        search_results.corrections = corrections;
+
+        search_results
+    }
+
+    pub fn get_completion_results(
+        &self,
+        query: &str,
+        count: u32,
+        with_tfidf: bool,
+    ) -> SearchResults {
+        SearchResults::new(self.complete(query, count, with_tfidf).into_iter())
+    }
+
+    pub fn get_page_results(
+        &self,
+        query: &str,
+        offset: u32,
+        limit: u32,
+        with_tfidf: bool,
+    ) -> SearchResults {
+        SearchResults::from_page(self.get_page(query, offset, limit, with_tfidf))
+    }
+
+    pub fn get_cursor_results(
+        &self,
+        query: &str,
+        cursor: &str,
+        limit: u32,
+        with_tfidf: bool,
+    ) -> SearchResults {
+        match self.get_page_with_cursor(query, cursor, limit, with_tfidf) {
+            Some(page) => SearchResults::from_page(page),
+            None => SearchResults::new(vec![].into_iter()),
+        }
+    }
+
     pub fn get_dist_results(&self, query: &str, candidates: &Vec<String>) -> DistResults {
         DistResults::new(self.get_distances(query, candidates).into_iter())
     }
@@ -965,3 +1676,206 @@ impl<'a> Qpick<'a> {
 
 #[allow(dead_code)]
 fn main() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use fst::MapBuilder;
+    use std::collections::BTreeSet;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::io::Write;
+
+    static QUERIES: [&str; 16] = [
+        "cheap hotels paris",
+        "cheap hotels berlin",
+        "paris museums",
+        "berlin museums",
+        "rome hotels",
+        "rome museums",
+        "cheap flights rome",
+        "cheap flights paris",
+        "paris hotels hostel",
+        "berlin hostel",
+        "hotels hostel",
+        "rome hostel",
+        "paris flights",
+        "berlin flights",
+        "museums hotels",
+        "flights hotels",
+    ];
+
+    // builds an index of the queries in 4 shards with word vectors of their words
+    fn build_index(name: &str) -> String {
+        let dir = temp_dir().join(name);
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap().to_string();
+
+        let words: BTreeSet<&str> = QUERIES.iter().flat_map(|q| q.split_whitespace()).collect();
+        let mut tr_builder =
+            MapBuilder::new(File::create(dir.join("terms_relevance.fst")).unwrap()).unwrap();
+        let mut glove = File::create(dir.join("vectors.txt")).unwrap();
+        for (i, word) in words.iter().enumerate() {
+            tr_builder.insert(word, 50).unwrap();
+            let vec: Vec<String> = (0..8).map(|j| ((i * j) % 5).to_string()).collect();
+            writeln!(glove, "{} {} 1", word, vec.join(" ")).unwrap();
+        }
+        tr_builder.finish().unwrap();
+        File::create(dir.join("stopwords.txt")).unwrap();
+
+        let words_path = dir.join("words.json");
+        let word_vecs_path = dir.join("word_vecs.bin");
+        vectors::import(
+            &dir.join("vectors.txt"),
+            vectors::Format::Glove,
+            &words_path,
+            &word_vecs_path,
+            word_vec::VecType::F32,
+            true,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let mut config = File::create(dir.join("config.json")).unwrap();
+        write!(
+            config,
+            "{{\"nr_shards\": 4, \"terms_relevance_file\": \"terms_relevance.fst\", \
+             \"stopwords_file\": \"stopwords.txt\", \"i2q_file\": \"i2q\", \
+             \"use_word_vectors\": true, \"words_file\": {:?}, \"word_vecs_file\": {:?}}}",
+            words_path.to_str().unwrap(),
+            word_vecs_path.to_str().unwrap()
+        )
+        .unwrap();
+
+        let queries_path = dir.join("queries.gz");
+        let mut gz = GzEncoder::new(File::create(&queries_path).unwrap(), Compression::Default);
+        for query in QUERIES.iter() {
+            writeln!(gz, "{}", query).unwrap();
+        }
+        gz.finish().unwrap();
+
+        shard::shard(queries_path.to_str().unwrap(), 4, &path, &vec![], true).unwrap();
+        builder::index(&path, 0, 4, &path).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn test_shard_range_ann() {
+        let path = build_index("test_qpick_shard_range_ann");
+
+        let qpick = Qpick::from_path_with_shard_range(path.clone(), 2..4);
+        assert!(qpick.build_ann(8, 50, word_vec::VecType::F32).unwrap() > 0);
+        for shard_id in 0..4 {
+            let ann_path = PathBuf::from(&path).join(format!("ann.{}", shard_id));
+            assert_eq!(ann_path.is_file(), shard_id >= 2);
+        }
+
+        // queries of the shard range have their global ids
+        let qpick = Qpick::from_path_with_shard_range(path.clone(), 2..4);
+        for (query_id, query) in QUERIES.iter().enumerate() {
+            let query_id = query_id as u64;
+            let in_range = query_id % 4 >= 2;
+            assert_eq!(qpick.lookup_id(query), Some(query_id).filter(|_| in_range));
+            assert_eq!(
+                qpick.get_query(query_id).map(|q| q.into_owned()),
+                Some(query.to_string()).filter(|_| in_range)
+            );
+        }
+
+        let words = vec!["cheap".to_string(), "hotels".to_string()];
+        let matches = qpick.get_ann_matches(&words);
+        assert!(!matches.is_empty());
+        for (query_id, shard_query_id, shard_id) in matches {
+            assert!(shard_id >= 2 && shard_id < 4);
+            assert_eq!(
+                util::query_id_2_shard_id(query_id, 4),
+                (shard_query_id as u64, shard_id)
+            );
+        }
+
+        for r in qpick.get("cheap hotels", 10, false) {
+            if let Some(query) = qpick.get_query(r.query_id) {
+                assert_eq!(r.query, Some(query.into_owned()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_lookup() {
+        let path = build_index("test_qpick_batch_lookup");
+        let qpick = Qpick::from_path(path);
+
+        assert_eq!(
+            qpick.get_queries_as_string("2\n 3\nx\n\n99999"),
+            r#"["paris museums","berlin museums",null,null,null]"#
+        );
+        assert_eq!(
+            qpick.lookup_ids_as_string("Paris  Museums\nberlin museums\nnew york"),
+            "[2,3,null]"
+        );
+        assert_eq!(qpick.lookup_ids_as_string(""), "[]");
+    }
+
+    #[test]
+    fn test_union_map_shard_range() {
+        let path = build_index("test_qpick_union_map_shard_range");
+        merge::merge(&path, 4).unwrap();
+
+        let ids = |qpick: &Qpick, query: &str| -> Vec<u64> {
+            qpick
+                .get(query, 20, false)
+                .iter()
+                .map(|r| r.query_id)
+                .collect()
+        };
+
+        for shard_range in vec![0..4, 2..4, 1..2] {
+            let union_qpick =
+                Qpick::from_path_with_union_map(path.clone(), true, Some(shard_range.clone()));
+            let shards_qpick = Qpick::from_path_with_shard_range(path.clone(), shard_range);
+            assert!(union_qpick.union_map_is_loaded());
+            assert!(!shards_qpick.union_map_is_loaded());
+
+            for query in vec!["cheap hotels", "rome", "berlin hostel", "paris flights"] {
+                assert_eq!(ids(&union_qpick, query), ids(&shards_qpick, query));
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_match_operators() {
+        let path = build_index("test_qpick_exact_match_operators");
+        let qpick = Qpick::from_path(path);
+
+        let ids = |query: &str, count: u32| -> Vec<u64> {
+            qpick
+                .get(query, count, false)
+                .iter()
+                .map(|r| r.query_id)
+                .collect()
+        };
+
+        // exact matches of the query and of the query without operators
+        assert_eq!(ids("hotels hostel", 1), vec![10]);
+        assert_eq!(ids("rome hostel -museums", 1), vec![11]);
+        assert_eq!(ids("+rome hostel", 1), vec![11]);
+        assert_eq!(ids("\"rome hostel\"", 1), vec![11]);
+
+        // the operator words are not a part of the exact match
+        for count in vec![1, 5] {
+            let results = ids("hotels -hostel", count);
+            assert!(!results.is_empty());
+            assert!(!results.contains(&10));
+            assert!(!results.contains(&8));
+        }
+
+        // the exact match of the plain query is dropped if it has an excluded word
+        assert!(!ids("rome hostel -hostel", 5).contains(&11));
+        assert!(!ids("rome hostel -hostel", 1).contains(&11));
+    }
+}
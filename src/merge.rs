@@ -4,10 +4,21 @@ use std::io::BufWriter;
 
 static SEPARATOR: &'static str = "\u{0}\u{0}";
 
+// key of an ngram of a shard in the union map
+#[inline]
+pub fn union_key(ngram: &str, pid: usize) -> String {
+    format!("{}{}{}", ngram, SEPARATOR, pid)
+}
+
+#[inline]
+pub fn union_map_path(dir_path: &str, nr_shards: usize) -> String {
+    format!("{}/union_map.{}.fst", dir_path, nr_shards)
+}
+
 #[inline]
 fn npid2key(ngramv: &mut Vec<u8>, pid: usize) -> String {
     let n = String::from_utf8_lossy(&ngramv).into_owned();
-    let k = format!("{}{}{}", n, SEPARATOR, pid);
+    let k = union_key(&n, pid);
 
     if key2npid(&k) != (n.clone(), pid) {
         panic!("Failed to turn {} and {} into key {}", n, pid, &k);
@@ -39,10 +50,7 @@ pub fn merge(dir_path: &str, nr_shards: usize) -> Result<(), Error> {
     }
     let mut union = fsts.iter().collect::<raw::OpBuilder>().union();
 
-    let wtr = BufWriter::new(File::create(format!(
-        "{}/union_map.{}.fst",
-        dir_path, nr_shards
-    ))?);
+    let wtr = BufWriter::new(File::create(union_map_path(dir_path, nr_shards))?);
     let mut builder = raw::Builder::new(wtr)?;
 
     let mut count: u64 = 0;
@@ -67,3 +75,37 @@ pub fn merge(dir_path: &str, nr_shards: usize) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fst::{Map, MapBuilder};
+    use std::env::temp_dir;
+    use std::fs::create_dir_all;
+
+    #[test]
+    fn test_merge_union_key() {
+        let dir = temp_dir().join("test_merge");
+        create_dir_all(&dir).unwrap();
+        let dir_path = dir.to_str().unwrap();
+
+        let shards = vec![vec![("hotels", 10), ("paris", 20)], vec![("hotels", 30)]];
+        for (i, entries) in shards.iter().enumerate() {
+            let wtr = BufWriter::new(File::create(dir.join(format!("map.{}", i))).unwrap());
+            let mut builder = MapBuilder::new(wtr).unwrap();
+            for &(ngram, addr) in entries.iter() {
+                builder.insert(ngram, addr).unwrap();
+            }
+            builder.finish().unwrap();
+        }
+        merge(dir_path, 2).unwrap();
+
+        let union = Map::from_path(union_map_path(dir_path, 2)).unwrap();
+        assert_eq!(union.len(), 3);
+        assert_eq!(union.get(union_key("hotels", 0)), Some(10));
+        assert_eq!(union.get(union_key("hotels", 1)), Some(30));
+        assert_eq!(union.get(union_key("paris", 0)), Some(20));
+        assert_eq!(union.get(union_key("paris", 1)), None);
+        assert_eq!(key2npid(&union_key("paris", 1)), ("paris".to_string(), 1));
+    }
+}
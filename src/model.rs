@@ -0,0 +1,217 @@
+/*
+    Learned ranking models over the candidate features (see scorer::FEATURES), described
+    in a JSON file next to config.json ("model_file", "model.json" if not set). A model
+    predicts a distance, the smaller the better, as the cosine distance of the other scorers.
+
+    A linear model, features without a weight are ignored:
+
+        {"type": "linear", "bias": 0.1, "weights": {"keyword": 0.6, "cosine": 0.3}}
+
+    A tree ensemble, the sum of the leaf values of all trees, a node goes left if the
+    feature value is smaller than the threshold:
+
+        {"type": "trees", "bias": 0.0, "trees": [
+            {"feature": "keyword", "threshold": 0.4, "left": {"value": 0.1}, "right": {"value": 0.7}}
+        ]}
+*/
+
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::path::Path;
+
+use scorer::FEATURES;
+
+#[derive(Debug, PartialEq)]
+pub enum Tree {
+    Leaf(f32),
+    Node {
+        feature: usize,
+        threshold: f32,
+        left: Box<Tree>,
+        right: Box<Tree>,
+    },
+}
+
+impl Tree {
+    #[inline]
+    fn predict(&self, features: &[f32]) -> f32 {
+        match *self {
+            Tree::Leaf(value) => value,
+            Tree::Node {
+                feature,
+                threshold,
+                ref left,
+                ref right,
+            } => {
+                if features[feature] < threshold {
+                    left.predict(features)
+                } else {
+                    right.predict(features)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Model {
+    Linear { bias: f32, weights: Vec<f32> },
+    Trees { bias: f32, trees: Vec<Tree> },
+}
+
+#[inline]
+fn invalid_model(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid model: {}", msg))
+}
+
+fn feature_idx(name: &str) -> Result<usize, Error> {
+    match FEATURES.iter().position(|f| *f == name) {
+        Some(idx) => Ok(idx),
+        None => Err(invalid_model(&format!(
+            "unknown feature {}, expected one of: {}",
+            name,
+            FEATURES.join(", ")
+        ))),
+    }
+}
+
+fn as_f32(value: &Value, name: &str) -> Result<f32, Error> {
+    match value.as_f64() {
+        Some(v) => Ok(v as f32),
+        None => Err(invalid_model(&format!("{} is not a number", name))),
+    }
+}
+
+fn parse_tree(node: &Value) -> Result<Tree, Error> {
+    if let Some(value) = node.get("value") {
+        return Ok(Tree::Leaf(as_f32(value, "value")?));
+    }
+
+    let feature = match node.get("feature").and_then(|f| f.as_str()) {
+        Some(feature) => feature_idx(feature)?,
+        None => return Err(invalid_model("a tree node without a feature or a value")),
+    };
+    let threshold = as_f32(node.get("threshold").unwrap_or(&Value::Null), "threshold")?;
+    let (left, right) = match (node.get("left"), node.get("right")) {
+        (Some(left), Some(right)) => (parse_tree(left)?, parse_tree(right)?),
+        _ => return Err(invalid_model("a tree node without both children")),
+    };
+
+    Ok(Tree::Node {
+        feature: feature,
+        threshold: threshold,
+        left: Box::new(left),
+        right: Box::new(right),
+    })
+}
+
+impl Model {
+    pub fn parse(json: &str) -> Result<Model, Error> {
+        let model: Value = match serde_json::from_str(json) {
+            Ok(model) => model,
+            Err(err) => return Err(invalid_model(&format!("{}", err))),
+        };
+        let bias = match model.get("bias") {
+            Some(bias) => as_f32(bias, "bias")?,
+            None => 0.0,
+        };
+
+        match model.get("type").and_then(|t| t.as_str()) {
+            Some("linear") => {
+                let mut weights = vec![0.0; FEATURES.len()];
+                if let Some(model_weights) = model.get("weights").and_then(|w| w.as_object()) {
+                    for (feature, weight) in model_weights.iter() {
+                        weights[feature_idx(feature)?] = as_f32(weight, feature)?;
+                    }
+                }
+
+                Ok(Model::Linear {
+                    bias: bias,
+                    weights: weights,
+                })
+            }
+            Some("trees") => {
+                let mut trees = vec![];
+                if let Some(model_trees) = model.get("trees").and_then(|t| t.as_array()) {
+                    for tree in model_trees.iter() {
+                        trees.push(parse_tree(tree)?);
+                    }
+                }
+
+                Ok(Model::Trees {
+                    bias: bias,
+                    trees: trees,
+                })
+            }
+            _ => Err(invalid_model("type has to be linear or trees")),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Model, Error> {
+        let mut json = String::new();
+        BufReader::new(File::open(path)?).read_to_string(&mut json)?;
+
+        Model::parse(&json)
+    }
+
+    // predicted distance of a candidate with the given features
+    #[inline]
+    pub fn predict(&self, features: &[f32]) -> f32 {
+        match *self {
+            Model::Linear { bias, ref weights } => weights
+                .iter()
+                .zip(features.iter())
+                .fold(bias, |sum, (w, x)| sum + w * x),
+            Model::Trees { bias, ref trees } => {
+                trees.iter().fold(bias, |sum, t| sum + t.predict(features))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(keyword: f32, cosine: f32) -> Vec<f32> {
+        let mut features = vec![0.0; FEATURES.len()];
+        features[feature_idx("keyword").unwrap()] = keyword;
+        features[feature_idx("cosine").unwrap()] = cosine;
+        features
+    }
+
+    #[test]
+    fn test_linear() {
+        let model = Model::parse(
+            r#"{"type": "linear", "bias": 0.1, "weights": {"keyword": 0.5, "cosine": 0.25}}"#,
+        )
+        .unwrap();
+        assert_eq!(model.predict(&features(0.4, 0.8)), 0.1 + 0.2 + 0.2);
+        assert_eq!(model.predict(&features(0.0, 0.0)), 0.1);
+    }
+
+    #[test]
+    fn test_trees() {
+        let model = Model::parse(
+            r#"{"type": "trees", "bias": 0.5, "trees": [
+                {"feature": "keyword", "threshold": 0.4,
+                 "left": {"value": -0.25},
+                 "right": {"feature": "cosine", "threshold": 0.5, "left": {"value": 0.125}, "right": {"value": 0.25}}},
+                {"value": 0.125}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(model.predict(&features(0.2, 0.9)), 0.5 - 0.25 + 0.125);
+        assert_eq!(model.predict(&features(0.6, 0.2)), 0.5 + 0.125 + 0.125);
+        assert_eq!(model.predict(&features(0.6, 0.7)), 0.5 + 0.25 + 0.125);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Model::parse(r#"{"type": "svm"}"#).is_err());
+        assert!(Model::parse(r#"{"type": "linear", "weights": {"clicks": 1.0}}"#).is_err());
+        assert!(Model::parse(r#"{"type": "trees", "trees": [{"feature": "keyword"}]}"#).is_err());
+        assert!(Model::parse("{").is_err());
+    }
+}
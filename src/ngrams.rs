@@ -1,12 +1,23 @@
 use fnv::{FnvHashMap, FnvHashSet};
 
+use analyzer::Analyzer;
+use stopwords::Stopwords;
+use synonyms::{Synonym, Synonyms, MAX_PHRASE_LEN};
+use toponyms::{Policy, Toponyms};
 use util;
 
 use regex::Regex;
 use std::borrow::Cow;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 pub const MISS_WORD_REL: u64 = 6666;
-pub const WORDS_PER_QUERY: usize = 15;
+pub const WORDS_PER_QUERY: usize = 15; // default max number of words of a query used for ngrams
+pub const MAX_COMPLETIONS: usize = 5; // max number of words a prefix is expanded to
+const MAX_PREFIX_SCAN: usize = 100_000; // max number of terms scanned for a prefix
+pub const MAX_FUZZY_EDITS: u32 = 2;
+const MIN_FUZZY_WORD_LEN: usize = 3; // shorter words are never corrected
+const MIN_FUZZY_2_EDITS_LEN: usize = 6; // shorter words are corrected within 1 edit at most
 
 const PUNCT_SYMBOLS: &str = "[/@#!,'?:();.+-_]";
 
@@ -168,13 +179,66 @@ pub fn separate_digits<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
     Cow::Owned(RE_DIG.replace_all(&input.into(), "$b $d $e").to_string())
 }
 
+// Unicode aware counterpart of u8_find_and_replace, runs before an analyzer: applies NFKC
+// (full-width characters, ligatures etc. become their common forms), unifies apostrophes and
+// dashes to ASCII, replaces Unicode whitespace, punctuation and symbols with a space and drops
+// invisible format characters. ASCII punctuation is left to the analyzer.
 #[inline]
-pub fn normalize(query: &str) -> String {
-    separate_digits(u8_normalize_umlauts(
-        u8_find_and_replace(query).to_lowercase(),
-    ))
-    .trim()
-    .to_string()
+pub fn unicode_find_and_replace(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.nfkc() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{02BC}' | '\u{2032}' => output.push('\''),
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => output.push('-'),
+            '\u{00AD}' | '\u{200B}'..='\u{200F}' | '\u{2060}' | '\u{FEFF}' => (),
+            _ if c.is_ascii() || c.is_alphanumeric() || is_combining_mark(c) => output.push(c),
+            _ => output.push(' '),
+        }
+    }
+
+    output
+}
+
+// Case folding beyond lowercasing, for characters with more than one lowercase form
+#[inline]
+pub fn case_fold<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    let input = input.into();
+    if !input
+        .chars()
+        .any(|c| c == 'ß' || c == 'ς' || c == 'ſ' || c.is_uppercase())
+    {
+        return input;
+    }
+
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            'ß' => output.push_str("ss"),
+            'ς' => output.push('σ'),
+            'ſ' => output.push('s'),
+            _ => output.extend(c.to_lowercase()),
+        }
+    }
+
+    Cow::Owned(output)
+}
+
+// Strips general diacritics (combining marks U+0300 - U+036F) of Latin, Greek and Cyrillic
+// letters: é -> e, ñ -> n, ё -> е. Marks of other scripts are kept, they're part of the letter.
+#[inline]
+pub fn strip_diacritics<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    let input = input.into();
+    if input.is_ascii() {
+        return input;
+    }
+
+    Cow::Owned(
+        input
+            .nfd()
+            .filter(|c| *c < '\u{0300}' || *c > '\u{036F}')
+            .nfc()
+            .collect(),
+    )
 }
 
 #[inline]
@@ -210,11 +274,20 @@ fn suffix_words(words: &mut Vec<String>, suffix_letters: &mut Vec<(usize, String
     suffixed_words
 }
 
+// adds an alternative for the word at word_idx, unless it's already there
+#[inline]
+fn add_synonym(synonyms: &mut FnvHashMap<usize, Vec<Synonym>>, word_idx: usize, synonym: Synonym) {
+    let word_synonyms = synonyms.entry(word_idx).or_insert(vec![]);
+    if !word_synonyms.iter().any(|s| s.phrase == synonym.phrase) {
+        word_synonyms.push(synonym);
+    }
+}
+
 #[inline]
 fn suffix_synonyms(
     words: &Vec<String>,
     suffix_letters: &mut Vec<(usize, String)>,
-    synonyms: &mut FnvHashMap<usize, String>,
+    synonyms: &mut FnvHashMap<usize, Vec<Synonym>>,
 ) {
     if suffix_letters.is_empty() {
         return;
@@ -232,7 +305,7 @@ fn suffix_synonyms(
             }
         }
         if word.len() < synonym.len() {
-            synonyms.insert(word_idx, synonym);
+            add_synonym(synonyms, word_idx, Synonym::new(&synonym, 1.0));
         }
     }
 }
@@ -240,34 +313,209 @@ fn suffix_synonyms(
 #[inline]
 fn word_synonyms(
     words: &Vec<String>,
-    synonyms: &mut FnvHashMap<usize, String>,
-    synonyms_dict: &Option<FnvHashMap<String, String>>,
+    synonyms: &mut FnvHashMap<usize, Vec<Synonym>>,
+    synonyms_dict: &Option<Synonyms>,
 ) {
-    let words_set: FnvHashSet<String> = words.iter().map(|w| w.to_string()).collect();
+    let words_set: FnvHashSet<&str> = words.iter().map(|w| w.as_str()).collect();
     if let Some(syn_dict) = synonyms_dict {
-        for (word_idx, word) in words.iter().enumerate() {
-            if !synonyms.contains_key(&word_idx) {
-                if let Some(syn) = syn_dict.get(word) {
-                    if !words_set.contains(syn) {
-                        synonyms.insert(word_idx, syn.to_string());
+        for word_idx in 0..words.len() {
+            // phrases of up to MAX_PHRASE_LEN words starting at word_idx, their alternatives
+            // are attached to the first word of the phrase and span all of its words
+            let max_len = util::min(MAX_PHRASE_LEN, words.len() - word_idx);
+            for phrase_len in 1..max_len + 1 {
+                let phrase = words[word_idx..word_idx + phrase_len].join(" ");
+                for syn in syn_dict.get(&phrase).into_iter() {
+                    // skip alternatives already present in the query
+                    if syn.phrase.split(" ").all(|w| words_set.contains(w)) {
+                        continue;
                     }
+                    add_synonym(
+                        synonyms,
+                        word_idx,
+                        Synonym {
+                            span: phrase_len,
+                            ..syn
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[inline]
+fn correction_synonyms(
+    words: &Vec<String>,
+    synonyms: &mut FnvHashMap<usize, Vec<Synonym>>,
+    corrections: &FnvHashMap<String, String>,
+) {
+    if corrections.is_empty() {
+        return;
+    }
+
+    let words_set: FnvHashSet<&String> = words.iter().collect();
+    for (word_idx, word) in words.iter().enumerate() {
+        if let Some(correction) = corrections.get(word) {
+            if !words_set.contains(correction) {
+                add_synonym(synonyms, word_idx, Synonym::new(correction, 1.0));
+            }
+        }
+    }
+}
+
+// alternative names of the toponyms in the query, attached to the first word of a toponym
+// and spanning all of its words
+#[inline]
+fn toponym_synonyms(
+    words: &Vec<String>,
+    synonyms: &mut FnvHashMap<usize, Vec<Synonym>>,
+    toponyms: &Option<Toponyms>,
+) {
+    if let Some(toponyms) = toponyms {
+        let words_set: FnvHashSet<&str> = words.iter().map(|w| w.as_str()).collect();
+        for (start, end) in toponyms.find(words) {
+            for syn in toponyms.synonyms(&words[start..end].join(" ")) {
+                if syn.phrase.split(" ").all(|w| words_set.contains(w)) {
+                    continue;
                 }
+                add_synonym(
+                    synonyms,
+                    start,
+                    Synonym {
+                        span: end - start,
+                        ..syn
+                    },
+                );
             }
         }
     }
 }
 
+// stems every word of the synonym phrases
+#[inline]
+fn stem_synonyms(
+    synonyms: &FnvHashMap<usize, Vec<Synonym>>,
+    analyzer: &dyn Analyzer,
+) -> FnvHashMap<usize, Vec<Synonym>> {
+    synonyms
+        .iter()
+        .map(|(word_idx, syns)| {
+            let syns = syns
+                .iter()
+                .map(|syn| Synonym {
+                    phrase: syn
+                        .phrase
+                        .split(" ")
+                        .map(|w| analyzer.stem(w))
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                    weight: syn.weight,
+                    span: syn.span,
+                })
+                .collect();
+
+            (*word_idx, syns)
+        })
+        .collect()
+}
+
+#[inline]
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..b.len() + 1).collect();
+    let mut curr: Vec<usize> = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let subst = if ca == *cb { prev[j] } else { prev[j] + 1 };
+            curr[j + 1] = util::min(subst, util::min(prev[j + 1] + 1, curr[j] + 1));
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+// Finds corrections for query words missing from the terms relevance map (words that would
+// get MISS_WORD_REL), within max_edits from the original word. The closest word wins, ties
+// go to the more common word, the one with a lower relevance.
+#[inline]
+pub fn get_corrections(
+    query: &str,
+    analyzer: &dyn Analyzer,
+    tr_map: &fst::Map,
+    stopwords: &Stopwords,
+    max_edits: u32,
+) -> Vec<(String, String)> {
+    use fst::{IntoStreamer, Levenshtein, Streamer};
+
+    let max_edits = util::min(max_edits, MAX_FUZZY_EDITS);
+    if max_edits == 0 {
+        return vec![];
+    }
+
+    let (words, _) = get_norm_query_vec(query, analyzer, &None, ParseMode::Search);
+    let mut seen_words: FnvHashSet<&String> = FnvHashSet::default();
+    let mut corrections: Vec<(String, String)> = vec![];
+    for word in words.iter() {
+        if seen_words.contains(word)
+            || word.chars().count() < MIN_FUZZY_WORD_LEN
+            || word.chars().any(char::is_numeric)
+            || stopwords.contains(word)
+            || tr_map.contains_key(word)
+        {
+            continue;
+        }
+        seen_words.insert(word);
+
+        let edits = if word.chars().count() < MIN_FUZZY_2_EDITS_LEN {
+            1
+        } else {
+            max_edits
+        };
+
+        // no correction if the automaton is too large, e.g. for a long word
+        let lev = match Levenshtein::new(word, edits) {
+            Ok(lev) => lev,
+            Err(_) => continue,
+        };
+
+        let mut best: Option<(usize, u64, String)> = None;
+        let mut stream = tr_map.search(lev).into_stream();
+        while let Some((cand, rel)) = stream.next() {
+            let cand = String::from_utf8_lossy(cand).into_owned();
+            let dist = edit_distance(word, &cand);
+            let is_better = match best {
+                Some((best_dist, best_rel, _)) => (dist, rel) < (best_dist, best_rel),
+                None => true,
+            };
+            if is_better {
+                best = Some((dist, rel, cand));
+            }
+        }
+
+        if let Some((_, _, correction)) = best {
+            corrections.push((word.to_string(), correction));
+        }
+    }
+
+    corrections
+}
+
 #[inline]
 fn get_norm_query_vec(
     query: &str,
-    synonyms_dict: &Option<FnvHashMap<String, String>>,
+    analyzer: &dyn Analyzer,
+    synonyms_dict: &Option<Synonyms>,
     mode: ParseMode,
-) -> (Vec<String>, FnvHashMap<usize, String>) {
+) -> (Vec<String>, FnvHashMap<usize, Vec<Synonym>>) {
     let mut suffix_letters: Vec<(usize, String)> = Vec::with_capacity(WORDS_PER_QUERY - 1);
-    let mut synonyms: FnvHashMap<usize, String> = FnvHashMap::default();
+    let mut synonyms: FnvHashMap<usize, Vec<Synonym>> = FnvHashMap::default();
 
     let mut words_cnt: usize = 0;
-    let mut words = normalize(query)
+    let mut words = analyzer
+        .normalize(query)
         .split(" ")
         .enumerate()
         .filter(|(i, word)| {
@@ -296,7 +544,6 @@ fn get_norm_query_vec(
         })
         .map(|(_, w)| w.to_string())
         .collect::<Vec<String>>();
-    words.truncate(WORDS_PER_QUERY);
 
     if words.is_empty() {
         return (words, synonyms);
@@ -313,17 +560,90 @@ fn get_norm_query_vec(
     (words, synonyms)
 }
 
+// Keeps at most max_words most relevant words of a long query, in their original order.
+// Returns the kept words with their synonyms re-indexed and the dropped words.
+#[inline]
+fn keep_relevant_words(
+    words: Vec<String>,
+    synonyms: FnvHashMap<usize, Vec<Synonym>>,
+    tr_map: &fst::Map,
+    stopwords: &Stopwords,
+    max_words: usize,
+) -> (Vec<String>, FnvHashMap<usize, Vec<Synonym>>, Vec<String>) {
+    let max_words = util::max(max_words, 1);
+    if words.len() <= max_words {
+        return (words, synonyms, vec![]);
+    }
+
+    let mut words_rels: Vec<(usize, f32)> = words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let rel = tr_map.get(word).unwrap_or(MISS_WORD_REL) as f32;
+            if stopwords.contains(word) || word.len() == 1 {
+                (i, 0.5 * rel)
+            } else {
+                (i, rel)
+            }
+        })
+        .collect();
+    // the most relevant first, ties are broken by the position in the query
+    words_rels.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(Ordering::Equal)
+            .then(a.0.cmp(&b.0))
+    });
+    let kept_idx: FnvHashSet<usize> = words_rels[..max_words].iter().map(|(i, _)| *i).collect();
+
+    let mut kept_words: Vec<String> = Vec::with_capacity(max_words);
+    let mut kept_positions: FnvHashMap<usize, usize> = FnvHashMap::default();
+    let mut dropped_words: Vec<String> = Vec::with_capacity(words.len() - max_words);
+    for (i, word) in words.into_iter().enumerate() {
+        if kept_idx.contains(&i) {
+            kept_positions.insert(i, kept_words.len());
+            kept_words.push(word);
+        } else {
+            dropped_words.push(word);
+        }
+    }
+
+    // synonyms move to the first kept word of their span, and span only the kept words
+    let mut synonyms: Vec<(usize, Vec<Synonym>)> = synonyms.into_iter().collect();
+    synonyms.sort_by_key(|(i, _)| *i);
+    let mut kept_synonyms: FnvHashMap<usize, Vec<Synonym>> = FnvHashMap::default();
+    for (i, syns) in synonyms.into_iter() {
+        for syn in syns.into_iter() {
+            let kept_span: Vec<usize> = (i..i + syn.span)
+                .filter_map(|j| kept_positions.get(&j).cloned())
+                .collect();
+            if let Some(first) = kept_span.first() {
+                add_synonym(
+                    &mut kept_synonyms,
+                    *first,
+                    Synonym {
+                        span: kept_span.len(),
+                        ..syn
+                    },
+                );
+            }
+        }
+    }
+
+    (kept_words, kept_synonyms, dropped_words)
+}
+
 #[inline]
 pub fn get_words_relevances(
     query: &str,
+    analyzer: &dyn Analyzer,
     tr_map: &fst::Map,
-    stopwords: &FnvHashSet<String>,
-    synonyms: &FnvHashMap<usize, String>,
-    toponyms: &Option<fst::Set>,
-    synonyms_dict: &Option<FnvHashMap<String, String>>,
+    stopwords: &Stopwords,
+    synonyms: &FnvHashMap<usize, Vec<Synonym>>,
+    toponyms: &Option<Toponyms>,
+    synonyms_dict: &Option<Synonyms>,
     mode: ParseMode,
 ) -> FnvHashMap<String, f32> {
-    let (words, _) = get_norm_query_vec(query, &None, mode);
+    let (words, _) = get_norm_query_vec(query, analyzer, &None, mode);
     let (_, _, relevs, _, _) =
         index_words(&words, tr_map, stopwords, synonyms, toponyms, synonyms_dict);
 
@@ -334,14 +654,38 @@ pub fn get_words_relevances(
         .collect::<FnvHashMap<String, f32>>()
 }
 
+// Toponym words that are must-have words according to the toponyms policy,
+// the most relevant first
+#[inline]
+fn must_have_toponyms(
+    mut toponym_words: Vec<usize>,
+    rels: &Vec<f32>,
+    toponyms: &Option<Toponyms>,
+) -> Vec<usize> {
+    toponym_words.retain(|i| rels[*i] > 0.0);
+    toponym_words.sort_by(|a, b| {
+        rels[*b]
+            .partial_cmp(&rels[*a])
+            .unwrap_or(Ordering::Equal)
+            .then(a.cmp(b))
+    });
+    toponym_words.dedup();
+
+    match toponyms.as_ref().map(|t| t.policy) {
+        Some(Policy::Top) => toponym_words.into_iter().take(1).collect(),
+        Some(Policy::All) => toponym_words,
+        Some(Policy::None) | None => vec![],
+    }
+}
+
 #[inline]
 fn index_words(
     words: &Vec<String>,
     tr_map: &fst::Map,
-    stopwords: &FnvHashSet<String>,
-    synonyms: &FnvHashMap<usize, String>,
-    toponyms: &Option<fst::Set>,
-    synonyms_dict: &Option<FnvHashMap<String, String>>,
+    stopwords: &Stopwords,
+    synonyms: &FnvHashMap<usize, Vec<Synonym>>,
+    toponyms: &Option<Toponyms>,
+    synonyms_dict: &Option<Synonyms>,
 ) -> (
     Vec<usize>,
     Vec<usize>,
@@ -354,7 +698,7 @@ fn index_words(
     let mut stop_vec: Vec<usize> = Vec::with_capacity(words_len);
     let mut numerics: FnvHashSet<usize> = FnvHashSet::default();
     let (mut numeric_rel, mut numeric) = (0.0, words_len);
-    let (mut toponym_rel, mut toponym) = (0.0, words_len);
+    let mut toponym_words: Vec<usize> = vec![];
     let mut word_vec: Vec<usize> = Vec::with_capacity(words_len);
     let mut seen_words: FnvHashSet<String> = FnvHashSet::default();
 
@@ -372,9 +716,14 @@ fn index_words(
             rels.push(rel);
             norm += rel;
         } else {
-            if synonyms.contains_key(&i) {
-                let syn = synonyms.get(&i).unwrap();
-                rel = util::max(tr_map.get(syn).unwrap_or(MISS_WORD_REL) as f32, rel);
+            if let Some(syns) = synonyms.get(&i) {
+                for syn in syns.iter() {
+                    // a phrase is as relevant as its most relevant word
+                    let syn_rel = syn.phrase.split(" ").fold(0.0, |max_rel, w| {
+                        util::max(tr_map.get(w).unwrap_or(MISS_WORD_REL) as f32, max_rel)
+                    });
+                    rel = util::max(syn.weight * syn_rel, rel);
+                }
             }
 
             if !seen_words.contains(word) {
@@ -389,13 +738,6 @@ fn index_words(
                 }
             }
 
-            if let Some(toponyms) = toponyms {
-                if toponyms.contains(word) && rel > toponym_rel {
-                    toponym_rel = rel;
-                    toponym = i;
-                }
-            }
-
             rels.push(rel);
             word_vec.push(i);
         }
@@ -412,19 +754,31 @@ fn index_words(
         // record seen words
         seen_words.insert(word.to_string());
         if let Some(syn_dict) = synonyms_dict {
-            if let Some(syn) = syn_dict.get(word) {
-                seen_words.insert(syn.to_string());
-
+            for syn in syn_dict.get(word).into_iter() {
                 if let Some(toponyms) = toponyms {
-                    if toponyms.contains(syn) && rel > toponym_rel {
-                        toponym_rel = rel;
-                        toponym = i;
+                    if toponyms.contains(&syn.phrase) {
+                        toponym_words.push(i);
                     }
                 }
+
+                seen_words.insert(syn.phrase);
             }
         }
     }
 
+    if let Some(toponyms) = toponyms {
+        for (start, end) in toponyms.find(words) {
+            // the most relevant word of a multi word toponym stands for the toponym
+            let word = (start..end)
+                .filter(|i| !stop_vec.contains(i))
+                .max_by(|a, b| rels[*a].partial_cmp(&rels[*b]).unwrap_or(Ordering::Equal));
+            if let Some(i) = word {
+                toponym_words.push(i);
+            }
+        }
+    }
+    let toponym_words = must_have_toponyms(toponym_words, &rels, toponyms);
+
     let rels: Vec<f32> = rels.into_iter().map(|r| r / norm).collect();
 
     if stop_vec.is_empty() && words_len > 3 && rels[min_word_idx] < stop_word_thresh {
@@ -438,13 +792,72 @@ fn index_words(
         must_have.push(numeric)
     }
 
-    if toponym < words_len && toponym != numeric {
-        must_have.push(toponym)
+    for toponym in toponym_words.into_iter() {
+        if toponym != numeric {
+            must_have.push(toponym)
+        }
     }
 
     (word_vec, stop_vec, rels, must_have, numerics)
 }
 
+// Ngram of the words at the indices with the words in the span of a synonym, attached to the
+// word at syn_idx, replaced by the synonym phrase. Returns the ngram and the indices of the
+// words it stands for.
+#[inline]
+fn synonym_ngram(
+    words: &Vec<String>,
+    indices: &Vec<usize>,
+    syn_idx: usize,
+    syn: &Synonym,
+) -> (String, Vec<usize>) {
+    let mut parts: Vec<&str> = vec![&syn.phrase];
+    let mut word_indices = indices.clone();
+    for i in indices.iter() {
+        if *i < syn_idx || *i >= syn_idx + syn.span {
+            parts.push(&words[*i]);
+        }
+    }
+    for i in syn_idx..syn_idx + syn.span {
+        if !word_indices.contains(&i) {
+            word_indices.push(i);
+        }
+    }
+
+    let ngram = match parts.len() {
+        1 => parts[0].to_string(),
+        2 => bow2(parts[0], parts[1]),
+        _ => bow3(parts[0], parts[1], parts[2]),
+    };
+
+    (ngram, word_indices)
+}
+
+// Pairs a synonym ngram of a stop ngram with another stop ngram. The other ngram is left out
+// if it's a part of the synonym span, there is no pair if only some of its words are.
+#[inline]
+fn pair_synonym_ngram(
+    stop_ngram: &StopNgram,
+    syn_ngram: &StopNgram,
+    other: &StopNgram,
+) -> Option<(String, Vec<usize>)> {
+    let spanned = other
+        .word_indices
+        .iter()
+        .filter(|i| syn_ngram.word_indices.contains(i) && !stop_ngram.word_indices.contains(i))
+        .count();
+
+    if spanned == 0 {
+        let mut word_indices = syn_ngram.word_indices.clone();
+        word_indices.extend(other.word_indices.clone());
+        Some((bow2(&syn_ngram.ngram, &other.ngram), word_indices))
+    } else if spanned == other.len() {
+        Some((syn_ngram.ngram.clone(), syn_ngram.word_indices.clone()))
+    } else {
+        None
+    }
+}
+
 #[derive(Debug)]
 pub struct StopNgram {
     pub ngram: String,
@@ -501,42 +914,33 @@ impl StopNgrams {
         words: &Vec<String>,
         rels: &Vec<f32>,
         indices: Vec<usize>,
-        synonyms: &FnvHashMap<usize, String>,
+        synonyms: &FnvHashMap<usize, Vec<Synonym>>,
     ) {
         let ngram_idx = self.ngrams.len();
 
-        if indices.len() == 3 {
-            let (i, j, k) = (indices[0], indices[1], indices[2]);
-
-            if self.mode == ParseMode::Search {
-                if let Some(syn) = synonyms.get(&i) {
-                    let ngram_syns = self.synonyms.entry(ngram_idx).or_insert(vec![]);
-                    ngram_syns.push(StopNgram::new(
-                        bow3(&syn, &words[j], &words[k]),
-                        rels[i] + rels[j] + rels[k],
-                        indices.clone(),
-                    ));
-                };
-
-                if let Some(syn) = synonyms.get(&j) {
-                    let ngram_syns = self.synonyms.entry(ngram_idx).or_insert(vec![]);
-                    ngram_syns.push(StopNgram::new(
-                        bow3(&words[i], &syn, &words[k]),
-                        rels[i] + rels[j] + rels[k],
-                        indices.clone(),
-                    ));
-                };
-
-                if let Some(syn) = synonyms.get(&k) {
-                    let ngram_syns = self.synonyms.entry(ngram_idx).or_insert(vec![]);
-                    ngram_syns.push(StopNgram::new(
-                        bow3(&words[i], &words[j], &syn),
-                        rels[i] + rels[j] + rels[k],
-                        indices.clone(),
-                    ));
-                };
+        if self.mode == ParseMode::Search {
+            for idx in indices.iter() {
+                if let Some(syns) = synonyms.get(idx) {
+                    for syn in syns.iter() {
+                        // words in the span of the synonym are weighted by it
+                        let relev = indices.iter().fold(0.0, |relev, i| {
+                            if *i >= *idx && *i < *idx + syn.span {
+                                relev + syn.weight * rels[*i]
+                            } else {
+                                relev + rels[*i]
+                            }
+                        });
+                        let (ngram, word_indices) = synonym_ngram(words, &indices, *idx, syn);
+
+                        let ngram_syns = self.synonyms.entry(ngram_idx).or_insert(vec![]);
+                        ngram_syns.push(StopNgram::new(ngram, relev, word_indices));
+                    }
+                }
             }
+        }
 
+        if indices.len() == 3 {
+            let (i, j, k) = (indices[0], indices[1], indices[2]);
             self.ngrams.push(StopNgram::new(
                 bow3(&words[i], &words[j], &words[k]),
                 rels[i] + rels[j] + rels[k],
@@ -544,27 +948,6 @@ impl StopNgrams {
             ));
         } else if indices.len() == 2 {
             let (i, j) = (indices[0], indices[1]);
-
-            if self.mode == ParseMode::Search {
-                if let Some(syn) = synonyms.get(&i) {
-                    let ngram_syns = self.synonyms.entry(ngram_idx).or_insert(vec![]);
-                    ngram_syns.push(StopNgram::new(
-                        bow2(&syn, &words[j]),
-                        rels[i] + rels[j],
-                        indices.clone(),
-                    ));
-                };
-
-                if let Some(syn) = synonyms.get(&j) {
-                    let ngram_syns = self.synonyms.entry(ngram_idx).or_insert(vec![]);
-                    ngram_syns.push(StopNgram::new(
-                        bow2(&words[i], &syn),
-                        rels[i] + rels[j],
-                        indices.clone(),
-                    ));
-                };
-            };
-
             self.ngrams.push(StopNgram::new(
                 bow2(&words[i], &words[j]),
                 rels[i] + rels[j],
@@ -572,14 +955,6 @@ impl StopNgrams {
             ));
         } else if indices.len() == 1 {
             let i = indices[0];
-
-            if self.mode == ParseMode::Search {
-                if let Some(syn) = synonyms.get(&i) {
-                    let ngram_syns = self.synonyms.entry(ngram_idx).or_insert(vec![]);
-                    ngram_syns.push(StopNgram::new(syn.to_string(), rels[i], indices.clone()));
-                };
-            };
-
             self.ngrams
                 .push(StopNgram::new(words[i].to_string(), rels[i], indices));
         }
@@ -597,7 +972,7 @@ pub fn get_stop_ngrams(
     rels: &Vec<f32>,
     word_idx: &mut Vec<usize>,
     stop_idx: &Vec<usize>,
-    synonyms: &FnvHashMap<usize, String>,
+    synonyms: &FnvHashMap<usize, Vec<Synonym>>,
     mode: ParseMode,
 ) -> StopNgrams {
     let words_len = words.len();
@@ -815,10 +1190,12 @@ fn find_must_have_words(
 #[inline]
 pub fn parse(
     query: &str,
-    synonyms_dict: &Option<FnvHashMap<String, String>>,
-    toponyms: &Option<fst::Set>,
-    stopwords: &FnvHashSet<String>,
+    analyzer: &dyn Analyzer,
+    synonyms_dict: &Option<Synonyms>,
+    toponyms: &Option<Toponyms>,
+    stopwords: &Stopwords,
     tr_map: &fst::Map,
+    words_per_query: usize,
     mode: ParseMode,
 ) -> (
     Vec<String>,
@@ -827,13 +1204,56 @@ pub fn parse(
     Vec<String>,
     Vec<f32>,
     Vec<usize>,
-    FnvHashMap<usize, String>,
+    FnvHashMap<usize, Vec<Synonym>>,
+    Vec<String>,
+) {
+    parse_with_corrections(
+        query,
+        analyzer,
+        synonyms_dict,
+        toponyms,
+        stopwords,
+        tr_map,
+        &FnvHashMap::default(),
+        words_per_query,
+        mode,
+    )
+}
+
+// Same as parse, misspelled query words found in corrections get their corrections as synonyms
+#[inline]
+pub fn parse_with_corrections(
+    query: &str,
+    analyzer: &dyn Analyzer,
+    synonyms_dict: &Option<Synonyms>,
+    toponyms: &Option<Toponyms>,
+    stopwords: &Stopwords,
+    tr_map: &fst::Map,
+    corrections: &FnvHashMap<String, String>,
+    words_per_query: usize,
+    mode: ParseMode,
+) -> (
+    Vec<String>,
+    Vec<f32>,
+    FnvHashMap<String, Vec<usize>>,
+    Vec<String>,
+    Vec<f32>,
+    Vec<usize>,
+    FnvHashMap<usize, Vec<Synonym>>,
+    Vec<String>,
 ) {
     let mut ngrams_relevs: Vec<f32> = Vec::with_capacity(WORDS_PER_QUERY * 3);
     let mut ngrams: Vec<String> = Vec::with_capacity(WORDS_PER_QUERY * 3);
     let mut ngrams_ids: FnvHashMap<String, Vec<usize>> = FnvHashMap::default();
 
-    let (words, synonyms) = get_norm_query_vec(query, synonyms_dict, mode);
+    let (words, mut synonyms) = get_norm_query_vec(query, analyzer, synonyms_dict, mode);
+    if mode == ParseMode::Search {
+        correction_synonyms(&words, &mut synonyms, corrections);
+        toponym_synonyms(&words, &mut synonyms, toponyms);
+    }
+    let (words, synonyms, dropped_words) =
+        keep_relevant_words(words, synonyms, tr_map, stopwords, words_per_query);
+
     if words.is_empty() {
         return (
             ngrams,
@@ -843,16 +1263,21 @@ pub fn parse(
             vec![],
             vec![],
             FnvHashMap::default(),
+            dropped_words,
         );
     }
 
+    // ngrams are built from stems, relevances are of the original words
+    let stems: Vec<String> = words.iter().map(|w| analyzer.stem(w)).collect();
+    let stem_synonyms = stem_synonyms(&synonyms, analyzer);
+
     let words_len = words.len();
     if words_len == 1 {
         update(
             &mut ngrams,
             &mut ngrams_relevs,
             &mut ngrams_ids,
-            words[0].clone(),
+            stems[0].clone(),
             1.0,
             vec![0],
         );
@@ -864,6 +1289,7 @@ pub fn parse(
             vec![1.0],
             vec![0],
             synonyms,
+            dropped_words,
         );
     }
 
@@ -877,18 +1303,18 @@ pub fn parse(
     );
 
     let stop_ngrams = get_stop_ngrams(
-        &words,
+        &stems,
         &words_relevs,
         &mut word_idx,
         &stop_idx,
-        &synonyms,
+        &stem_synonyms,
         mode,
     );
 
     let stop_ngrams_len = stop_ngrams.len();
     let word_thresh = 1.0 / util::max(2.0, words_len as f32 - 1.0);
 
-    let mut words_vec = words
+    let mut words_vec = stems
         .iter()
         .enumerate()
         .zip(words_relevs.iter())
@@ -951,12 +1377,14 @@ pub fn parse(
 
                 if let Some(syn_ngrams) = stop_ngrams.synonyms.get(&i) {
                     for syn_ngram in syn_ngrams.iter() {
-                        let ngram = bow2(&syn_ngram.ngram, &stop_ngrams[j].ngram);
+                        let (ngram, ngram_ids_vec) =
+                            match pair_synonym_ngram(&stop_ngrams[i], syn_ngram, &stop_ngrams[j]) {
+                                Some(pair) => pair,
+                                None => continue,
+                            };
                         if ngrams_ids.contains_key(&ngram) {
                             continue;
                         }
-                        let mut ngram_ids_vec = syn_ngram.word_indices.clone();
-                        ngram_ids_vec.extend(stop_ngrams[j].word_indices.clone());
 
                         update(
                             &mut ngrams,
@@ -971,12 +1399,14 @@ pub fn parse(
 
                 if let Some(syn_ngrams) = stop_ngrams.synonyms.get(&j) {
                     for syn_ngram in syn_ngrams.iter() {
-                        let ngram = bow2(&stop_ngrams[i].ngram, &syn_ngram.ngram);
+                        let (ngram, ngram_ids_vec) =
+                            match pair_synonym_ngram(&stop_ngrams[j], syn_ngram, &stop_ngrams[i]) {
+                                Some(pair) => pair,
+                                None => continue,
+                            };
                         if ngrams_ids.contains_key(&ngram) {
                             continue;
                         }
-                        let mut ngram_ids_vec = syn_ngram.word_indices.clone();
-                        ngram_ids_vec.extend(stop_ngrams[i].word_indices.clone());
 
                         update(
                             &mut ngrams,
@@ -1005,15 +1435,19 @@ pub fn parse(
 
         // unigram synonyms
         if mode == ParseMode::Search {
-            if let Some(syn) = synonyms.get(&words_vec[0].0) {
-                update(
-                    &mut ngrams,
-                    &mut ngrams_relevs,
-                    &mut ngrams_ids,
-                    syn.clone(),
-                    words_vec[0].2,
-                    vec![words_vec[0].0],
-                );
+            if let Some(syns) = stem_synonyms.get(&words_vec[0].0) {
+                for syn in syns.iter() {
+                    let (ngram, ngram_ids) =
+                        synonym_ngram(&stems, &vec![words_vec[0].0], words_vec[0].0, syn);
+                    update(
+                        &mut ngrams,
+                        &mut ngrams_relevs,
+                        &mut ngrams_ids,
+                        ngram,
+                        words_vec[0].2,
+                        ngram_ids,
+                    );
+                }
             };
         };
     }
@@ -1031,15 +1465,19 @@ pub fn parse(
 
         // unigram synonyms
         if mode == ParseMode::Search {
-            if let Some(syn) = synonyms.get(&words_vec[1].0) {
-                update(
-                    &mut ngrams,
-                    &mut ngrams_relevs,
-                    &mut ngrams_ids,
-                    syn.clone(),
-                    words_vec[1].2,
-                    vec![words_vec[1].0],
-                );
+            if let Some(syns) = stem_synonyms.get(&words_vec[1].0) {
+                for syn in syns.iter() {
+                    let (ngram, ngram_ids) =
+                        synonym_ngram(&stems, &vec![words_vec[1].0], words_vec[1].0, syn);
+                    update(
+                        &mut ngrams,
+                        &mut ngrams_relevs,
+                        &mut ngrams_ids,
+                        ngram,
+                        words_vec[1].2,
+                        ngram_ids,
+                    );
+                }
             };
         };
     }
@@ -1052,19 +1490,23 @@ pub fn parse(
             &mut ngrams,
             &mut ngrams_relevs,
             &mut ngrams_ids,
-            words[must_word_idx].clone(),
+            stems[must_word_idx].clone(),
             words_relevs[must_word_idx],
             vec![must_word_idx],
         );
-        if let Some(syn) = synonyms.get(&must_word_idx) {
-            update(
-                &mut ngrams,
-                &mut ngrams_relevs,
-                &mut ngrams_ids,
-                syn.to_string(),
-                words_relevs[must_word_idx],
-                vec![must_word_idx],
-            );
+        if let Some(syns) = stem_synonyms.get(&must_word_idx) {
+            for syn in syns.iter() {
+                let (ngram, ngram_ids) =
+                    synonym_ngram(&stems, &vec![must_word_idx], must_word_idx, syn);
+                update(
+                    &mut ngrams,
+                    &mut ngrams_relevs,
+                    &mut ngrams_ids,
+                    ngram,
+                    words_relevs[must_word_idx],
+                    ngram_ids,
+                );
+            }
         };
     }
 
@@ -1086,15 +1528,23 @@ pub fn parse(
 
         // add (syn, w1, w2)
         if mode == ParseMode::Search {
-            if let Some(syn) = synonyms.get(&words_vec[0].0) {
-                update(
-                    &mut ngrams,
-                    &mut ngrams_relevs,
-                    &mut ngrams_ids,
-                    bow3(&syn, &words_vec[1].1.clone(), &words_vec[2].1.clone()),
-                    words_vec[0].2 + words_vec[1].2 + words_vec[2].2,
-                    vec![words_vec[0].0, words_vec[1].0, words_vec[2].0],
-                );
+            if let Some(syns) = stem_synonyms.get(&words_vec[0].0) {
+                for syn in syns.iter() {
+                    let (ngram, ngram_ids) = synonym_ngram(
+                        &stems,
+                        &vec![words_vec[0].0, words_vec[1].0, words_vec[2].0],
+                        words_vec[0].0,
+                        syn,
+                    );
+                    update(
+                        &mut ngrams,
+                        &mut ngrams_relevs,
+                        &mut ngrams_ids,
+                        ngram,
+                        words_vec[0].2 + words_vec[1].2 + words_vec[2].2,
+                        ngram_ids,
+                    );
+                }
             };
         };
 
@@ -1113,15 +1563,23 @@ pub fn parse(
 
                 // add (syn_w0, last)
                 if mode == ParseMode::Search {
-                    if let Some(syn) = synonyms.get(&words_vec[0].0) {
-                        update(
-                            &mut ngrams,
-                            &mut ngrams_relevs,
-                            &mut ngrams_ids,
-                            bow2(syn, &last.1.clone()),
-                            words_vec[0].2 + last.2,
-                            vec![words_vec[0].0, last.0],
-                        );
+                    if let Some(syns) = stem_synonyms.get(&words_vec[0].0) {
+                        for syn in syns.iter() {
+                            let (ngram, ngram_ids) = synonym_ngram(
+                                &stems,
+                                &vec![words_vec[0].0, last.0],
+                                words_vec[0].0,
+                                syn,
+                            );
+                            update(
+                                &mut ngrams,
+                                &mut ngrams_relevs,
+                                &mut ngrams_ids,
+                                ngram,
+                                words_vec[0].2 + last.2,
+                                ngram_ids,
+                            );
+                        }
                     };
                 };
             } else {
@@ -1144,23 +1602,37 @@ pub fn parse(
 
                 // add (syn_w1, last), (syn_w1, w2)
                 if mode == ParseMode::Search {
-                    if let Some(syn) = synonyms.get(&words_vec[1].0) {
-                        update(
-                            &mut ngrams,
-                            &mut ngrams_relevs,
-                            &mut ngrams_ids,
-                            bow2(syn, &last.1.clone()),
-                            words_vec[1].2 + last.2,
-                            vec![words_vec[1].0, last.0],
-                        );
-                        update(
-                            &mut ngrams,
-                            &mut ngrams_relevs,
-                            &mut ngrams_ids,
-                            bow2(syn, &words_vec[2].1),
-                            words_vec[1].2 + words_vec[2].2,
-                            vec![words_vec[1].0, words_vec[2].0],
-                        );
+                    if let Some(syns) = stem_synonyms.get(&words_vec[1].0) {
+                        for syn in syns.iter() {
+                            let (ngram, ngram_ids) = synonym_ngram(
+                                &stems,
+                                &vec![words_vec[1].0, last.0],
+                                words_vec[1].0,
+                                syn,
+                            );
+                            update(
+                                &mut ngrams,
+                                &mut ngrams_relevs,
+                                &mut ngrams_ids,
+                                ngram,
+                                words_vec[1].2 + last.2,
+                                ngram_ids,
+                            );
+                            let (ngram, ngram_ids) = synonym_ngram(
+                                &stems,
+                                &vec![words_vec[1].0, words_vec[2].0],
+                                words_vec[1].0,
+                                syn,
+                            );
+                            update(
+                                &mut ngrams,
+                                &mut ngrams_relevs,
+                                &mut ngrams_ids,
+                                ngram,
+                                words_vec[1].2 + words_vec[2].2,
+                                ngram_ids,
+                            );
+                        }
                     };
                 };
             }
@@ -1180,15 +1652,23 @@ pub fn parse(
 
         // add (syn_w0, w1)
         if mode == ParseMode::Search {
-            if let Some(syn) = synonyms.get(&words_vec[0].0) {
-                update(
-                    &mut ngrams,
-                    &mut ngrams_relevs,
-                    &mut ngrams_ids,
-                    bow2(syn, &words_vec[1].1.clone()),
-                    words_vec[0].2 + words_vec[1].2,
-                    vec![words_vec[0].0, words_vec[1].0],
-                );
+            if let Some(syns) = stem_synonyms.get(&words_vec[0].0) {
+                for syn in syns.iter() {
+                    let (ngram, ngram_ids) = synonym_ngram(
+                        &stems,
+                        &vec![words_vec[0].0, words_vec[1].0],
+                        words_vec[0].0,
+                        syn,
+                    );
+                    update(
+                        &mut ngrams,
+                        &mut ngrams_relevs,
+                        &mut ngrams_ids,
+                        ngram,
+                        words_vec[0].2 + words_vec[1].2,
+                        ngram_ids,
+                    );
+                }
             };
         };
     }
@@ -1206,15 +1686,23 @@ pub fn parse(
 
         // add (syn_0, w2)
         if mode == ParseMode::Search {
-            if let Some(syn) = synonyms.get(&words_vec[0].0) {
-                update(
-                    &mut ngrams,
-                    &mut ngrams_relevs,
-                    &mut ngrams_ids,
-                    bow2(syn, &words_vec[2].1.clone()),
-                    words_vec[0].2 + words_vec[2].2,
-                    vec![words_vec[0].0, words_vec[2].0],
-                );
+            if let Some(syns) = stem_synonyms.get(&words_vec[0].0) {
+                for syn in syns.iter() {
+                    let (ngram, ngram_ids) = synonym_ngram(
+                        &stems,
+                        &vec![words_vec[0].0, words_vec[2].0],
+                        words_vec[0].0,
+                        syn,
+                    );
+                    update(
+                        &mut ngrams,
+                        &mut ngrams_relevs,
+                        &mut ngrams_ids,
+                        ngram,
+                        words_vec[0].2 + words_vec[2].2,
+                        ngram_ids,
+                    );
+                }
             };
         };
     }
@@ -1227,16 +1715,20 @@ pub fn parse(
         words_relevs,
         must_have,
         synonyms,
+        dropped_words,
     )
 }
 
 #[inline]
 pub fn match_queries(
     cand_query: &str,
+    analyzer: &dyn Analyzer,
     words_set: &FnvHashSet<String>,
     cand_synonyms: &FnvHashMap<String, String>,
 ) -> (Vec<String>, Vec<String>, Vec<String>, Vec<String>) {
-    let cand_words = normalize(cand_query)
+    // cand_synonyms: synonym (word or phrase) -> query words it stands for, space separated
+    let cand_words = analyzer
+        .normalize(cand_query)
         .clone()
         .split(" ")
         .filter(|w| w.len() > 1 || (w.len() == 1 && w.chars().next().unwrap().is_digit(10)))
@@ -1249,10 +1741,36 @@ pub fn match_queries(
         .map(|w| w.to_string())
         .collect::<FnvHashSet<String>>();
 
-    for (cand_word, syn) in cand_synonyms {
-        if cand_words_set.contains(cand_word) && !match_words.contains(syn) {
-            match_words.insert(syn.to_string());
-            cand_words_set.remove(cand_word);
+    for (cand_phrase, syn) in cand_synonyms {
+        // a phrase matches only if all of its words are in the candidate
+        if !syn.split(" ").all(|w| match_words.contains(w))
+            && cand_phrase.split(" ").all(|w| cand_words_set.contains(w))
+        {
+            match_words.extend(syn.split(" ").map(|w| w.to_string()));
+            for cand_word in cand_phrase.split(" ") {
+                cand_words_set.remove(cand_word);
+            }
+        }
+    }
+
+    // words with the same stem match, e.g. changing and change
+    let stem_words: FnvHashMap<String, &String> = words_set
+        .iter()
+        .filter(|w| !match_words.contains(*w))
+        .map(|w| (analyzer.stem(w), w))
+        .collect();
+    if !stem_words.is_empty() {
+        for cand_word in cand_words.iter() {
+            if !cand_words_set.contains(cand_word) || match_words.contains(cand_word) {
+                continue;
+            }
+
+            if let Some(word) = stem_words.get(&analyzer.stem(cand_word)) {
+                if !match_words.contains(*word) {
+                    match_words.insert(word.to_string());
+                    cand_words_set.remove(cand_word);
+                }
+            }
         }
     }
 
@@ -1274,11 +1792,70 @@ pub fn match_queries(
     )
 }
 
+// Splits a partially typed query into its complete part and the prefix of the last word,
+// the prefix is empty if the last word is complete (the query ends with a whitespace).
+#[inline]
+pub fn split_prefix(query: &str, analyzer: &dyn Analyzer) -> (String, String) {
+    let norm_query = analyzer.normalize(query);
+    if query.ends_with(char::is_whitespace) {
+        return (norm_query, String::from(""));
+    }
+
+    match norm_query.rfind(" ") {
+        Some(i) => (
+            norm_query[..i].trim().to_string(),
+            norm_query[i + 1..].to_string(),
+        ),
+        None => (String::from(""), norm_query),
+    }
+}
+
+// Expands a word prefix to at most max_completions words from the terms relevance map,
+// the most common words (the ones with the lowest relevance) first.
+#[inline]
+pub fn complete_prefix(prefix: &str, tr_map: &fst::Map, max_completions: usize) -> Vec<String> {
+    use fst::{IntoStreamer, Streamer};
+
+    if prefix.is_empty() || max_completions == 0 {
+        return vec![];
+    }
+
+    // the upper bound is the prefix with the last byte incremented, e.g. 'chan' -> 'chao'
+    let mut upper = prefix.as_bytes().to_vec();
+    while let Some(b) = upper.pop() {
+        if b < u8::max_value() {
+            upper.push(b + 1);
+            break;
+        }
+    }
+
+    let range = if upper.is_empty() {
+        tr_map.range().ge(prefix)
+    } else {
+        tr_map.range().ge(prefix).lt(&upper)
+    };
+
+    let mut completions: Vec<(String, u64)> = vec![];
+    let mut stream = range.into_stream();
+    while let Some((word, rel)) = stream.next() {
+        completions.push((String::from_utf8_lossy(word).into_owned(), rel));
+        if completions.len() >= MAX_PREFIX_SCAN {
+            break;
+        }
+    }
+    completions.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+    completions.truncate(max_completions);
+
+    completions.into_iter().map(|(w, _)| w).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use analyzer::{DefaultAnalyzer, StemmingAnalyzer};
     use fst::Map;
     use std::path::PathBuf;
+    use stemmer;
     use stopwords;
     use synonyms;
     use toponyms;
@@ -1288,7 +1865,7 @@ mod tests {
     fn test_suffix_words() {
         let q = "@xel en e x";
         let e = vec!["xelenex"];
-        let (words, _) = get_norm_query_vec(q, &None, ParseMode::Search);
+        let (words, _) = get_norm_query_vec(q, &DefaultAnalyzer, &None, ParseMode::Search);
         assert_eq!(words, e);
     }
 
@@ -1296,65 +1873,65 @@ mod tests {
     fn test_u8_find_and_replace() {
         let q = "'Here's@#An ##example!";
         let e = "heres  an   example";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "'Here's@#another one with some question?## and a comma, and (parenthesis)!";
         let e = "heres  another one with some question   and a comma and  parenthesis";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "München Gödel Gießen Bären";
         let e = "muenchen goedel giessen baeren";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "root_type hubspot";
         let e = "root type hubspot";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
     }
 
     #[test]
     fn test_separate_digits() {
         let q = "123movies123free";
         let e = "123 movies 123 free";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "ormlite h2";
         let e = "ormlite h2";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "peer2peer";
         let e = "peer2peer";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "peer22peer";
         let e = "peer 22 peer";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "friends s01 e01 stream";
         let e = "friends s01 e01 stream";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "laptop-ersatzteile24";
         let e = "laptop ersatzteile 24";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "ersatzteile24 laptop";
         let e = "ersatzteile 24  laptop";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "neumeyer str. 22 24nürnberg";
         let e = "neumeyer str   22   24 nuernberg";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
 
         let q = "neumeyer str. 22-24nürnberg";
         let e = "neumeyer str   22   24 nuernberg";
-        assert_eq!(normalize(q), e);
+        assert_eq!(DefaultAnalyzer.normalize(q), e);
     }
 
     #[test]
     fn test_get_norm_query_vec() {
         let q = "ruby date and time as string";
         let e = vec!["ruby", "date", "and", "time", "as", "string"];
-        let (words, _) = get_norm_query_vec(q, &None, ParseMode::Search);
+        let (words, _) = get_norm_query_vec(q, &DefaultAnalyzer, &None, ParseMode::Search);
         assert_eq!(words, e);
 
         let q = "sim karte defekt t mobile iphone";
@@ -1362,16 +1939,17 @@ mod tests {
         // TODO fix defektt -> tmobile
         let e_synonyms = vec![(2, "defektt")]
             .into_iter()
-            .map(|(i, s)| (i, s.to_string()))
-            .collect::<FnvHashMap<usize, String>>();
-        let (words, synonyms) = get_norm_query_vec(q, &None, ParseMode::Search);
+            .map(|(i, s)| (i, vec![Synonym::new(s, 1.0)]))
+            .collect::<FnvHashMap<usize, Vec<Synonym>>>();
+        let (words, synonyms) = get_norm_query_vec(q, &DefaultAnalyzer, &None, ParseMode::Search);
         assert_eq!(words, e_words);
         assert_eq!(synonyms, e_synonyms);
 
         let q = "sim karte defekt t mobile iphone";
         let e_words = vec!["sim", "karte", "defekt", "mobile", "iphone"];
-        let e_suffix_letters: FnvHashMap<usize, String> = FnvHashMap::default();
-        let (words, suffix_letters) = get_norm_query_vec(q, &None, ParseMode::Index);
+        let e_suffix_letters: FnvHashMap<usize, Vec<Synonym>> = FnvHashMap::default();
+        let (words, suffix_letters) =
+            get_norm_query_vec(q, &DefaultAnalyzer, &None, ParseMode::Index);
         assert_eq!(words, e_words);
         assert_eq!(suffix_letters, e_suffix_letters);
 
@@ -1379,32 +1957,32 @@ mod tests {
         let e_words = vec!["caddy", "14", "ersatzteile"];
         let e_synonyms = vec![(1, "14d")]
             .into_iter()
-            .map(|(i, s)| (i, s.to_string()))
-            .collect::<FnvHashMap<usize, String>>();
-        let (words, synonyms) = get_norm_query_vec(q, &None, ParseMode::Search);
+            .map(|(i, s)| (i, vec![Synonym::new(s, 1.0)]))
+            .collect::<FnvHashMap<usize, Vec<Synonym>>>();
+        let (words, synonyms) = get_norm_query_vec(q, &DefaultAnalyzer, &None, ParseMode::Search);
         assert_eq!(words, e_words);
         assert_eq!(synonyms, e_synonyms);
 
         let q = "caddy14 d ersatzteile";
         let e_words = vec!["caddy", "14", "ersatzteile"];
-        let e_synonyms: FnvHashMap<usize, String> = FnvHashMap::default();
-        let (words, synonyms) = get_norm_query_vec(q, &None, ParseMode::Index);
+        let e_synonyms: FnvHashMap<usize, Vec<Synonym>> = FnvHashMap::default();
+        let (words, synonyms) = get_norm_query_vec(q, &DefaultAnalyzer, &None, ParseMode::Index);
         assert_eq!(words, e_words);
         assert_eq!(synonyms, e_synonyms);
 
         let q = "r sim 7 free mobile iphone 5";
         let e = vec!["r", "sim", "7", "free", "mobile", "iphone", "5"];
-        let (words, _) = get_norm_query_vec(q, &None, ParseMode::Search);
+        let (words, _) = get_norm_query_vec(q, &DefaultAnalyzer, &None, ParseMode::Search);
         assert_eq!(words, e);
     }
 
     fn get_stop_ngrams_test(
         query: &str,
         tr_map: &Map,
-        stopwords: &FnvHashSet<String>,
+        stopwords: &Stopwords,
         mode: ParseMode,
     ) -> Vec<String> {
-        let (words, _) = get_norm_query_vec(query, &None, mode);
+        let (words, _) = get_norm_query_vec(query, &DefaultAnalyzer, &None, mode);
 
         if words.is_empty() {
             return vec![];
@@ -1414,7 +1992,7 @@ mod tests {
             return words;
         }
 
-        let synonyms: FnvHashMap<usize, String> = FnvHashMap::default();
+        let synonyms: FnvHashMap<usize, Vec<Synonym>> = FnvHashMap::default();
         let (mut word_idx, stop_idx, rels, _, _) =
             index_words(&words, tr_map, stopwords, &synonyms, &None, &None);
 
@@ -1621,19 +2199,167 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_word_synonyms() {
+        let syn_dict = vec![
+            (
+                "nyc",
+                vec![
+                    Synonym::new("new york", 0.9),
+                    Synonym::new("manhattan", 0.5),
+                ],
+            ),
+            ("new york", vec![Synonym::new("nyc", 1.0)]),
+        ]
+        .into_iter()
+        .map(|(p, s)| (p.to_string(), s))
+        .collect::<FnvHashMap<String, Vec<Synonym>>>();
+        let syn_dict = Some(Synonyms::from_dict(syn_dict));
+
+        let (words, synonyms) =
+            get_norm_query_vec("hotels nyc", &DefaultAnalyzer, &syn_dict, ParseMode::Search);
+        assert_eq!(words, vec!["hotels", "nyc"]);
+        assert_eq!(
+            synonyms.get(&1),
+            Some(&vec![
+                Synonym::new("new york", 0.9),
+                Synonym::new("manhattan", 0.5)
+            ])
+        );
+
+        // phrase alternatives are attached to the first word of the phrase, spanning its words
+        let (_, synonyms) = get_norm_query_vec(
+            "new york hotels",
+            &DefaultAnalyzer,
+            &syn_dict,
+            ParseMode::Search,
+        );
+        assert_eq!(
+            synonyms.get(&0),
+            Some(&vec![Synonym {
+                span: 2,
+                ..Synonym::new("nyc", 1.0)
+            }])
+        );
+        assert_eq!(synonyms.len(), 1);
+
+        // alternatives already in the query are skipped
+        let (_, synonyms) = get_norm_query_vec(
+            "nyc new york hotels",
+            &DefaultAnalyzer,
+            &syn_dict,
+            ParseMode::Search,
+        );
+        assert_eq!(
+            synonyms.get(&0),
+            Some(&vec![Synonym::new("manhattan", 0.5)])
+        );
+        assert_eq!(synonyms.get(&1), None);
+    }
+
+    #[test]
+    fn test_parse_phrase_synonyms() {
+        let tr_map =
+            Map::from_iter(vec![("hotels", 40), ("new", 20), ("nyc", 60), ("york", 50)]).unwrap();
+        let syn_dict = vec![("new york".to_string(), vec![Synonym::new("nyc", 1.0)])]
+            .into_iter()
+            .collect::<FnvHashMap<String, Vec<Synonym>>>();
+        let syn_dict = Some(Synonyms::from_dict(syn_dict));
+
+        let (ngrams, _, ngrams_ids, words, _, _, synonyms, _) = parse(
+            "new york hotels",
+            &DefaultAnalyzer,
+            &syn_dict,
+            &None,
+            &Stopwords::empty(),
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Search,
+        );
+        assert_eq!(words, vec!["new", "york", "hotels"]);
+        assert_eq!(synonyms.get(&0).map(|syns| syns[0].span), Some(2));
+
+        // the alternative replaces the whole phrase, it's not mixed with the phrase words
+        assert_eq!(
+            ngrams,
+            vec![
+                "new york",
+                "nyc",
+                "hotels new",
+                "hotels nyc",
+                "hotels york",
+                "new",
+                "york"
+            ]
+        );
+        assert_eq!(ngrams_ids.get("nyc"), Some(&vec![0, 1]));
+        assert_eq!(ngrams_ids.get("hotels nyc"), Some(&vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn test_match_queries_phrase_synonyms() {
+        let words_set: FnvHashSet<String> = vec!["hotels", "nyc"]
+            .into_iter()
+            .map(|w| w.to_string())
+            .collect();
+        let cand_synonyms: FnvHashMap<String, String> =
+            vec![("new york".to_string(), "nyc".to_string())]
+                .into_iter()
+                .collect();
+
+        let (_, mut match_words, miss_words, excess_words) = match_queries(
+            "cheap hotels new york",
+            &DefaultAnalyzer,
+            &words_set,
+            &cand_synonyms,
+        );
+        match_words.sort();
+        assert_eq!(match_words, vec!["hotels", "nyc"]);
+        assert!(miss_words.is_empty());
+        assert_eq!(excess_words, vec!["cheap"]);
+
+        let (_, _, miss_words, _) =
+            match_queries("new hotels", &DefaultAnalyzer, &words_set, &cand_synonyms);
+        assert_eq!(miss_words, vec!["nyc"]);
+
+        // an alternative of a query phrase matches all of its words
+        let words_set: FnvHashSet<String> = vec!["new", "york", "hotels"]
+            .into_iter()
+            .map(|w| w.to_string())
+            .collect();
+        let cand_synonyms: FnvHashMap<String, String> =
+            vec![("nyc".to_string(), "new york".to_string())]
+                .into_iter()
+                .collect();
+        let (_, mut match_words, miss_words, excess_words) =
+            match_queries("nyc hotels", &DefaultAnalyzer, &words_set, &cand_synonyms);
+        match_words.sort();
+        assert_eq!(match_words, vec!["hotels", "new", "york"]);
+        assert!(miss_words.is_empty());
+        assert!(excess_words.is_empty());
+    }
+
     fn assert_must_have_words_ngrams_ids(
         query: &str,
-        synonyms: &Option<FnvHashMap<String, String>>,
-        toponyms: &Option<fst::Set>,
-        stopwords: &FnvHashSet<String>,
+        synonyms: &Option<Synonyms>,
+        toponyms: &Option<Toponyms>,
+        stopwords: &Stopwords,
         tr_map: &fst::Map,
         mode: ParseMode,
         e_must_have: Vec<usize>,
         e_words: Vec<&str>,
         e_ngrams_ids: Vec<(&str, Vec<usize>)>,
     ) {
-        let (_, _, ngrams_ids, words, _, must_have, _) =
-            parse(query, synonyms, &toponyms, &stopwords, &tr_map, mode);
+        let (_, _, ngrams_ids, words, _, must_have, _, _) = parse(
+            query,
+            &DefaultAnalyzer,
+            synonyms,
+            &toponyms,
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            mode,
+        );
         let e_ngrams_ids = e_ngrams_ids
             .into_iter()
             .map(|(s, v)| (s.to_string(), v))
@@ -1646,8 +2372,9 @@ mod tests {
 
     #[test]
     fn test_parse() {
-        let synonyms = synonyms::load(&PathBuf::from("./index/synonyms.txt"));
-        let toponyms = toponyms::load(&PathBuf::from("./index/toponyms.fst"));
+        let synonyms = synonyms::load(&PathBuf::from("./index/synonyms.txt"), &DefaultAnalyzer);
+        let toponyms = toponyms::load(&PathBuf::from("./index/toponyms.fst"))
+            .map(|set| Toponyms::new(set, None, Policy::Top));
 
         let stopwords = match stopwords::load("./index/stopwords.txt") {
             Ok(stopwords) => stopwords,
@@ -2132,20 +2859,24 @@ mod tests {
             ],
         );
         // assert equal outcomes for different parsing modes
-        let (_, _, s_ngrams_ids, s_words, _, s_must_have, _) = parse(
+        let (_, _, s_ngrams_ids, s_words, _, s_must_have, _, _) = parse(
             q,
+            &DefaultAnalyzer,
             &synonyms,
             &toponyms,
             &stopwords,
             &tr_map,
+            WORDS_PER_QUERY,
             ParseMode::Index,
         );
-        let (_, _, i_ngrams_ids, i_words, _, i_must_have, _) = parse(
+        let (_, _, i_ngrams_ids, i_words, _, i_must_have, _, _) = parse(
             q,
+            &DefaultAnalyzer,
             &synonyms,
             &toponyms,
             &stopwords,
             &tr_map,
+            WORDS_PER_QUERY,
             ParseMode::Search,
         );
         assert_eq!(s_ngrams_ids, i_ngrams_ids);
@@ -2169,10 +2900,26 @@ mod tests {
             ],
         );
         // assert equal outcomes for different parsing modes
-        let (_, _, s_ngrams_ids, s_words, _, s_must_have, _) =
-            parse(q, &None, &None, &stopwords, &tr_map, ParseMode::Index);
-        let (_, _, i_ngrams_ids, i_words, _, i_must_have, _) =
-            parse(q, &None, &None, &stopwords, &tr_map, ParseMode::Search);
+        let (_, _, s_ngrams_ids, s_words, _, s_must_have, _, _) = parse(
+            q,
+            &DefaultAnalyzer,
+            &None,
+            &None,
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Index,
+        );
+        let (_, _, i_ngrams_ids, i_words, _, i_must_have, _, _) = parse(
+            q,
+            &DefaultAnalyzer,
+            &None,
+            &None,
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Search,
+        );
         assert_eq!(s_ngrams_ids, i_ngrams_ids, "query: {}", q);
         assert_eq!(s_words, i_words);
         assert_eq!(s_must_have, i_must_have, "query: {}", q);
@@ -2199,10 +2946,26 @@ mod tests {
             ],
         );
         // assert (not) equal outcomes for different parsing modes [ormlite missing on indexing part]
-        let (_, _, s_ngrams_ids, s_words, _, s_must_have, _) =
-            parse(q, &None, &None, &stopwords, &tr_map, ParseMode::Search);
-        let (_, _, i_ngrams_ids, i_words, _, i_must_have, _) =
-            parse(q, &None, &None, &stopwords, &tr_map, ParseMode::Index);
+        let (_, _, s_ngrams_ids, s_words, _, s_must_have, _, _) = parse(
+            q,
+            &DefaultAnalyzer,
+            &None,
+            &None,
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Search,
+        );
+        let (_, _, i_ngrams_ids, i_words, _, i_must_have, _, _) = parse(
+            q,
+            &DefaultAnalyzer,
+            &None,
+            &None,
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Index,
+        );
         assert_ne!(s_ngrams_ids, i_ngrams_ids, "query: {}", q);
         assert_eq!(s_words, i_words);
         assert_eq!(s_must_have, i_must_have, "query: {}", q);
@@ -2339,6 +3102,307 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_split_prefix() {
+        assert_eq!(
+            split_prefix("how to Chan", &DefaultAnalyzer),
+            ("how to".to_string(), "chan".to_string())
+        );
+        assert_eq!(
+            split_prefix("chan", &DefaultAnalyzer),
+            ("".to_string(), "chan".to_string())
+        );
+        assert_eq!(
+            split_prefix("how to change ", &DefaultAnalyzer),
+            ("how to change".to_string(), "".to_string())
+        );
+    }
+
+    #[test]
+    fn test_complete_prefix() {
+        let tr_map = Map::from_iter(vec![
+            ("chain", 40),
+            ("chair", 30),
+            ("change", 60),
+            ("changelog", 70),
+            ("channel", 50),
+            ("chant", 10),
+            ("chapel", 50),
+            ("cheap", 90),
+        ])
+        .unwrap();
+
+        // a lower relevance is a more common word
+        assert_eq!(
+            complete_prefix("chan", &tr_map, 3),
+            vec!["chant", "channel", "change"]
+        );
+        assert_eq!(complete_prefix("chai", &tr_map, 5), vec!["chair", "chain"]);
+        // ties are broken alphabetically
+        assert_eq!(
+            complete_prefix("cha", &tr_map, 5),
+            vec!["chant", "chair", "chain", "channel", "chapel"]
+        );
+        assert_eq!(complete_prefix("chx", &tr_map, 5), Vec::<String>::new());
+        assert_eq!(complete_prefix("", &tr_map, 5), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("menu", "menu"), 0);
+        assert_eq!(edit_distance("mneu", "menu"), 2);
+        assert_eq!(edit_distance("chnage", "change"), 2);
+        assert_eq!(edit_distance("chang", "change"), 1);
+        assert_eq!(edit_distance("münchen", "munchen"), 1);
+        assert_eq!(edit_distance("", "bar"), 3);
+    }
+
+    #[test]
+    fn test_keep_relevant_words() {
+        let tr_map =
+            Map::from_iter(vec![("bar", 40), ("mac", 70), ("menu", 55), ("the", 80)]).unwrap();
+        let stopwords = Stopwords::from(
+            vec!["the".to_string()]
+                .into_iter()
+                .collect::<FnvHashSet<String>>(),
+        );
+        let words: Vec<String> = vec!["the", "mac", "bar", "xyzzy", "menu"]
+            .into_iter()
+            .map(|w| w.to_string())
+            .collect();
+        let synonyms: FnvHashMap<usize, Vec<Synonym>> = vec![(4, vec![Synonym::new("menus", 1.0)])]
+            .into_iter()
+            .collect();
+
+        let (kept, kept_synonyms, dropped) =
+            keep_relevant_words(words.clone(), synonyms.clone(), &tr_map, &stopwords, 3);
+        assert_eq!(kept, vec!["mac", "xyzzy", "menu"]);
+        assert_eq!(dropped, vec!["the", "bar"]);
+        assert_eq!(kept_synonyms.get(&2), synonyms.get(&4));
+
+        let (kept, _, dropped) =
+            keep_relevant_words(words.clone(), synonyms, &tr_map, &stopwords, 5);
+        assert_eq!(kept, words);
+        assert!(dropped.is_empty());
+
+        let (_, _, _, words, _, _, _, dropped) = parse(
+            "the mac bar xyzzy menu",
+            &DefaultAnalyzer,
+            &None,
+            &None,
+            &stopwords,
+            &tr_map,
+            4,
+            ParseMode::Search,
+        );
+        assert_eq!(words, vec!["the", "mac", "xyzzy", "menu"]);
+        assert_eq!(dropped, vec!["bar"]);
+    }
+
+    #[test]
+    fn test_toponyms_must_have() {
+        let tr_map = Map::from_iter(vec![
+            ("berlin", 60),
+            ("flights", 50),
+            ("hotels", 40),
+            ("muenchen", 60),
+            ("new", 20),
+            ("paris", 70),
+            ("to", 10),
+            ("york", 50),
+        ])
+        .unwrap();
+        let stopwords = Stopwords::from(
+            vec!["to".to_string()]
+                .into_iter()
+                .collect::<FnvHashSet<String>>(),
+        );
+        let toponyms = |policy: Policy| {
+            let set = fst::Set::from_iter(vec!["berlin", "new york", "paris"]).unwrap();
+            let mut dict = FnvHashMap::default();
+            let (phrase, alternatives) =
+                synonyms::parse_line("muenchen munich", &DefaultAnalyzer).unwrap();
+            dict.insert(phrase, alternatives);
+            Some(Toponyms::new(set, Some(Synonyms::from_dict(dict)), policy))
+        };
+        let must_have = |query: &str, policy: Policy| {
+            let (words, synonyms) =
+                get_norm_query_vec(query, &DefaultAnalyzer, &None, ParseMode::Search);
+            let (_, _, _, must_have, _) = index_words(
+                &words,
+                &tr_map,
+                &stopwords,
+                &synonyms,
+                &toponyms(policy),
+                &None,
+            );
+            must_have
+        };
+
+        assert_eq!(
+            must_have("flights paris to berlin", Policy::All),
+            vec![1, 3]
+        );
+        assert_eq!(must_have("flights paris to berlin", Policy::Top), vec![1]);
+        assert!(must_have("flights paris to berlin", Policy::None).is_empty());
+        assert_eq!(must_have("new york hotels", Policy::All), vec![1]);
+        assert_eq!(must_have("cheap hotels muenchen", Policy::Top), vec![2]);
+
+        let (_, _, _, _, _, _, synonyms, _) = parse(
+            "muenchen hotels",
+            &DefaultAnalyzer,
+            &None,
+            &toponyms(Policy::Top),
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Search,
+        );
+        assert_eq!(synonyms.get(&0), Some(&vec![Synonym::new("munich", 1.0)]));
+    }
+
+    #[test]
+    fn test_toponym_phrase_synonyms() {
+        let tr_map = Map::from_iter(vec![
+            ("city", 30),
+            ("de", 5),
+            ("hotels", 40),
+            ("janeiro", 60),
+            ("rio", 50),
+            ("rj", 70),
+        ])
+        .unwrap();
+        let set = fst::Set::from_iter(vec!["rio de janeiro"]).unwrap();
+        let mut dict = FnvHashMap::default();
+        let (phrase, alternatives) =
+            synonyms::parse_line("rio de janeiro\trj", &DefaultAnalyzer).unwrap();
+        dict.insert(phrase, alternatives);
+        let toponyms = Some(Toponyms::new(
+            set,
+            Some(Synonyms::from_dict(dict)),
+            Policy::Top,
+        ));
+
+        let (ngrams, _, ngrams_ids, words, _, _, synonyms, _) = parse(
+            "hotels rio de janeiro city",
+            &DefaultAnalyzer,
+            &None,
+            &toponyms,
+            &Stopwords::empty(),
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Search,
+        );
+        assert_eq!(synonyms.get(&1).map(|syns| syns[0].span), Some(3));
+
+        // the alternative name stands for all the words of the toponym
+        assert_eq!(ngrams_ids.get("rj"), Some(&vec![1, 2, 3]));
+        assert!(ngrams.contains(&"hotels rj".to_string()));
+        assert!(ngrams.contains(&"city rj".to_string()));
+        for ngram in ngrams.iter().filter(|ngram| ngram.contains("rj")) {
+            assert!(!ngram
+                .split(" ")
+                .any(|w| w == "rio" || w == "de" || w == "janeiro"));
+        }
+        assert_eq!(words.len(), 5);
+    }
+
+    #[test]
+    fn test_parse_stems() {
+        let tr_map = Map::from_iter(vec![
+            ("bar", 40),
+            ("bars", 45),
+            ("change", 60),
+            ("changing", 65),
+            ("mac", 70),
+            ("menu", 55),
+        ])
+        .unwrap();
+        let stopwords = Stopwords::empty();
+        let analyzer =
+            StemmingAnalyzer::new(Box::new(DefaultAnalyzer), stemmer::get("en").unwrap());
+
+        let (ngrams_0, _, _, words_0, _, _, _, _) = parse(
+            "changing mac menu bar",
+            &analyzer,
+            &None,
+            &None,
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Index,
+        );
+        let (ngrams_1, _, _, words_1, _, _, _, _) = parse(
+            "change mac menu bars",
+            &analyzer,
+            &None,
+            &None,
+            &stopwords,
+            &tr_map,
+            WORDS_PER_QUERY,
+            ParseMode::Search,
+        );
+
+        // original words are kept
+        assert_eq!(words_0, vec!["changing", "mac", "menu", "bar"]);
+        assert_eq!(words_1, vec!["change", "mac", "menu", "bars"]);
+        assert!(ngrams_0.contains(&"chang mac menu".to_string()));
+        assert_eq!(ngrams_0, ngrams_1);
+
+        let words_set: FnvHashSet<String> = words_0.into_iter().collect();
+        let (_, match_words, miss_words, excess_words) = match_queries(
+            "change mac menu bars",
+            &analyzer,
+            &words_set,
+            &FnvHashMap::default(),
+        );
+        assert_eq!(match_words.len(), 4);
+        assert!(miss_words.is_empty());
+        assert!(excess_words.is_empty());
+
+        let (_, match_words, miss_words, excess_words) = match_queries(
+            "change mac menu bars",
+            &DefaultAnalyzer,
+            &words_set,
+            &FnvHashMap::default(),
+        );
+        assert_eq!(match_words.len(), 2);
+        assert_eq!(miss_words.len(), 2);
+        assert_eq!(excess_words.len(), 2);
+    }
+
+    #[test]
+    fn test_get_corrections() {
+        let tr_map = Map::from_iter(vec![
+            ("bar", 40),
+            ("change", 60),
+            ("changed", 50),
+            ("mac", 70),
+            ("menu", 55),
+            ("menus", 80),
+        ])
+        .unwrap();
+        let stopwords = Stopwords::empty();
+
+        let q = "chnage mac os menux bar";
+        let e = vec![
+            ("chnage".to_string(), "change".to_string()),
+            ("menux".to_string(), "menu".to_string()),
+        ];
+        assert_eq!(
+            get_corrections(q, &DefaultAnalyzer, &tr_map, &stopwords, 2),
+            e
+        );
+
+        // 'chnage' is 2 edits away from 'change'
+        let e = vec![("menux".to_string(), "menu".to_string())];
+        assert_eq!(
+            get_corrections(q, &DefaultAnalyzer, &tr_map, &stopwords, 1),
+            e
+        );
+        assert!(get_corrections(q, &DefaultAnalyzer, &tr_map, &stopwords, 0).is_empty());
+    }
+
     fn assert_match_miss_excess(
         q_cand: &str,
         org_q: &FnvHashSet<String>,
@@ -2349,7 +3413,7 @@ mod tests {
         e_excess: Vec<&str>,
     ) {
         let (cand_words, match_words, miss_words, excess_words) =
-            match_queries(q_cand, &org_q, &cand_syns);
+            match_queries(q_cand, &DefaultAnalyzer, &org_q, &cand_syns);
         assert_eq!(cand_words, e_cand);
         assert_eq!(match_words, e_match);
         assert_eq!(miss_words, e_miss);
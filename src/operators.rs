@@ -0,0 +1,188 @@
+/*
+    Query operators, parsed from the raw query before it's normalized:
+
+        +word           the word is required, it's added to the must-have words
+        -word           the word is excluded, candidates containing it are dropped
+        "exact phrase"  candidates have to contain the phrase, checked against the i2q text
+
+    Operators are removed from the query, required words and phrases are searched for
+    as any other words, excluded words are not. Excluded words and phrases are checked
+    against the candidate texts, they are ignored with a warning if i2q is not loaded.
+*/
+
+use analyzer::Analyzer;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Operators {
+    pub required: Vec<String>,
+    pub excluded: Vec<String>,
+    pub phrases: Vec<String>,
+}
+
+#[inline]
+fn norm_words(text: &str, analyzer: &dyn Analyzer) -> Vec<String> {
+    analyzer
+        .normalize(text)
+        .split(" ")
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect()
+}
+
+impl Operators {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.excluded.is_empty() && self.phrases.is_empty()
+    }
+
+    // true if the operators are checked against the candidate texts
+    #[inline]
+    pub fn needs_text(&self) -> bool {
+        !self.excluded.is_empty() || !self.phrases.is_empty()
+    }
+
+    // adds the indices of the required query words to must_have
+    pub fn extend_must_have(
+        &self,
+        words: &Vec<String>,
+        must_have: &mut Vec<usize>,
+        analyzer: &dyn Analyzer,
+    ) {
+        for required in self.required.iter() {
+            let stem = analyzer.stem(required);
+            let word_idx = words
+                .iter()
+                .position(|w| w == required || analyzer.stem(w) == stem);
+            if let Some(word_idx) = word_idx {
+                if !must_have.contains(&word_idx) {
+                    must_have.push(word_idx);
+                }
+            }
+        }
+    }
+
+    // true if a candidate, its normalized words and the query words it misses,
+    // satisfies the operators
+    pub fn accepts(
+        &self,
+        cand_words: &Vec<String>,
+        miss_words: &Vec<String>,
+        analyzer: &dyn Analyzer,
+    ) -> bool {
+        if self.required.iter().any(|w| miss_words.contains(w)) {
+            return false;
+        }
+
+        if !self.excluded.is_empty() {
+            let cand_stems: Vec<String> = cand_words.iter().map(|w| analyzer.stem(w)).collect();
+            for excluded in self.excluded.iter() {
+                if cand_words.contains(excluded) || cand_stems.contains(&analyzer.stem(excluded)) {
+                    return false;
+                }
+            }
+        }
+
+        if !self.phrases.is_empty() {
+            let cand_text = format!(" {} ", cand_words.join(" "));
+            for phrase in self.phrases.iter() {
+                if !cand_text.contains(&format!(" {} ", phrase)) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+// Splits the raw query into the query without operators and the operators.
+// An unbalanced quote is ignored, the text after it is a part of the query.
+pub fn parse(query: &str, analyzer: &dyn Analyzer) -> (String, Operators) {
+    let mut operators = Operators::default();
+    let mut plain: Vec<&str> = vec![];
+
+    let parts: Vec<&str> = query.split('"').collect();
+    let balanced = parts.len() % 2 == 1;
+    for (i, part) in parts.iter().enumerate() {
+        // odd parts are quoted
+        if i % 2 == 1 && (balanced || i < parts.len() - 1) {
+            let phrase = norm_words(part, analyzer).join(" ");
+            if !phrase.is_empty() {
+                operators.phrases.push(phrase);
+            }
+            plain.push(part);
+            continue;
+        }
+
+        for token in part.split_whitespace() {
+            if token.len() > 1 && token.starts_with('+') {
+                operators.required.extend(norm_words(&token[1..], analyzer));
+                plain.push(&token[1..]);
+            } else if token.len() > 1 && token.starts_with('-') {
+                operators.excluded.extend(norm_words(&token[1..], analyzer));
+            } else {
+                plain.push(token);
+            }
+        }
+    }
+
+    (plain.join(" "), operators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyzer::DefaultAnalyzer;
+
+    fn words(query: &str) -> Vec<String> {
+        query.split(" ").map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let (query, operators) = parse(
+            "cheap +Hotels -hostel \"new York\" center",
+            &DefaultAnalyzer,
+        );
+        assert_eq!(query, "cheap Hotels new York center");
+        assert_eq!(operators.required, vec!["hotels"]);
+        assert_eq!(operators.excluded, vec!["hostel"]);
+        assert_eq!(operators.phrases, vec!["new york"]);
+
+        let (query, operators) = parse("e-mail client - free", &DefaultAnalyzer);
+        assert_eq!(query, "e-mail client - free");
+        assert!(operators.is_empty());
+
+        let (query, operators) = parse("menu \"bar mac", &DefaultAnalyzer);
+        assert_eq!(query, "menu bar mac");
+        assert!(operators.is_empty());
+    }
+
+    #[test]
+    fn test_extend_must_have() {
+        let (_, operators) = parse("+hotels nyc", &DefaultAnalyzer);
+        let mut must_have = vec![1];
+        operators.extend_must_have(&words("nyc hotels"), &mut must_have, &DefaultAnalyzer);
+        assert_eq!(must_have, vec![1]);
+
+        let mut must_have = vec![];
+        operators.extend_must_have(&words("hotels nyc"), &mut must_have, &DefaultAnalyzer);
+        assert_eq!(must_have, vec![0]);
+    }
+
+    #[test]
+    fn test_accepts() {
+        let (_, operators) = parse("hotels -hostel \"new york\"", &DefaultAnalyzer);
+        let no_miss = vec![];
+
+        assert!(operators.accepts(&words("new york hotels"), &no_miss, &DefaultAnalyzer));
+        assert!(!operators.accepts(&words("york new hotels"), &no_miss, &DefaultAnalyzer));
+        assert!(!operators.accepts(&words("new york hostel"), &no_miss, &DefaultAnalyzer));
+        assert!(operators.needs_text());
+
+        let (_, operators) = parse("+hotels nyc", &DefaultAnalyzer);
+        assert!(operators.accepts(&words("nyc hotels"), &no_miss, &DefaultAnalyzer));
+        assert!(!operators.accepts(&words("nyc"), &words("hotels"), &DefaultAnalyzer));
+        assert!(!operators.needs_text());
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ Opaque continuation cursor for paginated search results.
+
+ A cursor remembers where the next page starts (offset), how many keyword
+ candidates were re-scored for the first page (window) and a hash of the query
+ it belongs to. Re-using the same window for every page keeps the ordering of
+ results stable between pages, ties are broken by query id.
+
+   encoded cursor: [offset]-[window]-[query hash], all numbers in hex
+*/
+
+extern crate seahash;
+
+// max number of results reachable through pagination with the default window
+pub const PAGE_WINDOW: usize = 1000;
+// max number of candidates a page can be cut from, larger offsets return empty pages
+pub const MAX_PAGE_WINDOW: usize = 10 * PAGE_WINDOW;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageCursor {
+    pub offset: usize,
+    pub window: usize,
+    query_hash: u64,
+}
+
+#[inline]
+fn query_hash(query: &str) -> u64 {
+    seahash::hash(query.trim().as_bytes())
+}
+
+impl PageCursor {
+    pub fn new(query: &str, offset: usize, window: usize) -> Self {
+        PageCursor {
+            offset: offset,
+            window: window,
+            query_hash: query_hash(query),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!(
+            "{:x}-{:x}-{:016x}",
+            self.offset, self.window, self.query_hash
+        )
+    }
+
+    // returns None if the cursor is malformed, its window is out of bounds
+    // or it was issued for a different query
+    pub fn decode(cursor: &str, query: &str) -> Option<Self> {
+        let v: Vec<&str> = cursor.trim().split("-").collect();
+        if v.len() != 3 {
+            return None;
+        }
+
+        let offset = usize::from_str_radix(v[0], 16).ok()?;
+        let window = usize::from_str_radix(v[1], 16).ok()?;
+        let hash = u64::from_str_radix(v[2], 16).ok()?;
+
+        if hash != query_hash(query) || window == 0 || window > MAX_PAGE_WINDOW || offset > window {
+            return None;
+        }
+
+        Some(PageCursor {
+            offset: offset,
+            window: window,
+            query_hash: hash,
+        })
+    }
+
+    // cursor for the page following the one with the given limit, if there are results left
+    pub fn next(&self, limit: usize, total: usize) -> Option<Self> {
+        let offset = self.offset + limit;
+        if offset >= total || offset >= self.window {
+            return None;
+        }
+
+        Some(PageCursor {
+            offset: offset,
+            window: self.window,
+            query_hash: self.query_hash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_encode_decode() {
+        let q = "changing mac os menu bar";
+        let c = PageCursor::new(q, 20, PAGE_WINDOW);
+        assert_eq!(Some(c), PageCursor::decode(&c.encode(), q));
+        assert_eq!(
+            None,
+            PageCursor::decode(&c.encode(), "emails menu bar mac os")
+        );
+        assert_eq!(None, PageCursor::decode("20-3e8", q));
+        assert_eq!(None, PageCursor::decode("not-a-cursor", q));
+
+        let c = PageCursor::new(q, 0, MAX_PAGE_WINDOW + 1);
+        assert_eq!(None, PageCursor::decode(&c.encode(), q));
+        let c = PageCursor::new(q, 0, MAX_PAGE_WINDOW);
+        assert_eq!(Some(c), PageCursor::decode(&c.encode(), q));
+    }
+
+    #[test]
+    fn test_cursor_next() {
+        let q = "changing mac os menu bar";
+        let c = PageCursor::new(q, 0, 30);
+        let n = c.next(10, 25).unwrap();
+        assert_eq!(10, n.offset);
+        assert_eq!(30, n.window);
+        assert_eq!(None, n.next(20, 25));
+        assert_eq!(None, PageCursor::new(q, 20, 30).next(10, 100));
+    }
+}
@@ -0,0 +1,101 @@
+/*
+    Query to id map of a shard: an fst map from the normalized text of a query to its shard
+    query id, that is the index of the query in the i2q file of the shard. The map is built
+    from the i2q file while sharding, a duplicated query maps to its smallest id.
+*/
+
+use fst::{Map, MapBuilder};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use analyzer::Analyzer;
+use stringvec::StrVec;
+
+// normalized query text, words separated by a single space
+#[inline]
+pub fn key(query: &str, analyzer: &dyn Analyzer) -> String {
+    analyzer
+        .normalize(query)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+pub fn build<I, S>(
+    queries: I,
+    output_path: &Path,
+    analyzer: &dyn Analyzer,
+) -> Result<usize, fst::Error>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut entries: Vec<(String, u64)> = queries
+        .enumerate()
+        .map(|(shard_query_id, query)| (key(query.as_ref(), analyzer), shard_query_id as u64))
+        .filter(|(key, _)| !key.is_empty())
+        .collect();
+    entries.sort();
+    entries.dedup_by(|a, b| a.0 == b.0);
+
+    let wtr = BufWriter::new(File::create(output_path)?);
+    let mut builder = MapBuilder::new(wtr)?;
+    for (key, shard_query_id) in entries.iter() {
+        builder.insert(key, *shard_query_id)?;
+    }
+    builder.finish()?;
+
+    Ok(entries.len())
+}
+
+// Builds the query to id map of an i2q file
+pub fn compile(
+    i2q_path: &Path,
+    output_path: &Path,
+    analyzer: &dyn Analyzer,
+) -> Result<usize, fst::Error> {
+    let i2q = StrVec::load(i2q_path);
+
+    build(i2q.iter(), output_path, analyzer)
+}
+
+#[inline]
+pub fn get(map: &Map, query: &str, analyzer: &dyn Analyzer) -> Option<u64> {
+    map.get(key(query, analyzer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyzer::DefaultAnalyzer;
+    use std::env::temp_dir;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            key("  Cheap   Hotels Paris ", &DefaultAnalyzer),
+            "cheap hotels paris"
+        );
+        assert_eq!(key("", &DefaultAnalyzer), "");
+    }
+
+    #[test]
+    fn test_build_get() {
+        let queries = vec![
+            "cheap hotels paris",
+            "Paris hotels",
+            "",
+            "cheap  hotels Paris",
+        ];
+        let path = temp_dir().join("test_q2i.fst");
+        let nr_keys = build(queries.into_iter(), &path, &DefaultAnalyzer).unwrap();
+        assert_eq!(nr_keys, 2);
+
+        let map = Map::from_path(&path).unwrap();
+        assert_eq!(get(&map, "Cheap Hotels Paris", &DefaultAnalyzer), Some(0));
+        assert_eq!(get(&map, "paris hotels", &DefaultAnalyzer), Some(1));
+        assert_eq!(get(&map, "hotels", &DefaultAnalyzer), None);
+        assert_eq!(get(&map, "", &DefaultAnalyzer), None);
+    }
+}
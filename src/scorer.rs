@@ -0,0 +1,540 @@
+/*
+    Scorers re-rank the candidates of a query, found by keywords or by the query vector.
+    A scorer gets the parsed query and a candidate, its text and words, the keyword distance
+    and the matched, missing and excess words, and returns the keyword and the cosine distance
+    of the candidate. Candidates are ranked by the cosine distance if both of them have it,
+    by the keyword distance otherwise.
+
+    The scorer is selected by name in config.json ("scorer", "default" if not set), set for
+    a Qpick instance with `set_scorer` or passed per request to `get_with_scorer`:
+
+        default  the keyword distance and the word vectors distance of the matched,
+                 missing and excess words
+        keyword  the keyword distance only, no word vectors
+        model    a learned model over the candidate features, loaded from "model_file"
+                 next to config.json, see model.rs
+*/
+
+use fnv::FnvHashMap;
+use std::cell::Cell;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use analyzer::Analyzer;
+use config::Config;
+use model::Model;
+use stopwords::Stopwords;
+use util::{self, BRED, BYELL, ECOL};
+use word_vec::{self, WordVecs};
+
+pub const DEFAULT_SCORER: &str = "default";
+pub const SCORERS: [&str; 3] = ["default", "keyword", "model"];
+
+// features of a candidate, for learned models and offline training
+pub const FEATURES: [&str; 10] = [
+    "keyword",       // keyword distance
+    "cosine",        // cosine distance of the default scorer, 1 if unknown
+    "has_cosine",    // 1 if the cosine distance is known, 0 otherwise
+    "match_words",   // number of query words found in the candidate
+    "miss_words",    // number of query words not found in the candidate
+    "excess_words",  // number of candidate words not in the query
+    "must_have",     // number of must-have query words found in the candidate
+    "ngram_hits",    // number of query ngrams found in the candidate
+    "max_ngram_rel", // max relevance of the query ngrams found in the candidate
+    "length_ratio",  // number of candidate words / number of query words
+];
+
+// a query candidate to score
+pub struct Candidate<'a> {
+    pub words: &'a Vec<String>, // parsed query words
+    pub wrs: &'a Vec<f32>,      // relevances of the query words
+    pub query: &'a str,         // candidate query text, empty if i2q is not loaded
+    pub cand_words: &'a Vec<String>,
+    pub match_words: &'a Vec<String>, // query words found in the candidate
+    pub miss_words: &'a Vec<String>,  // query words not found in the candidate
+    pub excess_words: &'a Vec<String>, // candidate words not in the query
+    pub keyword_dist: f32,
+    pub query_id: u64,
+    pub must_have: &'a Vec<usize>, // indices of the must-have query words
+    pub ngram_hits: usize,         // query ngrams found in the candidate, 0 for ANN candidates
+    pub max_ngram_rel: f32,
+    pub cosine_memo: Cell<Option<Option<f32>>>, // see Candidate::cosine, Cell::new(None) to create
+}
+
+impl<'a> Candidate<'a> {
+    // cosine distance of the default scorer, computed once and shared by the scorers of
+    // the candidate, e.g. a FeaturesRecorder and the scorer it wraps
+    pub fn cosine(&self, ctx: &Context) -> Option<f32> {
+        if let Some(cosine) = self.cosine_memo.get() {
+            return cosine;
+        }
+
+        let cosine = cosine_diff_distance(self, ctx);
+        self.cosine_memo.set(Some(cosine));
+
+        cosine
+    }
+}
+
+// index data shared by the candidates of a query
+pub struct Context<'a> {
+    pub word_vecs: Option<&'a WordVecs<'a>>,
+    pub terms_relevance: &'a fst::Map,
+    pub stopwords: &'a Stopwords,
+    pub analyzer: &'a dyn Analyzer,
+}
+
+pub trait Scorer: Send + Sync {
+    // name the scorer is selected by in config.json
+    fn name(&self) -> &str;
+
+    // keyword and cosine distance of a candidate, [0, 1], the smaller the better
+    fn score(&self, cand: &Candidate, ctx: &Context) -> (f32, Option<f32>);
+}
+
+pub struct DefaultScorer;
+
+impl Scorer for DefaultScorer {
+    fn name(&self) -> &str {
+        "default"
+    }
+
+    fn score(&self, cand: &Candidate, ctx: &Context) -> (f32, Option<f32>) {
+        (cand.keyword_dist, cand.cosine(ctx))
+    }
+}
+
+pub struct KeywordScorer;
+
+impl Scorer for KeywordScorer {
+    fn name(&self) -> &str {
+        "keyword"
+    }
+
+    fn score(&self, cand: &Candidate, _ctx: &Context) -> (f32, Option<f32>) {
+        (cand.keyword_dist, None)
+    }
+}
+
+// FEATURES of a candidate, cosine is the cosine distance of the default scorer
+pub fn features(cand: &Candidate, cosine: Option<f32>) -> Vec<f32> {
+    let must_have_hits = cand
+        .must_have
+        .iter()
+        .filter(|i| !cand.miss_words.contains(&cand.words[**i]))
+        .count();
+
+    vec![
+        cand.keyword_dist,
+        cosine.unwrap_or(1.0),
+        if cosine.is_some() { 1.0 } else { 0.0 },
+        cand.match_words.len() as f32,
+        cand.miss_words.len() as f32,
+        cand.excess_words.len() as f32,
+        must_have_hits as f32,
+        cand.ngram_hits as f32,
+        cand.max_ngram_rel,
+        cand.cand_words.len() as f32 / util::max(cand.words.len(), 1) as f32,
+    ]
+}
+
+pub struct ModelScorer {
+    model: Model,
+}
+
+impl ModelScorer {
+    pub fn new(model: Model) -> Self {
+        ModelScorer { model: model }
+    }
+}
+
+impl Scorer for ModelScorer {
+    fn name(&self) -> &str {
+        "model"
+    }
+
+    // the predicted distance ranks all candidates
+    fn score(&self, cand: &Candidate, ctx: &Context) -> (f32, Option<f32>) {
+        let features = features(cand, cand.cosine(ctx));
+
+        (cand.keyword_dist, Some(self.model.predict(&features)))
+    }
+}
+
+// Scores with the given scorer and keeps the features of the scored candidates by query id
+pub struct FeaturesRecorder<'s> {
+    scorer: &'s dyn Scorer,
+    pub features: Mutex<FnvHashMap<u64, Vec<f32>>>,
+}
+
+impl<'s> FeaturesRecorder<'s> {
+    pub fn new(scorer: &'s dyn Scorer) -> Self {
+        FeaturesRecorder {
+            scorer: scorer,
+            features: Mutex::new(FnvHashMap::default()),
+        }
+    }
+}
+
+impl<'s> Scorer for FeaturesRecorder<'s> {
+    fn name(&self) -> &str {
+        self.scorer.name()
+    }
+
+    // the cosine distance is computed for the features only if the scorer didn't compute it
+    fn score(&self, cand: &Candidate, ctx: &Context) -> (f32, Option<f32>) {
+        let dists = self.scorer.score(cand, ctx);
+        let features = features(cand, cand.cosine(ctx));
+        self.features
+            .lock()
+            .unwrap()
+            .insert(cand.query_id, features);
+
+        dists
+    }
+}
+
+// returns the built-in scorer by its name, the model scorer needs a model, see load
+pub fn get(name: &str) -> Option<Box<dyn Scorer>> {
+    match name {
+        "default" => Some(Box::new(DefaultScorer)),
+        "keyword" => Some(Box::new(KeywordScorer)),
+        _ => None,
+    }
+}
+
+// returns the scorer by its name, the model of the model scorer is loaded from "model_file"
+// in the index directory path
+pub fn load(name: &str, path: &str, c: &Config) -> Result<Box<dyn Scorer>, Error> {
+    if name == "model" {
+        let model_path = PathBuf::from(path).join(&c.model_file);
+        return match Model::load(&model_path) {
+            Ok(model) => Ok(Box::new(ModelScorer::new(model))),
+            Err(err) => Err(Error::new(
+                err.kind(),
+                format!("Failed to load the model {}: {}", model_path.display(), err),
+            )),
+        };
+    }
+
+    match get(name) {
+        Some(scorer) => Ok(scorer),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Unknown scorer: {}, expected one of: {}",
+                name,
+                SCORERS.join(", ")
+            ),
+        )),
+    }
+}
+
+// scorer set in the index config.json, path is the index directory
+pub fn from_config(path: &str, c: &Config) -> Box<dyn Scorer> {
+    match load(&c.scorer, path, c) {
+        Ok(scorer) => scorer,
+        Err(err) => panic!([
+            BYELL,
+            "Failed to load the scorer ",
+            ECOL,
+            BRED,
+            &c.scorer,
+            ECOL,
+            BYELL,
+            ": ",
+            ECOL,
+            &format!("{}", err),
+        ]
+        .join("")),
+    }
+}
+
+// Word vectors distance of a candidate: the matched words vector w/o the missing words
+// against the one w/o the excess words, corrected by the keyword distance.
+#[inline]
+pub fn cosine_diff_distance(cand: &Candidate, ctx: &Context) -> Option<f32> {
+    let word_vecs = match ctx.word_vecs {
+        Some(word_vecs) => word_vecs,
+        None => return None,
+    };
+    let (words, cand_words, match_words, miss_words, excess_words, keyword_dist) = (
+        cand.words,
+        cand.cand_words,
+        cand.match_words,
+        cand.miss_words,
+        cand.excess_words,
+        cand.keyword_dist,
+    );
+
+    let match_len = match_words.len();
+    let (mut rhs_match_vec, nf_match, _nf_match_words) =
+        word_vecs.get_combined_vec(&match_words, ctx.terms_relevance, ctx.stopwords);
+
+    let missing_len = miss_words.len();
+    let excess_len = excess_words.len();
+
+    if miss_words.is_empty() && excess_words.is_empty() {
+        return Some(0.0);
+    }
+
+    let (mut missing_vec, nf_miss, _nf_miss_words) =
+        word_vecs.get_combined_vec(&miss_words, ctx.terms_relevance, ctx.stopwords);
+    let (mut excess_vec, nf_excs, _nf_excs_words) =
+        word_vecs.get_combined_vec(&excess_words, ctx.terms_relevance, ctx.stopwords);
+
+    // either no match words or none of them are found
+    if nf_match == match_len {
+        // no missing words or none of them found OR
+        // no excess words or none of them found
+        if nf_miss == missing_len || nf_excs == excess_len {
+            return None;
+        }
+
+        word_vec::normalize(&mut excess_vec[..]);
+        word_vec::normalize(&mut missing_vec[..]);
+        let cos_dist = word_vec::cosine_distance(&excess_vec, &missing_vec);
+
+        if missing_len > match_len || excess_len > match_len {
+            return Some(util::min(cos_dist * (1.0 + keyword_dist), 1.0));
+        }
+
+        return Some(word_vec::cosine_distance(&excess_vec, &missing_vec));
+    }
+
+    // there are at least some match words at this point
+
+    // if both, missing AND excess words are not found, we can't calculate cosine
+    if nf_miss == missing_len && nf_excs == excess_len {
+        return None;
+    }
+
+    // if no missing words, cosine dist
+    if nf_miss == missing_len {
+        if let Some(cos_dist) = cosine_distance(word_vecs, words, cand_words, ctx) {
+            let nf = (nf_excs + nf_match + nf_miss) as f32;
+            let nr_miss = (missing_len + excess_len) as f32;
+
+            if match_len >= 2 && missing_len == 0 && excess_len < 2 && keyword_dist < 0.3 {
+                return Some(cos_dist * keyword_dist);
+            }
+
+            if match_len >= 2
+                && match_len > missing_len
+                && match_len > excess_len
+                && keyword_dist < 0.45
+                && nf <= 1.0
+            {
+                return Some(cos_dist * (keyword_dist / (keyword_dist + cos_dist)));
+            }
+
+            return Some(util::min(cos_dist + nf * keyword_dist / nr_miss, 1.0));
+        }
+
+        return None;
+    }
+
+    if nf_excs == excess_len {
+        if let Some(cos_dist) = cosine_distance(word_vecs, words, cand_words, ctx) {
+            let nf = (nf_excs + nf_match + nf_miss) as f32;
+            let nr_miss = (missing_len + excess_len) as f32;
+
+            if match_len >= 2 && missing_len < 2 && excess_len == 0 && keyword_dist < 0.3 {
+                return Some(cos_dist * keyword_dist);
+            }
+
+            if match_len >= 2
+                && match_len > excess_len
+                && match_len > missing_len
+                && keyword_dist < 0.45
+                && nf <= 1.0
+            {
+                return Some(cos_dist * (keyword_dist / (keyword_dist + cos_dist)));
+            }
+
+            return Some(util::min(cos_dist + nf * keyword_dist / nr_miss, 1.0));
+        }
+
+        return None;
+    }
+
+    let mut lhs_match_vec = rhs_match_vec.clone();
+
+    if missing_len > 0 {
+        word_vec::subtract(&mut lhs_match_vec, &missing_vec);
+    }
+
+    if excess_len > 0 {
+        word_vec::subtract(&mut rhs_match_vec, &excess_vec);
+    }
+
+    word_vec::normalize(&mut missing_vec[..]);
+    word_vec::normalize(&mut excess_vec[..]);
+    let me_cos_dist = word_vec::cosine_distance(&missing_vec, &excess_vec);
+
+    word_vec::normalize(&mut lhs_match_vec[..]);
+    word_vec::normalize(&mut rhs_match_vec[..]);
+
+    let cos_dist = word_vec::cosine_distance(&lhs_match_vec, &rhs_match_vec);
+
+    let thresh: f32;
+    if missing_len > 1 {
+        if excess_len < 2 {
+            thresh = 0.5;
+        } else {
+            thresh = 0.45;
+        };
+    } else {
+        if excess_len < 2 {
+            thresh = 0.55;
+        } else {
+            thresh = 0.65;
+        };
+    };
+
+    // missing and excess words and keyword distance is too large
+    if excess_len >= 1 && missing_len >= 1 && match_len <= 2 && keyword_dist > 0.45 {
+        return Some(1.3 * cos_dist + util::max(0.0, me_cos_dist - thresh));
+    }
+
+    // more than one excess, at most one missing, keyword distance is not so big
+    if excess_len <= 2 && missing_len <= 2 && match_len > excess_len && keyword_dist <= 0.45 {
+        return Some(0.75 * cos_dist + util::max(0.0, me_cos_dist - thresh));
+    }
+
+    Some(cos_dist + util::max(0.0, me_cos_dist - thresh))
+}
+
+#[inline]
+fn cosine_distance(
+    word_vecs: &WordVecs,
+    query_words: &Vec<String>,
+    cand_words: &Vec<String>,
+    ctx: &Context,
+) -> Option<f32> {
+    let (mut query_vec, nf, _nf_query_words) =
+        word_vecs.get_combined_vec(&query_words, ctx.terms_relevance, ctx.stopwords);
+
+    if nf == query_words.len() {
+        return None;
+    }
+
+    let (mut cand_vec, nf, _nf_cand_words) =
+        word_vecs.get_combined_vec(&cand_words, ctx.terms_relevance, ctx.stopwords);
+
+    if nf == cand_words.len() {
+        return None;
+    }
+
+    word_vec::normalize(&mut query_vec[..]);
+    word_vec::normalize(&mut cand_vec[..]);
+
+    Some(word_vec::cosine_distance(&query_vec, &cand_vec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyzer::DefaultAnalyzer;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_file, File};
+    use std::io::Write;
+
+    fn words(query: &str) -> Vec<String> {
+        query.split(" ").map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_get() {
+        for name in SCORERS.iter().filter(|name| **name != "model") {
+            assert_eq!(get(name).unwrap().name(), *name);
+        }
+        assert!(get("model").is_none());
+        assert!(get("bm25").is_none());
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = temp_dir().join("test_scorer_load");
+        create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap();
+        let mut f = File::create(dir.join("config.json")).unwrap();
+        f.write_all(
+            b"{\"terms_relevance_file\": \"tr.fst\", \"stopwords_file\": \"stopwords.txt\", \
+              \"use_word_vectors\": false, \"model_file\": \"test_model.json\"}",
+        )
+        .unwrap();
+        let c = Config::init(path.to_string());
+
+        let _ = remove_file(dir.join("test_model.json"));
+        assert!(load("model", path, &c).is_err());
+        let mut f = File::create(dir.join("test_model.json")).unwrap();
+        f.write_all(br#"{"type": "linear", "weights": {"keyword": 1.0}}"#)
+            .unwrap();
+        assert_eq!(load("model", path, &c).unwrap().name(), "model");
+        assert_eq!(load("keyword", path, &c).unwrap().name(), "keyword");
+        assert_eq!(
+            load("bm25", path, &c).err().unwrap().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_score() {
+        let terms_relevance = fst::Map::from_iter(vec![("hotels", 50), ("paris", 80)]).unwrap();
+        let stopwords = Stopwords::empty();
+        let ctx = Context {
+            word_vecs: None,
+            terms_relevance: &terms_relevance,
+            stopwords: &stopwords,
+            analyzer: &DefaultAnalyzer,
+        };
+
+        let (query_words, wrs) = (words("paris hotels"), vec![0.6, 0.4]);
+        let (cand_words, match_words) = (words("paris hotels cheap"), words("paris hotels"));
+        let (miss_words, excess_words) = (vec![], words("cheap"));
+        let must_have = vec![0];
+        let cand = Candidate {
+            words: &query_words,
+            wrs: &wrs,
+            query: "paris hotels cheap",
+            cand_words: &cand_words,
+            match_words: &match_words,
+            miss_words: &miss_words,
+            excess_words: &excess_words,
+            keyword_dist: 0.2,
+            query_id: 7,
+            must_have: &must_have,
+            ngram_hits: 3,
+            max_ngram_rel: 0.8,
+            cosine_memo: Cell::new(None),
+        };
+
+        // no word vectors, no cosine distance
+        assert_eq!(DefaultScorer.score(&cand, &ctx), (0.2, None));
+        assert_eq!(KeywordScorer.score(&cand, &ctx), (0.2, None));
+
+        assert_eq!(
+            features(&cand, None),
+            vec![0.2, 1.0, 0.0, 2.0, 0.0, 1.0, 1.0, 3.0, 0.8, 1.5]
+        );
+
+        let model = Model::parse(r#"{"type": "linear", "weights": {"excess_words": 0.25}}"#);
+        let model_scorer = ModelScorer::new(model.unwrap());
+        assert_eq!(model_scorer.score(&cand, &ctx), (0.2, Some(0.25)));
+
+        let recorder = FeaturesRecorder::new(&KeywordScorer);
+        assert_eq!(recorder.score(&cand, &ctx), (0.2, None));
+        assert_eq!(recorder.features.lock().unwrap()[&7], features(&cand, None));
+
+        // the cosine distance computed for the candidate is recorded, not computed again
+        cand.cosine_memo.set(Some(Some(0.3)));
+        let recorder = FeaturesRecorder::new(&DefaultScorer);
+        assert_eq!(recorder.score(&cand, &ctx), (0.2, Some(0.3)));
+        assert_eq!(
+            recorder.features.lock().unwrap()[&7],
+            features(&cand, Some(0.3))
+        );
+    }
+}
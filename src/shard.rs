@@ -6,8 +6,11 @@ use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use analyzer;
+use analyzer::Analyzer;
 use config;
 use ngrams;
+use q2i;
 use stopwords;
 use stringvec;
 use synonyms;
@@ -87,27 +90,22 @@ pub fn shard(
 
     let c = config::Config::init(output_dir.to_string());
     let i2q_file = c.i2q_file.to_string();
+    let i2q_block_size = c.i2q_block_size;
+    let q2i_file = c.q2i_file.to_string();
+    let words_per_query = c.words_per_query;
     let terms_relevance_file = c.terms_relevance_file.to_string();
 
-    let stopwords_path = &format!("{}/{}", output_dir, c.stopwords_file);
-    let stopwords = match stopwords::load(stopwords_path) {
-        Ok(stopwords) => stopwords,
-        Err(_) => panic!([
-            BYELL,
-            "No such file or directory: ",
-            ECOL,
-            BRED,
-            stopwords_path,
-            ECOL
-        ]
-        .join("")),
-    };
+    let analyzer: Arc<dyn Analyzer> = Arc::from(analyzer::from_config(&c));
+
+    // index with the stopwords list selected by the analyzer
+    let mut stopwords_lists = stopwords::load_lists(output_dir, &c);
+    let stopwords_name = stopwords::select_name(&stopwords_lists, &*analyzer);
+    let stopwords = Arc::new(stopwords_lists.remove(&stopwords_name).unwrap());
 
     let synonyms_path = PathBuf::from(&output_dir).join(&c.synonyms_file);
-    let synonyms_dict = synonyms::load(&synonyms_path);
+    let synonyms_dict = Arc::new(synonyms::load(&synonyms_path, &*analyzer));
 
-    let toponyms_path = PathBuf::from(&output_dir).join(&c.toponyms_file);
-    let toponyms = Arc::new(toponyms::load(&toponyms_path));
+    let toponyms = Arc::new(toponyms::from_config(output_dir, &c, &*analyzer));
 
     let (sender, receiver): (Sender<u64>, Receiver<u64>) = mpsc::channel();
 
@@ -129,12 +127,14 @@ pub fn shard(
     for worker_id in 0..number_of_workers {
         let sender = sender.clone();
         let stopwords = stopwords.clone();
+        let analyzer = analyzer.clone();
         let synonyms_dict = synonyms_dict.clone();
         let toponyms = toponyms.clone();
         let queries_parts = queries_parts.clone();
         let valid_prefixes = valid_prefixes.clone();
         let output_dir = output_dir.to_string().clone();
         let i2q_file = i2q_file.clone();
+        let q2i_file = q2i_file.clone();
         let terms_relevance_file = terms_relevance_file.clone();
 
         let mut shards = vec![];
@@ -179,7 +179,15 @@ pub fn shard(
         thread::spawn(move || {
             let mut line_count: u64 = 0;
             let mut processed_count: u64 = 0;
-            let mut str_vec_writer = stringvec::StrVecWriter::init();
+            let i2q_path = format!("{}/{}.{}", &output_dir, i2q_file, worker_id);
+            let mut str_vec_writer = if create_i2q {
+                Some(
+                    stringvec::StrVecWriter::with_block_size(&Path::new(&i2q_path), i2q_block_size)
+                        .expect(&format!("Failed to create i2q {}", i2q_path)),
+                )
+            } else {
+                None
+            };
 
             for (file_name, query_file) in query_files.into_iter() {
                 println!("Worker: {}, Processing: {:?}", worker_id, file_name);
@@ -209,20 +217,22 @@ pub fn shard(
                     };
 
                     // add query to index -> query vector
-                    if create_i2q {
-                        str_vec_writer.add(query.clone());
+                    if let Some(ref mut str_vec_writer) = str_vec_writer {
+                        str_vec_writer.add(&query);
                     };
 
                     if !valid_prefixes.is_empty() && !valid_prefixes.contains(&query_type) {
                         continue;
                     }
 
-                    let (ngrams, trs, _, _, _, _, _) = &ngrams::parse(
+                    let (ngrams, trs, _, _, _, _, _, _) = &ngrams::parse(
                         &query,
+                        &*analyzer,
                         &synonyms_dict,
                         &toponyms,
                         &stopwords,
                         &tr_map,
+                        words_per_query,
                         ngrams::ParseMode::Index,
                     );
 
@@ -283,11 +293,17 @@ pub fn shard(
                 }
             }
 
-            if create_i2q {
-                str_vec_writer.write_to_file(&Path::new(&format!(
-                    "{}/{}.{}",
-                    &output_dir, i2q_file, worker_id
-                )));
+            if let Some(str_vec_writer) = str_vec_writer {
+                str_vec_writer
+                    .finish()
+                    .expect(&format!("Failed to write i2q {}", i2q_path));
+
+                // normalized query to id map, built from the i2q file
+                if !q2i_file.is_empty() {
+                    let q2i_path = format!("{}/{}.{}", &output_dir, q2i_file, worker_id);
+                    q2i::compile(&Path::new(&i2q_path), &Path::new(&q2i_path), &*analyzer)
+                        .expect(&format!("Failed to build the query to id map {}", q2i_path));
+                }
             };
 
             sender.send(processed_count).unwrap(); //finished!
@@ -303,5 +319,5 @@ pub fn shard(
         total_processed_count
     );
 
-    Ok(())
+    c.write_build_info(output_dir)
 }
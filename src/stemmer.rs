@@ -0,0 +1,527 @@
+/*
+ Snowball stemmers for English (Porter2) and German (german2 variant, ae/oe/ue are umlauts,
+ as the default analyzer folds them), implemented after snowballstem.org.
+
+ A stemmer is selected in config.json ("stemmer": "en" or "de", no stemming if not set),
+ ngrams are built from stems both at index and search time, while the original words are
+ kept for relevances and query matching. Words with digits or foreign letters are not stemmed.
+*/
+
+use util;
+
+pub const STEMMERS: [&str; 2] = ["en", "de"];
+
+pub trait Stemmer: Send + Sync {
+    fn stem(&self, word: &str) -> String;
+}
+
+pub fn get(name: &str) -> Option<Box<dyn Stemmer>> {
+    match name {
+        "en" => Some(Box::new(EnglishStemmer)),
+        "de" => Some(Box::new(GermanStemmer)),
+        _ => None,
+    }
+}
+
+#[inline]
+fn ends_with(w: &[char], suffix: &str) -> bool {
+    let n = suffix.chars().count();
+    w.len() >= n && w[w.len() - n..].iter().cloned().eq(suffix.chars())
+}
+
+#[inline]
+fn replace_suffix(w: &mut Vec<char>, suffix_len: usize, replacement: &str) {
+    let len = w.len() - suffix_len;
+    w.truncate(len);
+    w.extend(replacement.chars());
+}
+
+// longest of the given suffixes the word ends with
+#[inline]
+fn longest_suffix<'a>(w: &[char], suffixes: &[&'a str]) -> Option<&'a str> {
+    suffixes
+        .iter()
+        .filter(|s| ends_with(w, s))
+        .max_by_key(|s| s.chars().count())
+        .cloned()
+}
+
+// start of the region after the first non-vowel following a vowel
+#[inline]
+fn region_start(w: &[char], from: usize, is_vowel: &dyn Fn(char) -> bool) -> usize {
+    for i in from + 1..w.len() {
+        if !is_vowel(w[i]) && is_vowel(w[i - 1]) {
+            return i + 1;
+        }
+    }
+
+    w.len()
+}
+
+/*
+ English
+*/
+
+pub struct EnglishStemmer;
+
+#[inline]
+fn en_vowel(c: char) -> bool {
+    match c {
+        'a' | 'e' | 'i' | 'o' | 'u' | 'y' => true,
+        _ => false,
+    }
+}
+
+#[inline]
+fn en_double(w: &[char]) -> bool {
+    let n = w.len();
+    n >= 2
+        && w[n - 1] == w[n - 2]
+        && match w[n - 1] {
+            'b' | 'd' | 'f' | 'g' | 'm' | 'n' | 'p' | 'r' | 't' => true,
+            _ => false,
+        }
+}
+
+#[inline]
+fn en_valid_li(c: char) -> bool {
+    match c {
+        'c' | 'd' | 'e' | 'g' | 'h' | 'k' | 'm' | 'n' | 'r' | 't' => true,
+        _ => false,
+    }
+}
+
+// a short syllable ends the word (given as its first n letters)
+#[inline]
+fn en_short_syllable(w: &[char], n: usize) -> bool {
+    if n == 2 {
+        return en_vowel(w[0]) && !en_vowel(w[1]);
+    }
+
+    n >= 3
+        && !en_vowel(w[n - 3])
+        && en_vowel(w[n - 2])
+        && !en_vowel(w[n - 1])
+        && w[n - 1] != 'w'
+        && w[n - 1] != 'x'
+        && w[n - 1] != 'Y'
+}
+
+fn en_exception(word: &str) -> Option<&'static str> {
+    Some(match word {
+        "skis" => "ski",
+        "skies" => "sky",
+        "dying" => "die",
+        "lying" => "lie",
+        "tying" => "tie",
+        "idly" => "idl",
+        "gently" => "gentl",
+        "ugly" => "ugli",
+        "early" => "earli",
+        "only" => "onli",
+        "singly" => "singl",
+        "sky" => "sky",
+        "news" => "news",
+        "howe" => "howe",
+        "atlas" => "atlas",
+        "cosmos" => "cosmos",
+        "bias" => "bias",
+        "andes" => "andes",
+        _ => return None,
+    })
+}
+
+impl Stemmer for EnglishStemmer {
+    fn stem(&self, word: &str) -> String {
+        if word.len() <= 2 || !word.chars().all(|c| c.is_ascii_lowercase() || c == '\'') {
+            return word.to_string();
+        }
+
+        if let Some(stem) = en_exception(word) {
+            return stem.to_string();
+        }
+
+        let mut w: Vec<char> = word.trim_start_matches('\'').chars().collect();
+        if w.len() <= 2 {
+            return w.into_iter().collect();
+        }
+
+        // y as a consonant
+        for i in 0..w.len() {
+            if w[i] == 'y' && (i == 0 || en_vowel(w[i - 1])) {
+                w[i] = 'Y';
+            }
+        }
+
+        let r1 = if word.starts_with("gener") || word.starts_with("arsen") {
+            5
+        } else if word.starts_with("commun") {
+            6
+        } else {
+            region_start(&w, 0, &en_vowel)
+        };
+        let r2 = region_start(&w, r1, &en_vowel);
+
+        // step 0
+        if let Some(s) = longest_suffix(&w, &["'", "'s", "'s'"]) {
+            let n = w.len() - s.len();
+            w.truncate(n);
+        }
+
+        // step 1a
+        if ends_with(&w, "sses") {
+            replace_suffix(&mut w, 4, "ss");
+        } else if ends_with(&w, "ied") || ends_with(&w, "ies") {
+            let replacement = if w.len() > 4 { "i" } else { "ie" };
+            replace_suffix(&mut w, 3, replacement);
+        } else if ends_with(&w, "s")
+            && !ends_with(&w, "us")
+            && !ends_with(&w, "ss")
+            && w[..w.len() - 2].iter().any(|c| en_vowel(*c))
+        {
+            w.pop();
+        }
+
+        let stem: String = w.iter().collect();
+        match stem.as_str() {
+            "inning" | "outing" | "canning" | "herring" | "earring" | "proceed" | "exceed"
+            | "succeed" => return stem,
+            _ => (),
+        }
+
+        // step 1b
+        if let Some(s) = longest_suffix(&w, &["eed", "eedly", "ed", "edly", "ing", "ingly"]) {
+            let n = w.len() - s.len();
+            if s == "eed" || s == "eedly" {
+                if n >= r1 {
+                    replace_suffix(&mut w, s.len(), "ee");
+                }
+            } else if w[..n].iter().any(|c| en_vowel(*c)) {
+                w.truncate(n);
+                if ends_with(&w, "at") || ends_with(&w, "bl") || ends_with(&w, "iz") {
+                    w.push('e');
+                } else if en_double(&w) {
+                    w.pop();
+                } else if r1 >= w.len() && en_short_syllable(&w, w.len()) {
+                    w.push('e');
+                }
+            }
+        }
+
+        // step 1c
+        let n = w.len();
+        if n > 2 && (w[n - 1] == 'y' || w[n - 1] == 'Y') && !en_vowel(w[n - 2]) {
+            w[n - 1] = 'i';
+        }
+
+        // step 2
+        let step2 = [
+            ("tional", "tion"),
+            ("enci", "ence"),
+            ("anci", "ance"),
+            ("abli", "able"),
+            ("entli", "ent"),
+            ("izer", "ize"),
+            ("ization", "ize"),
+            ("ational", "ate"),
+            ("ation", "ate"),
+            ("ator", "ate"),
+            ("alism", "al"),
+            ("aliti", "al"),
+            ("alli", "al"),
+            ("fulness", "ful"),
+            ("ousli", "ous"),
+            ("ousness", "ous"),
+            ("iveness", "ive"),
+            ("iviti", "ive"),
+            ("biliti", "ble"),
+            ("bli", "ble"),
+            ("ogi", "og"),
+            ("fulli", "ful"),
+            ("lessli", "less"),
+            ("li", ""),
+        ];
+        let suffixes: Vec<&str> = step2.iter().map(|(s, _)| *s).collect();
+        if let Some(s) = longest_suffix(&w, &suffixes) {
+            let n = w.len() - s.len();
+            let replacement = step2.iter().find(|(x, _)| *x == s).unwrap().1;
+            if n >= r1 {
+                if s == "ogi" {
+                    if n > 0 && w[n - 1] == 'l' {
+                        replace_suffix(&mut w, s.len(), replacement);
+                    }
+                } else if s == "li" {
+                    if n > 0 && en_valid_li(w[n - 1]) {
+                        w.truncate(n);
+                    }
+                } else {
+                    replace_suffix(&mut w, s.len(), replacement);
+                }
+            }
+        }
+
+        // step 3
+        let step3 = [
+            ("tional", "tion"),
+            ("ational", "ate"),
+            ("alize", "al"),
+            ("icate", "ic"),
+            ("iciti", "ic"),
+            ("ical", "ic"),
+            ("ful", ""),
+            ("ness", ""),
+            ("ative", ""),
+        ];
+        let suffixes: Vec<&str> = step3.iter().map(|(s, _)| *s).collect();
+        if let Some(s) = longest_suffix(&w, &suffixes) {
+            let n = w.len() - s.len();
+            let replacement = step3.iter().find(|(x, _)| *x == s).unwrap().1;
+            if n >= r1 && (s != "ative" || n >= r2) {
+                replace_suffix(&mut w, s.len(), replacement);
+            }
+        }
+
+        // step 4
+        let step4 = [
+            "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ism",
+            "ate", "iti", "ous", "ive", "ize", "ion",
+        ];
+        if let Some(s) = longest_suffix(&w, &step4) {
+            let n = w.len() - s.len();
+            if n >= r2 && (s != "ion" || (n > 0 && (w[n - 1] == 's' || w[n - 1] == 't'))) {
+                w.truncate(n);
+            }
+        }
+
+        // step 5
+        let n = w.len();
+        if n > 0 && w[n - 1] == 'e' {
+            if n - 1 >= r2 || (n - 1 >= r1 && !en_short_syllable(&w, n - 1)) {
+                w.pop();
+            }
+        } else if n > 1 && w[n - 1] == 'l' && n - 1 >= r2 && w[n - 2] == 'l' {
+            w.pop();
+        }
+
+        w.into_iter()
+            .map(|c| if c == 'Y' { 'y' } else { c })
+            .collect()
+    }
+}
+
+/*
+ German
+*/
+
+pub struct GermanStemmer;
+
+#[inline]
+fn de_vowel(c: char) -> bool {
+    match c {
+        'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'ä' | 'ö' | 'ü' => true,
+        _ => false,
+    }
+}
+
+#[inline]
+fn de_valid_s(c: char) -> bool {
+    match c {
+        'b' | 'd' | 'f' | 'g' | 'h' | 'k' | 'l' | 'm' | 'n' | 'r' | 't' => true,
+        _ => false,
+    }
+}
+
+#[inline]
+fn de_valid_st(c: char) -> bool {
+    c != 'r' && de_valid_s(c)
+}
+
+impl Stemmer for GermanStemmer {
+    fn stem(&self, word: &str) -> String {
+        if word.chars().count() <= 2
+            || !word
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || "äöüß".contains(c))
+        {
+            return word.to_string();
+        }
+
+        let chars: Vec<char> = word.replace("ß", "ss").chars().collect();
+
+        // german2: ae, oe, ue are umlauts, ue not after q
+        let mut w: Vec<char> = Vec::with_capacity(chars.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if i + 1 < chars.len() && chars[i + 1] == 'e' {
+                let umlaut = match c {
+                    'a' => Some('ä'),
+                    'o' => Some('ö'),
+                    'u' if i == 0 || chars[i - 1] != 'q' => Some('ü'),
+                    _ => None,
+                };
+                if let Some(u) = umlaut {
+                    w.push(u);
+                    i += 2;
+                    continue;
+                }
+            }
+            w.push(c);
+            i += 1;
+        }
+
+        // u and y between vowels as consonants
+        for i in 1..w.len().saturating_sub(1) {
+            if de_vowel(w[i - 1]) && de_vowel(w[i + 1]) {
+                if w[i] == 'u' {
+                    w[i] = 'U';
+                } else if w[i] == 'y' {
+                    w[i] = 'Y';
+                }
+            }
+        }
+
+        let r1 = util::max(region_start(&w, 0, &de_vowel), 3);
+        let r2 = region_start(&w, region_start(&w, 0, &de_vowel), &de_vowel);
+
+        // step 1
+        if let Some(s) = longest_suffix(&w, &["em", "ern", "er", "e", "en", "es", "s"]) {
+            let n = w.len() - s.len();
+            if n >= r1 {
+                match s {
+                    "em" | "ern" | "er" => w.truncate(n),
+                    "e" | "en" | "es" => {
+                        w.truncate(n);
+                        if ends_with(&w, "niss") {
+                            w.pop();
+                        }
+                    }
+                    _ => {
+                        if n > 0 && de_valid_s(w[n - 1]) {
+                            w.truncate(n);
+                        }
+                    }
+                }
+            }
+        }
+
+        // step 2
+        if let Some(s) = longest_suffix(&w, &["en", "er", "est", "st"]) {
+            let n = w.len() - s.len();
+            if n >= r1 {
+                if s != "st" {
+                    w.truncate(n);
+                } else if n > 3 && de_valid_st(w[n - 1]) {
+                    w.truncate(n);
+                }
+            }
+        }
+
+        // step 3
+        if let Some(s) = longest_suffix(
+            &w,
+            &["end", "ung", "ig", "ik", "isch", "lich", "heit", "keit"],
+        ) {
+            let n = w.len() - s.len();
+            if n >= r2 {
+                match s {
+                    "end" | "ung" => {
+                        w.truncate(n);
+                        if ends_with(&w, "ig") && n - 2 >= r2 && !(n > 2 && w[n - 3] == 'e') {
+                            w.truncate(n - 2);
+                        }
+                    }
+                    "ig" | "ik" | "isch" => {
+                        if !(n > 0 && w[n - 1] == 'e') {
+                            w.truncate(n);
+                        }
+                    }
+                    "lich" | "heit" => {
+                        w.truncate(n);
+                        if (ends_with(&w, "er") || ends_with(&w, "en")) && n - 2 >= r1 {
+                            w.truncate(n - 2);
+                        }
+                    }
+                    _ => {
+                        w.truncate(n);
+                        if ends_with(&w, "lich") && n - 4 >= r2 {
+                            w.truncate(n - 4);
+                        } else if ends_with(&w, "ig") && n - 2 >= r2 {
+                            w.truncate(n - 2);
+                        }
+                    }
+                }
+            }
+        }
+
+        w.into_iter()
+            .map(|c| match c {
+                'U' => 'u',
+                'Y' => 'y',
+                'ä' => 'a',
+                'ö' => 'o',
+                'ü' => 'u',
+                _ => c,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_english_stemmer() {
+        let s = EnglishStemmer;
+        let cases = vec![
+            ("changing", "chang"),
+            ("change", "chang"),
+            ("changes", "chang"),
+            ("bars", "bar"),
+            ("caresses", "caress"),
+            ("ponies", "poni"),
+            ("ties", "tie"),
+            ("cats", "cat"),
+            ("running", "run"),
+            ("hopping", "hop"),
+            ("hoping", "hope"),
+            ("generously", "generous"),
+            ("relational", "relat"),
+            ("conditional", "condit"),
+            ("happily", "happili"),
+            ("happy", "happi"),
+            ("consignment", "consign"),
+            ("skies", "sky"),
+            ("news", "news"),
+            ("succeeding", "succeed"),
+            ("mac", "mac"),
+            ("os", "os"),
+            ("iphone5", "iphone5"),
+        ];
+        for (word, stem) in cases {
+            assert_eq!(s.stem(word), stem, "word: {}", word);
+        }
+    }
+
+    #[test]
+    fn test_german_stemmer() {
+        let s = GermanStemmer;
+        let cases = vec![
+            ("aufeinanderfolgenden", "aufeinanderfolg"),
+            ("kategorischen", "kategor"),
+            ("haeuser", "haus"),
+            ("häuser", "haus"),
+            ("haus", "haus"),
+            ("ersatzteile", "ersatzteil"),
+            ("kenntnisse", "kenntnis"),
+            ("freundlichkeit", "freundlich"),
+            ("queue", "queu"),
+            ("strasse", "strass"),
+            ("straße", "strass"),
+        ];
+        for (word, stem) in cases {
+            assert_eq!(s.stem(word), stem, "word: {}", word);
+        }
+    }
+}
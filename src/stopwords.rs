@@ -1,23 +1,169 @@
-use fnv::FnvHashSet;
+/*
+    A stopwords list is either a plain text file, one word per line, kept in a hash set,
+    or an fst set (a file with the `.fst` extension) for large lists. The list can be empty,
+    i.e. no word of the query is treated as a stopword.
+*/
+
+use fnv::{FnvHashMap, FnvHashSet};
+use fst;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
+
+use analyzer::Analyzer;
+use config::Config;
+use util::{BRED, BYELL, ECOL};
+
+// name of the list loaded from `stopwords_file`, used when there is no list for the analyzer
+pub const DEFAULT_LIST: &str = "default";
+
+pub enum Stopwords {
+    Set(FnvHashSet<String>),
+    Fst(fst::Set),
+}
+
+impl Stopwords {
+    pub fn empty() -> Self {
+        Stopwords::Set(FnvHashSet::default())
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        match self {
+            Stopwords::Set(ref set) => set.contains(word),
+            Stopwords::Fst(ref set) => set.contains(word),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Stopwords::Set(ref set) => set.len(),
+            Stopwords::Fst(ref set) => set.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<FnvHashSet<String>> for Stopwords {
+    fn from(set: FnvHashSet<String>) -> Self {
+        Stopwords::Set(set)
+    }
+}
+
+pub fn load(path: &str) -> Result<Stopwords, Error> {
+    if path.ends_with(".fst") {
+        return match fst::Set::from_path(path) {
+            Ok(set) => Ok(Stopwords::Fst(set)),
+            Err(err) => Err(Error::new(ErrorKind::InvalidData, format!("{}", err))),
+        };
+    }
 
-pub fn load(path: &str) -> Result<FnvHashSet<String>, Error> {
     let mut stopwords = FnvHashSet::default();
 
     let f = File::open(path)?;
     let file = BufReader::new(&f);
 
     for line in file.lines() {
-        let sw = line.unwrap();
-        stopwords.insert(sw);
+        let sw = line?;
+        let sw = sw.trim();
+        if !sw.is_empty() {
+            stopwords.insert(sw.to_string());
+        }
     }
 
-    if stopwords.len() == 0 {
-        panic!("Stopwords are empty!");
+    Ok(Stopwords::Set(stopwords))
+}
+
+fn load_or_panic(dir: &str, file_name: &str) -> Stopwords {
+    // an empty file name stands for an intentionally empty list
+    if file_name.is_empty() {
+        return Stopwords::empty();
     }
 
-    Ok(stopwords)
+    let path = &format!("{}/{}", dir, file_name);
+    match load(path) {
+        Ok(stopwords) => stopwords,
+        Err(_) => panic!([BYELL, "Failed to load stopwords: ", ECOL, BRED, path, ECOL].join("")),
+    }
+}
+
+// Loads all stopwords lists from the config: the default one from `stopwords_file`
+// and the named ones (per language or per index) from `stopwords_files`.
+pub fn load_lists(dir: &str, c: &Config) -> FnvHashMap<String, Stopwords> {
+    let mut lists = FnvHashMap::default();
+    lists.insert(
+        DEFAULT_LIST.to_string(),
+        load_or_panic(dir, &c.stopwords_file),
+    );
+
+    for &(ref name, ref file_name) in c.stopwords_files.iter() {
+        lists.insert(name.to_string(), load_or_panic(dir, file_name));
+    }
+
+    lists
+}
+
+// The list named after the analyzer is used if there is one, the default list otherwise.
+pub fn select_name<T>(lists: &FnvHashMap<String, T>, analyzer: &dyn Analyzer) -> String {
+    if lists.contains_key(analyzer.name()) {
+        analyzer.name().to_string()
+    } else {
+        DEFAULT_LIST.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyzer::{DefaultAnalyzer, FrenchAnalyzer};
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn test_load() {
+        let dir = env::temp_dir().join("qpick_stopwords_test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let txt_path = dir.join("stopwords.txt");
+        let mut f = File::create(&txt_path).unwrap();
+        f.write_all(b"the\nof\n\n").unwrap();
+
+        let stopwords = load(txt_path.to_str().unwrap()).unwrap();
+        assert_eq!(stopwords.len(), 2);
+        assert!(stopwords.contains("the"));
+        assert!(!stopwords.contains("hotels"));
+
+        let empty_path = dir.join("empty.txt");
+        File::create(&empty_path).unwrap();
+        let stopwords = load(empty_path.to_str().unwrap()).unwrap();
+        assert!(stopwords.is_empty());
+
+        let fst_path = dir.join("stopwords.fst");
+        let wtr = ::std::io::BufWriter::new(File::create(&fst_path).unwrap());
+        let mut build = fst::SetBuilder::new(wtr).unwrap();
+        build.extend_iter(vec!["de", "la", "le"]).unwrap();
+        build.finish().unwrap();
+
+        let stopwords = load(fst_path.to_str().unwrap()).unwrap();
+        assert_eq!(stopwords.len(), 3);
+        assert!(stopwords.contains("la"));
+        assert!(!stopwords.contains("the"));
+
+        assert!(load(dir.join("missing.txt").to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_select_name() {
+        let mut lists = FnvHashMap::default();
+        lists.insert(DEFAULT_LIST.to_string(), Stopwords::empty());
+        lists.insert("fr".to_string(), Stopwords::empty());
+
+        assert_eq!(select_name(&lists, &FrenchAnalyzer), "fr");
+        assert_eq!(select_name(&lists, &DefaultAnalyzer), DEFAULT_LIST);
+    }
 }
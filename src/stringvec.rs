@@ -3,38 +3,35 @@
 
  String vector file layout:
 
- [8B off_a][[6B off_0] [6B off_1]  ... [6B off_n][[string 1][string 2] ... [string n]]
- ^         ^                                     ^          ^                        ^
- |         |                                     |          |                        |
- 0 ------- 8 ------------------------- [8 + off_a] --- [8 + off_a + off_1] --- [8 + off_a + off_n]
+ [8B magic][[string 1][string 2] ... [string n]][[6B off_0] [6B off_1]  ... [6B off_n]][8B off_a]
+ ^         ^          ^                        ^                                      ^
+ |         |          |                        |                                      |
+ 0 ------- 8 --- [8 + off_1] ----------- [8 + off_n] = off_a ----------------- [off_a + (n + 1) * 6]
 
- - first 8 bytes (u64) are used to store size of the offsets' array (off_a):
+ - first 8 bytes are the magic of the layout (RAW_MAGIC)
 
-       off_a = [number_of_strings + 1] * offset_size
+ - strings start at byte [8] and end at [8 + off_n], offsets are relative to the first string
 
-   where offset_size is 6 bytes and the +1 slot is for the offset zero, set to 0 value
+ - offsets (off_0 stores value 0) start at the off_a address, that is stored in the last 8 bytes
+   (u64) of the file, so that the strings are streamed into the file and offsets are appended
 
- - offsets start at byte [8] and end at byte [8 + off_a - 1]
+ - offset size is 6 bytes, there are [number_of_strings + 1] offsets
 
- - offset for the first string (*off_1) is stored at the [off_1] address (off_0 stores value 0)
-
- - offset for the last string (*off_n) is stored at the [off_n] address
-
- - strings start at [8 + off_a] and end at [8 + off_a + off_n] address, that is also the EOF.
+ Files of the legacy layout, without the magic and with the offsets first, are loaded as well:
 
+ [8B offsets size][[6B off_0] [6B off_1]  ... [6B off_n][[string 1][string 2] ... [string n]]
 
    write:
-       // The comment bellow for the file string vec layout
-       let mut str_vec_writer = StrVecWriter::init();
-       for query in string_vec.into_iter() {
+       let mut str_vec_writer = StrVecWriter::create(&file_path)?;
+       for query in string_vec.iter() {
            str_vec_writer.add(query);
        }
-       str_vec_writer.write_to_file(&file_path);
+       str_vec_writer.finish()?;
 
    read:
-       // loads strings and their offsets from files
-       let offsets = unsafe { memmap::Mmap::map(&File::open("./offsets.bin").unwrap()).unwrap() };
-       let str_vec = StrVec::load("./queries.bin", &offsets);
+       let str_vec = StrVec::load(&file_path);
+       // or in memory
+       let str_vec = StrVec::from_vec(string_vec);
 
    access:
        // string indexes start from 0, access the first string:
@@ -42,18 +39,39 @@
        // access the 10th string
        str_vec[9] -> &str
 
+ Block compressed string vector file layout, for large i2q files:
+
+ [8B magic][8B block_size][block 0][block 1] ... [block m][8B blk_off_0] ... [8B blk_off_m+1][8B n][8B blk_off_a]
+
+ - strings are split into blocks of block_size strings (the last one may be shorter), each block
+   is compressed with lz4 separately, a decompressed block holds u32 string end offsets
+   followed by the strings
+
+ - block offsets are addresses of the blocks in the file, followed by the number of strings (n)
+   and the address of the block offsets (blk_off_a) at the end of the file
+
+ - StrVec::load detects the layout by the magic, strings of the compressed layout are accessed
+   with str_vec.get(idx), the recently used decompressed blocks are kept in a small LRU cache
+
+   write:
+       let mut str_vec_writer = StrVecWriter::with_block_size(&file_path, BLOCK_SIZE)?;
+
 */
 
 use std::fs::{read_dir, File, OpenOptions};
 use std::io::prelude::*;
-use std::io::{BufReader, BufWriter, Error, SeekFrom};
+use std::io::{BufReader, BufWriter, Error};
 
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use fnv::FnvHashMap;
 use memmap::Mmap;
 use pbr::ProgressBar;
 use shard::parse_query_line;
+use std::borrow::Cow;
 use std::mem::{size_of, MaybeUninit};
+use std::ops::{Deref, Range};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 use util;
 
@@ -67,6 +85,12 @@ pub static NOF_MSG: &str = "No such file or directory: ";
 pub const BYTES_PER_OFFSET: usize = 6;
 pub const RAND_ALFANUM_LEN: usize = 24;
 
+pub const RAW_MAGIC: &[u8; 8] = b"QSTRVEC2";
+pub const COMPRESSED_MAGIC: &[u8; 8] = b"QSTRVLZ4";
+pub const WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+pub const BLOCK_SIZE: usize = 64; // strings per block of the compressed layout
+pub const CACHE_BLOCKS: usize = 256; // decompressed blocks kept in the LRU cache
+
 #[repr(C, packed)]
 #[derive(Debug, Copy, Clone)]
 pub struct Offset(pub [u8; BYTES_PER_OFFSET]);
@@ -106,11 +130,160 @@ impl Offset {
     }
 }
 
+// bytes of a string vector file or of an in-memory string vector
+enum Data {
+    Mmap(Mmap),
+    Vec(Vec<u8>),
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Data::Mmap(ref mmap) => &mmap[..],
+            Data::Vec(ref vec) => &vec[..],
+        }
+    }
+}
+
 pub struct StrVec {
     strings_addr: usize,
     // Vec<Offset> is not used due to a slow loading speed during initialization
     offsets: Vec<u8>,
-    strings: Mmap,
+    strings: Data,
+    blocks: Option<Blocks>, // the compressed layout, strings are blocks of the whole file
+}
+
+// least recently used decompressed blocks
+struct BlockCache {
+    capacity: usize,
+    tick: u64,
+    blocks: FnvHashMap<usize, (u64, Arc<Vec<u8>>)>,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity: capacity,
+            tick: 0,
+            blocks: FnvHashMap::default(),
+        }
+    }
+
+    #[inline]
+    fn get(&mut self, block_idx: usize) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.blocks.get_mut(&block_idx).map(|(used, block)| {
+            *used = tick;
+            block.clone()
+        })
+    }
+
+    fn insert(&mut self, block_idx: usize, block: Arc<Vec<u8>>) {
+        if self.blocks.len() >= self.capacity && !self.blocks.contains_key(&block_idx) {
+            let lru_idx = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(idx, _)| *idx);
+            if let Some(lru_idx) = lru_idx {
+                self.blocks.remove(&lru_idx);
+            }
+        }
+        self.tick += 1;
+        self.blocks.insert(block_idx, (self.tick, block));
+    }
+}
+
+struct Blocks {
+    block_size: usize,
+    nr_strings: usize,
+    offsets: Vec<u64>, // addresses of the blocks, the last one is the end of the last block
+    cache: Mutex<BlockCache>,
+}
+
+impl Blocks {
+    fn load(data: &[u8], path: &Path) -> Self {
+        let invalid = |msg: &str| -> ! {
+            panic!("Invalid compressed string vector file {:?}: {}!", path, msg)
+        };
+
+        let header_size = COMPRESSED_MAGIC.len() + size_of::<u64>();
+        let footer_size = 2 * size_of::<u64>();
+        if data.len() < header_size + footer_size {
+            invalid("too short");
+        }
+
+        let block_size = LittleEndian::read_u64(&data[COMPRESSED_MAGIC.len()..]) as usize;
+        let footer = data.len() - footer_size;
+        let nr_strings = LittleEndian::read_u64(&data[footer..]) as usize;
+        let offsets_addr = LittleEndian::read_u64(&data[footer + size_of::<u64>()..]) as usize;
+
+        if block_size == 0 {
+            invalid("zero block size");
+        }
+        let nr_blocks = (nr_strings + block_size - 1) / block_size;
+        if offsets_addr < header_size || offsets_addr + (nr_blocks + 1) * size_of::<u64>() != footer
+        {
+            invalid("block offsets don't match the number of strings");
+        }
+
+        let offsets: Vec<u64> = data[offsets_addr..footer]
+            .chunks(size_of::<u64>())
+            .map(|o| LittleEndian::read_u64(o))
+            .collect();
+        if offsets.windows(2).any(|o| o[0] > o[1]) || offsets[nr_blocks] as usize > offsets_addr {
+            invalid("block offsets out of order");
+        }
+
+        Blocks {
+            block_size: block_size,
+            nr_strings: nr_strings,
+            offsets: offsets,
+            cache: Mutex::new(BlockCache::new(CACHE_BLOCKS)),
+        }
+    }
+
+    fn block(&self, data: &[u8], block_idx: usize) -> Arc<Vec<u8>> {
+        if let Some(block) = self.cache.lock().unwrap().get(block_idx) {
+            return block;
+        }
+
+        // decompressed without the lock, a block may be decompressed by two threads at once
+        let (begin, end) = (
+            self.offsets[block_idx] as usize,
+            self.offsets[block_idx + 1] as usize,
+        );
+        let block = Arc::new(
+            lz4_flex::decompress_size_prepended(&data[begin..end]).expect(&format!(
+                "Failed to decompress string vector block {}",
+                block_idx
+            )),
+        );
+        self.cache.lock().unwrap().insert(block_idx, block.clone());
+
+        block
+    }
+
+    fn get(&self, data: &[u8], idx: usize) -> String {
+        let (block_idx, str_idx) = (idx / self.block_size, idx % self.block_size);
+        let block = self.block(data, block_idx);
+
+        let block_len = ::std::cmp::min(
+            self.block_size,
+            self.nr_strings - block_idx * self.block_size,
+        );
+        let strings_addr = block_len * size_of::<u32>();
+        let end = |i: usize| LittleEndian::read_u32(&block[i * size_of::<u32>()..]) as usize;
+        let begin = if str_idx == 0 { 0 } else { end(str_idx - 1) };
+
+        str::from_utf8(&block[strings_addr + begin..strings_addr + end(str_idx)])
+            .unwrap()
+            .to_string()
+    }
 }
 
 use std::ops::Index;
@@ -120,6 +293,17 @@ impl Index<usize> for StrVec {
 
     #[inline]
     fn index(&self, idx: usize) -> &str {
+        assert!(
+            self.blocks.is_none(),
+            "Strings of a compressed string vector are accessed with StrVec::get"
+        );
+        assert!(
+            idx < self.len(),
+            "Index {} out of range for a string vector of {} strings",
+            idx,
+            self.len()
+        );
+
         let b = idx * BYTES_PER_OFFSET;
         let (m, e) = (b + BYTES_PER_OFFSET, b + 2 * BYTES_PER_OFFSET);
 
@@ -133,33 +317,58 @@ impl Index<usize> for StrVec {
 }
 
 impl StrVec {
-    pub fn load(path: &Path) -> Self {
-        let mut buf = vec![0u8; size_of::<u64>()];
+    // number of strings, the offsets start with a zero offset
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self.blocks {
+            Some(ref blocks) => blocks.nr_strings,
+            None => (self.offsets.len() / BYTES_PER_OFFSET).saturating_sub(1),
+        }
+    }
 
-        // read offsets size
-        let ref vec_file = OpenOptions::new().read(true).open(&path).unwrap();
-        let mut handle = vec_file.take(size_of::<u64>() as u64);
-        let mut bytes_read = handle.read(&mut buf).unwrap_or(0);
-        assert!(
-            bytes_read == size_of::<u64>(),
-            "Failed to read offsets size from the file {:?}",
-            path
-        );
-        let offsets_size = LittleEndian::read_u64(&buf);
-        let strings_addr = bytes_read + offsets_size as usize;
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.blocks.is_some()
+    }
 
-        let mut offsets_data = vec![0u8; offsets_size as usize];
-        handle = vec_file.take(offsets_size);
-        bytes_read = handle.read(&mut offsets_data).unwrap_or(0);
-        assert!(
-            bytes_read == offsets_size as usize,
-            "Failed to read offsets data from the file {:?}, bytes_read: {:?}, expected: {:?}",
-            path,
-            bytes_read,
-            offsets_size
-        );
+    // string at the index for both layouts, borrowed from the uncompressed one,
+    // None if the index is out of range
+    #[inline]
+    pub fn get(&self, idx: usize) -> Option<Cow<'_, str>> {
+        if idx >= self.len() {
+            return None;
+        }
+
+        match self.blocks {
+            Some(ref blocks) => Some(Cow::Owned(blocks.get(&self.strings, idx))),
+            None => Some(Cow::Borrowed(&self[idx])),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        let strings = unsafe {
+    #[inline]
+    pub fn iter(&self) -> StrVecIter<'_> {
+        self.range(0..self.len())
+    }
+
+    // strings in the index range, clamped to the length
+    #[inline]
+    pub fn range(&self, range: Range<usize>) -> StrVecIter<'_> {
+        let end = ::std::cmp::min(range.end, self.len());
+
+        StrVecIter {
+            str_vec: self,
+            idx: ::std::cmp::min(range.start, end),
+            end: end,
+        }
+    }
+
+    pub fn load(path: &Path) -> Self {
+        let data = unsafe {
             Mmap::map(
                 &OpenOptions::new().read(true).open(path).expect(
                     &[
@@ -175,154 +384,250 @@ impl StrVec {
             )
             .unwrap()
         };
-        util::advise_ram(&strings[..]).expect(&format!("Advisory failed for i2q {:?}", &path));
+        util::advise_ram(&data[..]).expect(&format!("Advisory failed for i2q {:?}", &path));
+        assert!(
+            data.len() >= size_of::<u64>(),
+            "Failed to read the layout of the file {:?}",
+            path
+        );
+
+        if &data[..COMPRESSED_MAGIC.len()] == &COMPRESSED_MAGIC[..] {
+            let blocks = Blocks::load(&data, path);
+
+            return StrVec {
+                strings_addr: 0,
+                offsets: vec![],
+                strings: Data::Mmap(data),
+                blocks: Some(blocks),
+            };
+        }
+
+        let (strings_addr, offsets_data) = if &data[..RAW_MAGIC.len()] == &RAW_MAGIC[..] {
+            // offsets at the address from the footer
+            let footer = data.len().saturating_sub(size_of::<u64>());
+            let offsets_addr = if footer >= RAW_MAGIC.len() {
+                LittleEndian::read_u64(&data[footer..]) as usize
+            } else {
+                0
+            };
+            assert!(
+                offsets_addr >= RAW_MAGIC.len()
+                    && offsets_addr < footer
+                    && (footer - offsets_addr) % BYTES_PER_OFFSET == 0,
+                "Failed to read offsets data from the file {:?}, offsets address: {:?}",
+                path,
+                offsets_addr
+            );
+
+            (RAW_MAGIC.len(), data[offsets_addr..footer].to_vec())
+        } else {
+            // the legacy layout, offsets size and offsets first
+            let offsets_size = LittleEndian::read_u64(&data) as usize;
+            let strings_addr = size_of::<u64>() + offsets_size;
+            assert!(
+                strings_addr <= data.len(),
+                "Failed to read offsets data from the file {:?}, expected: {:?}",
+                path,
+                offsets_size
+            );
+
+            (strings_addr, data[size_of::<u64>()..strings_addr].to_vec())
+        };
 
         StrVec {
             strings_addr: strings_addr,
-            strings: strings,
+            strings: Data::Mmap(data),
             offsets: offsets_data,
+            blocks: None,
         }
     }
+
+    // in-memory string vector, for tests and small indexes
+    pub fn from_vec(strings: Vec<String>) -> Self {
+        let mut offsets = Vec::with_capacity((strings.len() + 1) * BYTES_PER_OFFSET);
+        let mut data = vec![];
+
+        offsets.extend_from_slice(&Offset::from(0).0);
+        for s in strings.iter() {
+            data.extend_from_slice(s.as_bytes());
+            offsets.extend_from_slice(&Offset::from(data.len()).0);
+        }
+
+        StrVec {
+            strings_addr: 0,
+            strings: Data::Vec(data),
+            offsets: offsets,
+            blocks: None,
+        }
+    }
+}
+
+pub struct StrVecIter<'a> {
+    str_vec: &'a StrVec,
+    idx: usize,
+    end: usize,
 }
 
+impl<'a> Iterator for StrVecIter<'a> {
+    type Item = Cow<'a, str>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        if self.idx >= self.end {
+            return None;
+        }
+        self.idx += 1;
+
+        self.str_vec.get(self.idx - 1)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.idx, Some(self.end - self.idx))
+    }
+}
+
+impl<'a> ExactSizeIterator for StrVecIter<'a> {}
+
+impl<'a> IntoIterator for &'a StrVec {
+    type Item = Cow<'a, str>;
+    type IntoIter = StrVecIter<'a>;
+
+    fn into_iter(self) -> StrVecIter<'a> {
+        self.iter()
+    }
+}
+
+// Streams strings into a string vector file, the offsets are written by finish at the end,
+// a file that is not finished has no footer and fails to load
 pub struct StrVecWriter {
-    tmp_off_name: String,
-    tmp_str_name: String,
-    off_writer: BufWriter<File>,
-    str_writer: BufWriter<File>,
+    writer: BufWriter<File>,
+    bytes_written: u64,
+    nr_strings: usize,
+    offsets: Vec<u8>,     // string offsets of the uncompressed layout
+    block_size: usize,    // strings per block of the compressed layout, 0 if uncompressed
+    block_ends: Vec<u32>, // string ends of the current block
+    block: Vec<u8>,       // strings of the current block
+    block_offsets: Vec<u64>,
 }
 
 impl StrVecWriter {
-    pub fn init() -> Self {
-        let mut str_vec_writer = StrVecWriter::new();
-        // add zero offset for an empty file
-        str_vec_writer.add_offset();
-
-        str_vec_writer
-    }
-
-    fn new() -> Self {
-        let offsets_file = util::tmp_file_path(&"tmp_", &".bin", RAND_ALFANUM_LEN);
-        let strings_file = util::tmp_file_path(&"tmp_", &".bin", RAND_ALFANUM_LEN);
-
-        StrVecWriter {
-            tmp_off_name: offsets_file.to_str().unwrap().to_string(),
-            tmp_str_name: strings_file.to_str().unwrap().to_string(),
-            off_writer: BufWriter::new(
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(offsets_file)
-                    .unwrap(),
-            ),
-            str_writer: BufWriter::new(
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(strings_file)
-                    .unwrap(),
-            ),
-        }
-    }
-
-    fn add_offset(&mut self) -> usize {
-        // A race condition, if multiple writers access the same file.
-        let offset = self.str_writer.seek(SeekFrom::End(0)).unwrap() as usize;
-        assert!(
-            Offset::max_value() > offset,
-            "File exceeds max byte size: {}",
-            Offset::max_value()
-        );
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        StrVecWriter::with_block_size(path, 0)
+    }
 
-        &self
-            .off_writer
-            .write_all(&Offset::from(offset).0)
-            .expect("Unable to query offset");
-        &self.off_writer.flush().expect("Offset flush failed!");
+    // block compressed if the block size is not zero
+    pub fn with_block_size(path: &Path, block_size: usize) -> Result<Self, Error> {
+        let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, File::create(path)?);
+        let mut offsets = vec![];
+        let mut block_offsets = vec![];
+
+        let bytes_written;
+        if block_size > 0 {
+            writer.write_all(COMPRESSED_MAGIC)?;
+            writer.write_u64::<LittleEndian>(block_size as u64)?;
+            bytes_written = (COMPRESSED_MAGIC.len() + size_of::<u64>()) as u64;
+            block_offsets.push(bytes_written);
+        } else {
+            writer.write_all(RAW_MAGIC)?;
+            bytes_written = RAW_MAGIC.len() as u64;
+            // zero offset for an empty file
+            offsets.extend_from_slice(&Offset::from(0).0);
+        }
 
-        offset
+        Ok(StrVecWriter {
+            writer: writer,
+            bytes_written: bytes_written,
+            nr_strings: 0,
+            offsets: offsets,
+            block_size: block_size,
+            block_ends: vec![],
+            block: vec![],
+            block_offsets: block_offsets,
+        })
     }
 
     #[inline]
-    pub fn add(&mut self, s: String) -> usize {
-        // A race condition, if multiple writers access the same file.
-        &self
-            .str_writer
-            .write_all(&s.as_bytes())
-            .expect("Unable to write query text");
-        &self.str_writer.flush().expect("String flush failed!");
-        let _offset = self.add_offset();
+    pub fn add(&mut self, s: &str) -> usize {
+        if self.block_size > 0 {
+            self.block.extend_from_slice(s.as_bytes());
+            assert!(
+                self.block.len() < u32::max_value() as usize,
+                "Block exceeds max byte size: {}",
+                u32::max_value()
+            );
+            self.block_ends.push(self.block.len() as u32);
+
+            if self.block_ends.len() == self.block_size {
+                self.write_block();
+            }
+        } else {
+            self.writer
+                .write_all(s.as_bytes())
+                .expect("Unable to write query text");
+            self.bytes_written += s.len() as u64;
+
+            let offset = self.bytes_written as usize - RAW_MAGIC.len();
+            assert!(
+                Offset::max_value() > offset,
+                "File exceeds max byte size: {}",
+                Offset::max_value()
+            );
+            self.offsets.extend_from_slice(&Offset::from(offset).0);
+        }
+        self.nr_strings += 1;
 
         s.len()
     }
 
-    #[inline]
-    pub fn write_to_file(&mut self, out_file_path: &Path) -> u64 {
-        let mut out_file = BufWriter::new(
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(out_file_path)
-                .unwrap(),
-        );
+    fn write_block(&mut self) {
+        let mut block =
+            Vec::with_capacity(self.block_ends.len() * size_of::<u32>() + self.block.len());
+        for end in self.block_ends.iter() {
+            block.write_u32::<LittleEndian>(*end).unwrap();
+        }
+        block.extend_from_slice(&self.block);
 
-        let mut bytes_written: u64 = 0;
+        let block = lz4_flex::compress_prepend_size(&block);
+        self.writer
+            .write_all(&block)
+            .expect("Unable to write a block");
+        self.bytes_written += block.len() as u64;
+        self.block_offsets.push(self.bytes_written);
 
-        // write offsets size first
-        let mut buf = Vec::with_capacity(size_of::<u64>());
-        let offsets_size = self.off_writer.seek(SeekFrom::End(0)).unwrap();
-        buf.write_u64::<LittleEndian>(offsets_size).unwrap();
-        out_file.write_all(buf.as_slice()).unwrap();
-        out_file.flush().expect("Failed to flush offset size!");
-        bytes_written += size_of::<u64>() as u64;
+        self.block_ends.clear();
+        self.block.clear();
+    }
 
-        // write offsets
-        let mut offsets_file = OpenOptions::new()
-            .read(true)
-            .open(&self.tmp_off_name)
-            .unwrap();
+    // Writes the offsets and the footer, returns the number of bytes written
+    pub fn finish(mut self) -> Result<u64, Error> {
+        if self.block_size > 0 && !self.block_ends.is_empty() {
+            self.write_block();
+        }
 
-        let mut buf = Vec::with_capacity(offsets_size as usize);
-        // Note: read_to_end appends data to a buffer
-        let bytes_read = offsets_file.read_to_end(&mut buf).unwrap_or(0) as u64;
-        assert!(
-            bytes_read == offsets_size,
-            "Failed to read offsets file, read {:?} out of {:?}",
-            bytes_read,
-            offsets_size,
-        );
-        out_file.write_all(&buf).unwrap();
-        out_file.flush().expect("Failed to flush offsets!");
-        bytes_written += bytes_read;
-
-        // write strings
-        let mut strings_file = OpenOptions::new()
-            .read(true)
-            .open(&self.tmp_str_name)
-            .unwrap();
+        let offsets_addr = self.bytes_written;
+        if self.block_size > 0 {
+            for offset in self.block_offsets.iter() {
+                self.writer.write_u64::<LittleEndian>(*offset)?;
+            }
+            self.writer
+                .write_u64::<LittleEndian>(self.nr_strings as u64)?;
+            self.bytes_written += ((self.block_offsets.len() + 1) * size_of::<u64>()) as u64;
+        } else {
+            self.writer.write_all(&self.offsets)?;
+            self.bytes_written += self.offsets.len() as u64;
+        }
 
-        // TODO assert the last offset == strings file size
-        let strings_size = self.str_writer.seek(SeekFrom::End(0)).unwrap();
-        let mut buf = Vec::with_capacity(strings_size as usize);
-        let bytes_read = strings_file.read_to_end(&mut buf).unwrap_or(0) as u64;
-        assert!(
-            bytes_read == strings_size,
-            "Failed to read offsets file, read {:?} out of {:?}",
-            bytes_read,
-            strings_size,
-        );
-        out_file.write_all(&buf).unwrap();
-        out_file.flush().expect("Failed to flush strings!");
-        bytes_written += bytes_read;
+        self.writer.write_u64::<LittleEndian>(offsets_addr)?;
+        self.bytes_written += size_of::<u64>() as u64;
+        self.writer.flush()?;
 
-        bytes_written
+        Ok(self.bytes_written)
     }
 }
 
-pub fn compile(queries_path: &str, out_file_path: &str) -> Result<(), Error> {
+// Compiles queries into a string vector, block compressed if the block size is not zero
+pub fn compile(queries_path: &str, out_file_path: &str, block_size: usize) -> Result<(), Error> {
     println!("compiling ...");
 
     let queries_path = &Path::new(&queries_path);
@@ -349,7 +654,7 @@ pub fn compile(queries_path: &str, out_file_path: &str) -> Result<(), Error> {
         })
         .collect();
 
-    let mut str_vec_writer = StrVecWriter::init();
+    let mut str_vec_writer = StrVecWriter::with_block_size(&Path::new(&out_file_path), block_size)?;
     let mut pb = ProgressBar::new(query_files.len() as u64);
     for (file_name, query_file) in query_files.into_iter() {
         let reader = BufReader::with_capacity(5 * 1024 * 1024, query_file);
@@ -367,13 +672,13 @@ pub fn compile(queries_path: &str, out_file_path: &str) -> Result<(), Error> {
                     continue;
                 }
             };
-            str_vec_writer.add(query);
+            str_vec_writer.add(&query);
         }
         pb.inc();
     }
     pb.finish_print("done");
 
-    let bytes_written = str_vec_writer.write_to_file(&Path::new(&out_file_path));
+    let bytes_written = str_vec_writer.finish()?;
     println!("total bytes written: {:?}", bytes_written);
 
     Ok(())
@@ -388,10 +693,10 @@ mod tests {
     #[test]
     fn test_queryvec_write_load() {
         let string_vec: Vec<String> = vec![
-            "aaaaaaaaaa".to_string(),           // len: 10
+            "aaaaaaaaaa".to_string(), // len: 10
             "ääääääääää".to_string(), // len: 20
-            "bbbbbbbbbb".to_string(),           // len: 10
-            "cccccccccc".to_string(),           // len: 10
+            "bbbbbbbbbb".to_string(), // len: 10
+            "cccccccccc".to_string(), // len: 10
             "ääääääääää".to_string(), // len: 20
         ];
         // write
@@ -405,29 +710,28 @@ mod tests {
             .unwrap();
         vec_file.lock_exclusive().unwrap();
 
-        let mut str_vec_writer = StrVecWriter::init();
-        for query in string_vec.into_iter() {
+        let mut str_vec_writer = StrVecWriter::create(&vec_file_path).unwrap();
+        for query in string_vec.iter() {
             str_vec_writer.add(query);
         }
-        str_vec_writer.write_to_file(&vec_file_path);
-        let bytes_read;
-        let mut buf = vec![0u8; size_of::<u64>()];
+        str_vec_writer.finish().unwrap();
+        let mut buf = vec![];
         {
-            // read offsets size
-            let vec_file = OpenOptions::new().read(true).open(&vec_file_path).unwrap();
-            let mut handle = vec_file.take(size_of::<u64>() as u64);
-            bytes_read = handle.read(&mut buf).unwrap_or(0);
-            assert_eq!(bytes_read, 8);
-            assert!(
-                bytes_read == size_of::<u64>(),
-                "Failed to read offsets size from the file {:?}",
-                vec_file_path
-            );
+            let mut vec_file = OpenOptions::new().read(true).open(&vec_file_path).unwrap();
+            vec_file.read_to_end(&mut buf).unwrap();
         }
-        let offsets_size = LittleEndian::read_u64(&buf);
+        assert_eq!(&buf[..RAW_MAGIC.len()], &RAW_MAGIC[..]);
+
+        // read offsets address from the footer
+        let footer = buf.len() - size_of::<u64>();
+        let offsets_addr = LittleEndian::read_u64(&buf[footer..]) as usize;
+        assert_eq!(offsets_addr, 78);
+        let offsets_size = footer - offsets_addr;
         assert_eq!(offsets_size, 36);
-        let strings_addr = bytes_read + offsets_size as usize;
-        assert_eq!(strings_addr, 44);
+        assert_eq!(
+            LittleEndian::read_uint(&buf[footer - BYTES_PER_OFFSET..], BYTES_PER_OFFSET),
+            70
+        );
 
         // unlock
         vec_file.unlock().unwrap();
@@ -436,10 +740,10 @@ mod tests {
     #[test]
     fn test_queryvec_access() {
         let string_vec: Vec<String> = vec![
-            "aaaaaaaaaa".to_string(),           // len: 10
+            "aaaaaaaaaa".to_string(), // len: 10
             "ääääääääää".to_string(), // len: 20
-            "bbbbbbbbbb".to_string(),           // len: 10
-            "cccccccccc".to_string(),           // len: 10
+            "bbbbbbbbbb".to_string(), // len: 10
+            "cccccccccc".to_string(), // len: 10
             "ääääääääää".to_string(), // len: 20
         ];
 
@@ -454,22 +758,132 @@ mod tests {
             .unwrap();
         vec_file.lock_exclusive().unwrap();
 
-        let mut str_vec_writer = StrVecWriter::init();
-        for query in string_vec.into_iter() {
+        let mut str_vec_writer = StrVecWriter::create(&vec_file_path).unwrap();
+        for query in string_vec.iter() {
             str_vec_writer.add(query);
         }
-        str_vec_writer.write_to_file(&vec_file_path);
+        str_vec_writer.finish().unwrap();
 
         let str_vec = StrVec::load(&vec_file_path);
         assert_eq!("aaaaaaaaaa", &str_vec[0]);
         assert_eq!("ääääääääää", &str_vec[1]);
         assert_eq!("bbbbbbbbbb", &str_vec[2]);
         assert_eq!("ääääääääää", &str_vec[4]);
+        assert_eq!(5, str_vec.len());
 
         // unlock
         vec_file.unlock().unwrap();
     }
 
+    #[test]
+    fn test_compressed_access() {
+        let string_vec: Vec<String> = (0..100)
+            .map(|i| format!("query {} ääää", i))
+            .chain(vec!["".to_string()].into_iter())
+            .collect();
+
+        let vec_file_path = temp_dir().join("test_queries_compressed.bin");
+        let mut str_vec_writer = StrVecWriter::with_block_size(&vec_file_path, 8).unwrap();
+        for query in string_vec.iter() {
+            str_vec_writer.add(query);
+        }
+        str_vec_writer.finish().unwrap();
+
+        let str_vec = StrVec::load(&vec_file_path);
+        assert!(str_vec.is_compressed());
+        assert_eq!(str_vec.len(), 101);
+        // twice, from the decompressed and the cached blocks
+        for _ in 0..2 {
+            for (idx, query) in string_vec.iter().enumerate() {
+                assert_eq!(str_vec.get(idx).unwrap(), query.as_str());
+            }
+        }
+        assert!(str_vec.get(101).is_none());
+
+        // blocks are evicted from a full cache, the least recently used first
+        let mut cache = BlockCache::new(2);
+        cache.insert(0, Arc::new(vec![0]));
+        cache.insert(1, Arc::new(vec![1]));
+        cache.get(0);
+        cache.insert(2, Arc::new(vec![2]));
+        assert!(cache.get(1).is_none());
+        assert_eq!(*cache.get(0).unwrap(), vec![0]);
+        assert_eq!(*cache.get(2).unwrap(), vec![2]);
+
+        // the uncompressed layout, strings are borrowed
+        let vec_file_path = temp_dir().join("test_queries_uncompressed.bin");
+        let mut str_vec_writer = StrVecWriter::create(&vec_file_path).unwrap();
+        for query in string_vec.iter() {
+            str_vec_writer.add(query);
+        }
+        str_vec_writer.finish().unwrap();
+        let str_vec = StrVec::load(&vec_file_path);
+        assert!(!str_vec.is_compressed());
+        match str_vec.get(3).unwrap() {
+            Cow::Borrowed(query) => assert_eq!(query, "query 3 ääää"),
+            Cow::Owned(_) => panic!("Expected a borrowed string"),
+        }
+    }
+
+    #[test]
+    fn test_legacy_layout_load() {
+        let mut buf = vec![];
+        buf.write_u64::<LittleEndian>(3 * BYTES_PER_OFFSET as u64)
+            .unwrap();
+        for offset in vec![0, 3, 8] {
+            buf.extend_from_slice(&Offset::from(offset).0);
+        }
+        buf.extend_from_slice("abcdefgh".as_bytes());
+
+        let vec_file_path = temp_dir().join("test_queries_legacy.bin");
+        File::create(&vec_file_path)
+            .unwrap()
+            .write_all(&buf)
+            .unwrap();
+
+        let str_vec = StrVec::load(&vec_file_path);
+        assert_eq!(2, str_vec.len());
+        assert_eq!("abc", &str_vec[0]);
+        assert_eq!("defgh", &str_vec[1]);
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let str_vec = StrVec::from_vec(vec![
+            "aaaaaaaaaa".to_string(),
+            "".to_string(),
+            "ääääääääää".to_string(),
+        ]);
+        assert_eq!(3, str_vec.len());
+        assert_eq!("aaaaaaaaaa", &str_vec[0]);
+        assert_eq!("", &str_vec[1]);
+        assert_eq!("ääääääääää", &str_vec[2]);
+        assert!(str_vec.get(3).is_none());
+
+        assert!(StrVec::from_vec(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_iter_range() {
+        let string_vec: Vec<String> = (0..10).map(|i| format!("query {}", i)).collect();
+        let str_vec = StrVec::from_vec(string_vec.clone());
+
+        let strings: Vec<String> = str_vec.iter().map(|s| s.into_owned()).collect();
+        assert_eq!(strings, string_vec);
+        assert_eq!(str_vec.iter().len(), 10);
+        assert_eq!((&str_vec).into_iter().count(), 10);
+
+        let strings: Vec<String> = str_vec.range(3..5).map(|s| s.into_owned()).collect();
+        assert_eq!(strings, vec!["query 3", "query 4"]);
+        // clamped to the length
+        assert_eq!(str_vec.range(8..20).count(), 2);
+        assert_eq!(str_vec.range(20..30).count(), 0);
+        assert_eq!(str_vec.range(5..2).count(), 0);
+
+        assert_eq!(str_vec.get(9).unwrap(), "query 9");
+        assert!(str_vec.get(10).is_none());
+    }
+
     #[test]
     #[should_panic]
     fn test_index_out_of_bounds_panic() {
@@ -485,11 +899,11 @@ mod tests {
         let vec_file = OpenOptions::new().read(true).open(&vec_file_path).unwrap();
         vec_file.lock_exclusive().unwrap();
 
-        let mut str_vec_writer = StrVecWriter::init();
-        for query in string_vec.into_iter() {
+        let mut str_vec_writer = StrVecWriter::create(&vec_file_path).unwrap();
+        for query in string_vec.iter() {
             str_vec_writer.add(query);
         }
-        str_vec_writer.write_to_file(&vec_file_path);
+        str_vec_writer.finish().unwrap();
 
         let str_vec = StrVec::load(&vec_file_path);
         assert_eq!("aaaaaaaaaa", &str_vec[4]);
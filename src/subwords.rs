@@ -0,0 +1,206 @@
+/*
+    Subword vectors, fastText style: a word is wrapped in "<" and ">" and split into character
+    n-grams of MIN_N to MAX_N characters, every n-gram is hashed into one of the buckets.
+    The vector of a word out of the vocabulary is the average of its n-gram bucket vectors.
+
+    The bucket vectors file has the word vectors file format (see word_vec) with a header,
+    bucket vectors are imported from a fastText .bin model, see vectors::import_subwords.
+*/
+
+use std::fs::File;
+use std::path::Path;
+
+use word_vec::{self, VecType, WordVec};
+
+pub const MIN_N: usize = 3; // fastText defaults of the character n-gram lengths
+pub const MAX_N: usize = 6;
+
+// FNV-1a 32 bit hash, bytes are sign extended as in fastText
+#[inline]
+pub fn hash(ngram: &str) -> u32 {
+    ngram.bytes().fold(2166136261u32, |h, b| {
+        (h ^ (b as i8 as i32 as u32)).wrapping_mul(16777619)
+    })
+}
+
+// character n-grams of "<word>", single characters at the boundaries are skipped
+pub fn ngrams(word: &str, min_n: usize, max_n: usize) -> Vec<String> {
+    let word = format!("<{}>", word);
+    let mut offsets: Vec<usize> = word.char_indices().map(|(i, _)| i).collect();
+    offsets.push(word.len());
+    let nr_chars = offsets.len() - 1;
+
+    let mut ngrams = vec![];
+    for i in 0..nr_chars {
+        for n in min_n..max_n + 1 {
+            if i + n > nr_chars {
+                break;
+            }
+            if n == 1 && (i == 0 || i + n == nr_chars) {
+                continue;
+            }
+            ngrams.push(word[offsets[i]..offsets[i + n]].to_string());
+        }
+    }
+
+    ngrams
+}
+
+pub struct SubwordVecs<'a> {
+    vecs: &'a [u8],
+    vec_type: VecType,
+    dim: usize,
+    nr_buckets: usize,
+    min_n: usize,
+    max_n: usize,
+    _data: memmap::Mmap,
+}
+
+impl<'a> SubwordVecs<'a> {
+    pub fn load(path: &Path, min_n: usize, max_n: usize) -> Self {
+        let file = File::open(path).unwrap();
+        let data = unsafe { memmap::Mmap::map(&file).unwrap() };
+
+        let (dim, vec_type) = match word_vec::read_header(&data) {
+            Some((dim, vec_type)) if dim > 0 => (dim, vec_type),
+            _ => panic!("Subword vectors file {:?} has no valid header!", path),
+        };
+
+        let vec_size = vec_type.vec_size(dim);
+        let nr_bytes = data.len() - word_vec::HEADER_SIZE;
+        if nr_bytes == 0 || nr_bytes % vec_size != 0 {
+            panic!(
+                "Invalid subword vectors file {:?}, {} bytes is not a multiple of {}-dimensional {:?} vectors!",
+                path, nr_bytes, dim, vec_type
+            );
+        }
+
+        if min_n == 0 || min_n > max_n {
+            panic!("Invalid subword n-gram lengths: {} to {}!", min_n, max_n);
+        }
+
+        let vecs: &[u8] = unsafe {
+            ::std::slice::from_raw_parts(data[word_vec::HEADER_SIZE..].as_ptr(), nr_bytes)
+        };
+
+        SubwordVecs {
+            vecs: vecs,
+            vec_type: vec_type,
+            dim: dim,
+            nr_buckets: nr_bytes / vec_size,
+            min_n: min_n,
+            max_n: max_n,
+            _data: data,
+        }
+    }
+
+    #[inline]
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nr_buckets
+    }
+
+    #[inline]
+    fn bucket_vec(&self, bucket: usize) -> WordVec {
+        word_vec::word_vec_at(self.vecs, self.vec_type, self.dim, bucket)
+    }
+
+    pub fn buckets(&self, word: &str) -> Vec<usize> {
+        ngrams(word, self.min_n, self.max_n)
+            .iter()
+            .map(|ngram| (hash(ngram) % self.nr_buckets as u32) as usize)
+            .collect()
+    }
+
+    // out = a * average of the n-gram vectors + out, false if the word has no n-grams
+    pub fn add_to(&self, word: &str, a: f32, out: &mut [f32]) -> bool {
+        let buckets = self.buckets(word);
+        if buckets.is_empty() {
+            return false;
+        }
+
+        let a = a / buckets.len() as f32;
+        for bucket in buckets.into_iter() {
+            self.bucket_vec(bucket).add_to(a, out);
+        }
+
+        true
+    }
+
+    #[inline]
+    pub fn get_vec(&self, word: &str) -> Vec<f32> {
+        let mut vec = vec![0.0; self.dim];
+        if !self.add_to(word, 1.0, &mut vec) {
+            return vec![];
+        }
+
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::io::Write;
+
+    fn write_buckets(name: &str, buckets: &Vec<Vec<f32>>) -> ::std::path::PathBuf {
+        let mut buffer: Vec<u8> = vec![];
+        word_vec::write_header(&mut buffer, buckets[0].len(), VecType::F32).unwrap();
+        for vec in buckets.iter() {
+            word_vec::write_vec(&mut buffer, vec, VecType::F32).unwrap();
+        }
+
+        let path = temp_dir().join(format!("test_subwords_{}", name));
+        File::create(&path).unwrap().write_all(&buffer).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(""), 2166136261);
+        assert_eq!(hash("a"), 0xe40c292c);
+        assert_eq!(hash("foobar"), 0xbf9cf968);
+        // non-ascii bytes are sign extended
+        let unsigned = "é"
+            .bytes()
+            .fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+        assert_ne!(hash("é"), unsigned);
+    }
+
+    #[test]
+    fn test_ngrams() {
+        assert_eq!(ngrams("ab", 1, 2), vec!["<a", "a", "ab", "b", "b>"]);
+        assert_eq!(
+            ngrams("paris", 3, 4),
+            vec!["<pa", "<par", "par", "pari", "ari", "aris", "ris", "ris>", "is>"]
+        );
+        assert_eq!(ngrams("été", 4, 6), vec!["<été", "<été>", "été>"]);
+        assert!(ngrams("a", 4, 6).is_empty());
+    }
+
+    #[test]
+    fn test_subword_vecs() {
+        let buckets = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]];
+        let sv = SubwordVecs::load(&write_buckets("vecs", &buckets), 3, 3);
+        assert_eq!(sv.dim(), 2);
+        assert_eq!(sv.len(), 3);
+
+        // "<ab", "ab>"
+        let word_buckets = sv.buckets("ab");
+        assert_eq!(word_buckets.len(), 2);
+
+        let mut e_vec = vec![0.0, 0.0];
+        for b in word_buckets.iter() {
+            e_vec[0] += buckets[*b][0] / 2.0;
+            e_vec[1] += buckets[*b][1] / 2.0;
+        }
+        assert_eq!(sv.get_vec("ab"), e_vec);
+        assert!(sv.get_vec("").is_empty());
+    }
+}
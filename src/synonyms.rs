@@ -1,40 +1,326 @@
+/*
+ Synonyms dictionary, phrase -> [alternative phrase with weight].
+
+ Text format, one entry per line:
+
+   word synonym                      # a single synonym, the original format
+   word synonym_1 synonym_2          # many single word synonyms
+   phrase<TAB>alternative_1[|weight]<TAB>alternative_2[|weight] ...
+
+ e.g.:
+
+   nyc<TAB>new york|0.9<TAB>new york city|0.8
+   new york<TAB>nyc
+
+ Lines starting with '#' and empty lines are skipped, the weight defaults to 1.0.
+
+ Fst format (synonyms file name ends with .fst), created with `compile` from the text format:
+
+   [phrase]\0[alternative] -> weight (f32 bits)
+
+ Alternatives of a phrase are read with a range lookup over [phrase]\0.
+*/
+
 use fnv::FnvHashMap;
+use fst::{Map, MapBuilder};
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::io::Error;
+use std::io::BufWriter;
 use std::path::Path;
 
+use analyzer::Analyzer;
+use util::{BRED, BYELL, ECOL};
+
+pub const MAX_PHRASE_LEN: usize = 3; // max number of words in a phrase looked up in a query
+const SEPARATOR: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Synonym {
+    pub phrase: String, // normalized, words of a multi word phrase are sorted
+    pub weight: f32,
+    pub span: usize, // number of query words it stands for, from the word it's attached to
+}
+
+impl Synonym {
+    #[inline]
+    pub fn new(phrase: &str, weight: f32) -> Self {
+        let mut words: Vec<&str> = phrase.split(" ").filter(|w| !w.is_empty()).collect();
+        words.sort();
+
+        Synonym {
+            phrase: words.join(" "),
+            weight: weight,
+            span: 1,
+        }
+    }
+
+    #[inline]
+    pub fn is_phrase(&self) -> bool {
+        self.phrase.contains(" ")
+    }
+}
+
+enum Store {
+    Dict(FnvHashMap<String, Vec<Synonym>>),
+    Fst(Map),
+}
+
+pub struct Synonyms {
+    store: Store,
+}
+
+impl Synonyms {
+    pub fn from_dict(dict: FnvHashMap<String, Vec<Synonym>>) -> Self {
+        Synonyms {
+            store: Store::Dict(dict),
+        }
+    }
+
+    // returns alternatives of a normalized word or phrase
+    #[inline]
+    pub fn get(&self, phrase: &str) -> Vec<Synonym> {
+        match self.store {
+            Store::Dict(ref dict) => dict.get(phrase).cloned().unwrap_or(vec![]),
+            Store::Fst(ref map) => {
+                use fst::{IntoStreamer, Streamer};
+
+                let mut lower = phrase.as_bytes().to_vec();
+                lower.push(SEPARATOR);
+                let mut upper = phrase.as_bytes().to_vec();
+                upper.push(SEPARATOR + 1);
+
+                let mut alternatives = vec![];
+                let mut stream = map.range().ge(&lower).lt(&upper).into_stream();
+                while let Some((key, weight)) = stream.next() {
+                    alternatives.push(Synonym {
+                        phrase: String::from_utf8_lossy(&key[lower.len()..]).into_owned(),
+                        weight: f32::from_bits(weight as u32),
+                        span: 1,
+                    });
+                }
+
+                alternatives
+            }
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, phrase: &str) -> bool {
+        !self.get(phrase).is_empty()
+    }
+}
+
 #[inline]
-pub fn parse_line(line: &str) -> Result<(String, String), Error> {
-    let v: Vec<&str> = line.split(" ").map(|t| t.trim()).collect();
-    let (word, synonym) = match v.len() {
-        2 => (v[0].to_string(), v[1].to_string()),
-        _ => panic!("Unknown format: {:?}!", &line),
+fn parse_alternative(alternative: &str, analyzer: &dyn Analyzer) -> Option<Synonym> {
+    let mut split = alternative.rsplitn(2, "|");
+    let (phrase, weight) = match (split.next(), split.next()) {
+        (Some(weight), Some(phrase)) => match weight.trim().parse::<f32>() {
+            Ok(weight) => (phrase, weight),
+            Err(_) => (alternative, 1.0),
+        },
+        _ => (alternative, 1.0),
     };
 
-    Ok((word, synonym))
+    let phrase = analyzer.normalize(phrase);
+    if phrase.is_empty() {
+        return None;
+    }
+
+    Some(Synonym::new(&phrase, weight))
 }
 
-pub fn load(path: &Path) -> Option<FnvHashMap<String, String>> {
-    if path.is_file() {
-        Some(parse_file(&path).unwrap())
+// returns None for empty lines, comments and lines without any alternative
+#[inline]
+pub fn parse_line(line: &str, analyzer: &dyn Analyzer) -> Option<(String, Vec<Synonym>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("#") {
+        return None;
+    }
+
+    let (phrase, alternatives): (String, Vec<Synonym>) = if line.contains("\t") {
+        let mut split = line.split("\t");
+        let phrase = split.next().unwrap_or("");
+
+        (
+            phrase.to_string(),
+            split
+                .filter_map(|a| parse_alternative(a, analyzer))
+                .collect(),
+        )
     } else {
-        None
+        let mut split = line.split(" ").filter(|t| !t.is_empty());
+        let phrase = split.next().unwrap_or("");
+
+        (
+            phrase.to_string(),
+            split
+                .filter_map(|a| parse_alternative(a, analyzer))
+                .collect(),
+        )
+    };
+
+    let phrase = analyzer.normalize(&phrase);
+    let alternatives: Vec<Synonym> = alternatives
+        .into_iter()
+        .filter(|s: &Synonym| s.phrase != phrase)
+        .collect();
+
+    if phrase.is_empty() || alternatives.is_empty() {
+        return None;
+    }
+
+    Some((phrase, alternatives))
+}
+
+// text synonyms are normalized with the given analyzer, fst ones are already normalized
+pub fn load(path: &Path, analyzer: &dyn Analyzer) -> Option<Synonyms> {
+    if !path.is_file() {
+        return None;
     }
+
+    if path.extension().map(|e| e == "fst").unwrap_or(false) {
+        return match Map::from_path(path) {
+            Ok(map) => Some(Synonyms {
+                store: Store::Fst(map),
+            }),
+            Err(err) => {
+                println!(
+                    "{}, err: {:?}",
+                    [
+                        BYELL,
+                        "Failed to load synonyms: ",
+                        ECOL,
+                        BRED,
+                        path.to_str().unwrap(),
+                        ECOL
+                    ]
+                    .join(""),
+                    err
+                );
+
+                None
+            }
+        };
+    }
+
+    Some(Synonyms::from_dict(parse_file(&path, analyzer)))
 }
 
-fn parse_file(path: &Path) -> Result<FnvHashMap<String, String>, Error> {
-    let mut synonyms = FnvHashMap::default();
+fn parse_file(path: &Path, analyzer: &dyn Analyzer) -> FnvHashMap<String, Vec<Synonym>> {
+    let mut synonyms: FnvHashMap<String, Vec<Synonym>> = FnvHashMap::default();
 
-    let f = File::open(path)?;
+    let f = File::open(path).unwrap();
     let file = BufReader::new(&f);
 
     for line in file.lines() {
         let line = line.unwrap();
-        let (word, synonym) = parse_line(&line).unwrap();
-        synonyms.insert(word, synonym);
+        if let Some((phrase, alternatives)) = parse_line(&line, analyzer) {
+            let phrase_synonyms = synonyms.entry(phrase).or_insert(vec![]);
+            for alternative in alternatives.into_iter() {
+                if !phrase_synonyms
+                    .iter()
+                    .any(|s| s.phrase == alternative.phrase)
+                {
+                    phrase_synonyms.push(alternative);
+                }
+            }
+        }
     }
 
-    Ok(synonyms)
+    synonyms
+}
+
+// Compiles a synonyms text file into the fst format
+pub fn compile(
+    input_path: &Path,
+    output_path: &Path,
+    analyzer: &dyn Analyzer,
+) -> Result<usize, fst::Error> {
+    let synonyms = parse_file(input_path, analyzer);
+
+    let mut entries: Vec<(Vec<u8>, u64)> = vec![];
+    for (phrase, alternatives) in synonyms.into_iter() {
+        for alternative in alternatives.into_iter() {
+            let mut key = phrase.as_bytes().to_vec();
+            key.push(SEPARATOR);
+            key.extend_from_slice(alternative.phrase.as_bytes());
+            entries.push((key, alternative.weight.to_bits() as u64));
+        }
+    }
+    entries.sort();
+
+    let wtr = BufWriter::new(File::create(output_path)?);
+    let mut builder = MapBuilder::new(wtr)?;
+    for (key, weight) in entries.iter() {
+        builder.insert(key, *weight)?;
+    }
+    builder.finish()?;
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyzer::DefaultAnalyzer;
+    use std::env::temp_dir;
+    use std::io::Write;
+
+    #[test]
+    fn test_parse_line() {
+        let (phrase, syns) = parse_line("millions million", &DefaultAnalyzer).unwrap();
+        assert_eq!(phrase, "millions");
+        assert_eq!(syns, vec![Synonym::new("million", 1.0)]);
+
+        let (phrase, syns) = parse_line("nyc new york", &DefaultAnalyzer).unwrap();
+        assert_eq!(phrase, "nyc");
+        assert_eq!(
+            syns,
+            vec![Synonym::new("new", 1.0), Synonym::new("york", 1.0)]
+        );
+
+        let (phrase, syns) =
+            parse_line("NYC\tnew york|0.9\tnew york city|0.8", &DefaultAnalyzer).unwrap();
+        assert_eq!(phrase, "nyc");
+        assert_eq!(
+            syns,
+            vec![
+                Synonym::new("new york", 0.9),
+                Synonym::new("city new york", 0.8)
+            ]
+        );
+        assert_eq!(syns[1].phrase, "city new york");
+
+        let (phrase, syns) = parse_line("new york\tnyc", &DefaultAnalyzer).unwrap();
+        assert_eq!(phrase, "new york");
+        assert_eq!(syns, vec![Synonym::new("nyc", 1.0)]);
+
+        assert_eq!(parse_line("", &DefaultAnalyzer), None);
+        assert_eq!(parse_line("# comment", &DefaultAnalyzer), None);
+        assert_eq!(parse_line("lonely", &DefaultAnalyzer), None);
+    }
+
+    #[test]
+    fn test_compile_load_fst() {
+        let txt_path = temp_dir().join("test_synonyms.txt");
+        {
+            let mut file = File::create(&txt_path).unwrap();
+            file.write_all(b"nyc\tnew york|0.9\tnew york city|0.8\nmillions million\n")
+                .unwrap();
+        }
+
+        let fst_path = temp_dir().join("test_synonyms.fst");
+        assert_eq!(compile(&txt_path, &fst_path, &DefaultAnalyzer).unwrap(), 3);
+
+        let txt_synonyms = load(&txt_path, &DefaultAnalyzer).unwrap();
+        let fst_synonyms = load(&fst_path, &DefaultAnalyzer).unwrap();
+        for phrase in vec!["nyc", "millions", "million", "new york"] {
+            let mut txt_syns = txt_synonyms.get(phrase);
+            txt_syns.sort_by(|a, b| a.phrase.cmp(&b.phrase));
+            assert_eq!(txt_syns, fst_synonyms.get(phrase));
+        }
+        assert_eq!(fst_synonyms.get("millions")[0].phrase, "million");
+        assert!(!fst_synonyms.contains("nyc new"));
+    }
 }
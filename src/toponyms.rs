@@ -1,7 +1,97 @@
+/*
+    Toponyms are single or multi word place names kept in an fst set. Toponym synonyms
+    (e.g. "muenchen munich") are in the synonyms file format, every phrase with alternatives
+    in the file is a toponym as well.
+*/
+
 use fst::Set;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use analyzer::Analyzer;
+use config::Config;
+use synonyms::{self, Synonym, Synonyms};
+use util::{self, BRED, BYELL, ECOL};
+
+pub const MAX_TOPONYM_LEN: usize = 3; // max number of words in a toponym, e.g. "rio de janeiro"
+pub const POLICIES: [&str; 3] = ["top", "all", "none"];
+pub const DEFAULT_POLICY: &str = "top";
+
+// Which of the toponyms found in a query are must-have words
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    Top,  // only the most relevant toponym
+    All,  // every toponym, e.g. both places of "flights paris to berlin"
+    None, // toponyms are ranked as any other word
+}
+
+impl Policy {
+    pub fn get(name: &str) -> Option<Policy> {
+        match name {
+            "top" => Some(Policy::Top),
+            "all" => Some(Policy::All),
+            "none" => Some(Policy::None),
+            _ => None,
+        }
+    }
+}
+
+pub struct Toponyms {
+    set: Set,
+    synonyms: Option<Synonyms>,
+    pub policy: Policy,
+}
 
-use util::{BRED, BYELL, ECOL};
+impl Toponyms {
+    pub fn new(set: Set, synonyms: Option<Synonyms>, policy: Policy) -> Self {
+        Toponyms {
+            set: set,
+            synonyms: synonyms,
+            policy: policy,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, phrase: &str) -> bool {
+        self.set.contains(phrase)
+            || self
+                .synonyms
+                .as_ref()
+                .map(|s| s.contains(phrase))
+                .unwrap_or(false)
+    }
+
+    // returns the alternative names of a toponym
+    #[inline]
+    pub fn synonyms(&self, phrase: &str) -> Vec<Synonym> {
+        match self.synonyms {
+            Some(ref synonyms) => synonyms.get(phrase),
+            None => vec![],
+        }
+    }
+
+    // Finds toponyms of up to MAX_TOPONYM_LEN words, the longest one first, left to right.
+    // Returns the spans of words, [start, end), of the found toponyms.
+    pub fn find(&self, words: &[String]) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = vec![];
+
+        let mut i = 0;
+        while i < words.len() {
+            let max_len = util::min(MAX_TOPONYM_LEN, words.len() - i);
+            match (1..max_len + 1)
+                .rev()
+                .find(|len| self.contains(&words[i..i + len].join(" ")))
+            {
+                Some(len) => {
+                    spans.push((i, i + len));
+                    i += len;
+                }
+                None => i += 1,
+            }
+        }
+
+        spans
+    }
+}
 
 pub fn load(path: &Path) -> Option<fst::Set> {
     if !path.is_file() {
@@ -28,3 +118,88 @@ pub fn load(path: &Path) -> Option<fst::Set> {
         }
     }
 }
+
+// toponyms, toponym synonyms and the must-have policy set in the index config.json
+pub fn from_config(path: &str, c: &Config, analyzer: &dyn Analyzer) -> Option<Toponyms> {
+    let policy = match Policy::get(&c.toponyms_policy) {
+        Some(policy) => policy,
+        None => panic!([
+            BYELL,
+            "Unknown toponyms policy: ",
+            ECOL,
+            BRED,
+            &c.toponyms_policy,
+            ECOL,
+            BYELL,
+            ", expected one of: ",
+            ECOL,
+            &POLICIES.join(", "),
+        ]
+        .join("")),
+    };
+
+    let set = load(&PathBuf::from(path).join(&c.toponyms_file))?;
+    let synonyms = synonyms::load(
+        &PathBuf::from(path).join(&c.toponym_synonyms_file),
+        analyzer,
+    );
+
+    Some(Toponyms::new(set, synonyms, policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyzer::DefaultAnalyzer;
+    use fnv::FnvHashMap;
+
+    fn toponyms(policy: Policy) -> Toponyms {
+        let set = Set::from_iter(vec!["berlin", "new york", "paris", "rio de janeiro"]).unwrap();
+        let mut dict = FnvHashMap::default();
+        let (phrase, alternatives) =
+            synonyms::parse_line("muenchen munich", &DefaultAnalyzer).unwrap();
+        dict.insert(phrase, alternatives);
+
+        Toponyms::new(set, Some(Synonyms::from_dict(dict)), policy)
+    }
+
+    fn words(query: &str) -> Vec<String> {
+        query.split(" ").map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find() {
+        let toponyms = toponyms(Policy::All);
+
+        assert_eq!(
+            toponyms.find(&words("flights paris to berlin")),
+            vec![(1, 2), (3, 4)]
+        );
+        assert_eq!(toponyms.find(&words("new york hotels")), vec![(0, 2)]);
+        assert_eq!(
+            toponyms.find(&words("hotels in rio de janeiro")),
+            vec![(2, 5)]
+        );
+        assert_eq!(toponyms.find(&words("york hotels")), vec![]);
+        assert_eq!(toponyms.find(&words("muenchen hotels")), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_synonyms() {
+        let toponyms = toponyms(Policy::Top);
+
+        assert!(toponyms.contains("muenchen"));
+        assert_eq!(
+            toponyms.synonyms("muenchen"),
+            vec![Synonym::new("munich", 1.0)]
+        );
+        assert!(toponyms.synonyms("paris").is_empty());
+    }
+
+    #[test]
+    fn test_policy() {
+        assert_eq!(Policy::get("all"), Some(Policy::All));
+        assert_eq!(Policy::get(DEFAULT_POLICY), Some(Policy::Top));
+        assert_eq!(Policy::get("some"), None);
+    }
+}
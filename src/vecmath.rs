@@ -0,0 +1,249 @@
+/*
+    Vector math for word vectors. The portable implementation is used by default, with
+    the `blas` cargo feature the same functions are computed by the system OpenBLAS.
+    Half precision conversions are for f16 word vectors.
+
+    The portable loops work on LANES floats at a time with independent accumulators,
+    so that the compiler can auto-vectorize them.
+*/
+
+#[cfg(feature = "blas")]
+pub use self::openblas::{dot, nrm2, saxpy, scale};
+#[cfg(not(feature = "blas"))]
+pub use self::portable::{dot, nrm2, saxpy, scale};
+
+const LANES: usize = 8;
+
+pub mod portable {
+    use super::LANES;
+
+    #[inline]
+    pub fn dot(v: &[f32], u: &[f32]) -> f32 {
+        let n = ::std::cmp::min(v.len(), u.len());
+        let (v, u) = (&v[..n], &u[..n]);
+
+        let mut acc = [0.0f32; LANES];
+        let mut v_chunks = v.chunks_exact(LANES);
+        let mut u_chunks = u.chunks_exact(LANES);
+        for (vc, uc) in (&mut v_chunks).zip(&mut u_chunks) {
+            for i in 0..LANES {
+                acc[i] += vc[i] * uc[i];
+            }
+        }
+
+        let rest = v_chunks
+            .remainder()
+            .iter()
+            .zip(u_chunks.remainder().iter())
+            .fold(0.0, |sum, (a, b)| sum + a * b);
+
+        acc.iter().sum::<f32>() + rest
+    }
+
+    #[inline]
+    pub fn nrm2(v: &[f32]) -> f32 {
+        dot(v, v).sqrt()
+    }
+
+    // v = a * v
+    #[inline]
+    pub fn scale(a: f32, v: &mut [f32]) {
+        for x in v.iter_mut() {
+            *x *= a;
+        }
+    }
+
+    // v = a * u + v
+    #[inline]
+    pub fn saxpy(a: f32, u: &[f32], v: &mut [f32]) {
+        for (y, x) in v.iter_mut().zip(u.iter()) {
+            *y += a * *x;
+        }
+    }
+}
+
+#[cfg(feature = "blas")]
+pub mod openblas {
+    use blas;
+
+    #[inline]
+    pub fn dot(v: &[f32], u: &[f32]) -> f32 {
+        let n = ::std::cmp::min(v.len(), u.len());
+        unsafe { blas::sdot(n as i32, v, 1, u, 1) }
+    }
+
+    #[inline]
+    pub fn nrm2(v: &[f32]) -> f32 {
+        unsafe { blas::snrm2(v.len() as i32, v, 1) }
+    }
+
+    // v = a * v
+    #[inline]
+    pub fn scale(a: f32, v: &mut [f32]) {
+        unsafe { blas::sscal(v.len() as i32, a, v, 1) }
+    }
+
+    // v = a * u + v
+    #[inline]
+    pub fn saxpy(a: f32, u: &[f32], v: &mut [f32]) {
+        let n = ::std::cmp::min(v.len(), u.len());
+        unsafe { blas::saxpy(n as i32, a, u, 1, v, 1) }
+    }
+}
+
+// IEEE half precision of a f32, rounded to the nearest even
+pub fn f32_to_f16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let man = bits & 0x7f_ffff;
+
+    // infinity and NaN
+    if exp == 0xff {
+        return sign | 0x7c00 | if man != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exp = exp - 127 + 15;
+    if half_exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // subnormal half or zero
+    if half_exp <= 0 {
+        if half_exp < -10 {
+            return sign;
+        }
+        let man = man | 0x80_0000;
+        let shift = (14 - half_exp) as u32;
+        let round_bit = 1 << (shift - 1);
+        let mut half_man = man >> shift;
+        if man & round_bit != 0 && man & (3 * round_bit - 1) != 0 {
+            half_man += 1;
+        }
+        return sign | half_man as u16;
+    }
+
+    // a carry of the rounding goes to the exponent
+    let mut half = ((half_exp as u32) << 10) | (man >> 13);
+    let round_bit = 0x1000;
+    if man & round_bit != 0 && man & (3 * round_bit - 1) != 0 {
+        half += 1;
+    }
+
+    sign | half as u16
+}
+
+pub fn f16_to_f32(h: u16) -> f32 {
+    let sign = ((h & 0x8000) as u32) << 16;
+    let exp = ((h >> 10) & 0x1f) as u32;
+    let man = (h & 0x3ff) as u32;
+
+    let bits = if exp == 0 {
+        if man == 0 {
+            sign
+        } else {
+            // subnormal half, normalized as f32
+            let (mut exp, mut man) = (127 - 15 + 1, man);
+            while man & 0x400 == 0 {
+                man <<= 1;
+                exp -= 1;
+            }
+            sign | (exp << 23) | ((man & 0x3ff) << 13)
+        }
+    } else if exp == 0x1f {
+        sign | 0x7f80_0000 | (man << 13)
+    } else {
+        sign | ((exp + 127 - 15) << 23) | (man << 13)
+    };
+
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn random_vec(len: usize) -> Vec<f32> {
+        let mut rng = rand::thread_rng();
+        (0..len).map(|_| rng.gen::<f32>() - 0.5).collect()
+    }
+
+    #[test]
+    fn test_portable() {
+        // lengths below, at and above the number of lanes
+        for len in vec![3, 8, 300, 301] {
+            let v = random_vec(len);
+            let u = random_vec(len);
+
+            let e_dot: f32 = v.iter().zip(u.iter()).map(|(a, b)| a * b).sum();
+            assert!((portable::dot(&v, &u) - e_dot).abs() < 1e-4);
+            assert!((portable::nrm2(&v) - portable::dot(&v, &v).sqrt()).abs() < 1e-6);
+
+            let mut w = v.clone();
+            portable::saxpy(-1.0, &u, &mut w);
+            for i in 0..len {
+                assert!((w[i] - (v[i] - u[i])).abs() < 1e-6);
+            }
+
+            portable::scale(2.0, &mut w);
+            for i in 0..len {
+                assert!((w[i] - 2.0 * (v[i] - u[i])).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_f16() {
+        for x in vec![
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            0.333251953125,
+            65504.0,
+            6.1035156e-5,
+            5.9604645e-8,
+        ] {
+            assert_eq!(f16_to_f32(f32_to_f16(x)), x);
+        }
+
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert!(f16_to_f32(f32_to_f16(::std::f32::NAN)).is_nan());
+
+        // relative error of a half is at most 2^-11
+        for x in random_vec(1000) {
+            let h = f16_to_f32(f32_to_f16(x));
+            assert!((h - x).abs() <= x.abs() / 2048.0 + 1e-7);
+        }
+    }
+
+    #[cfg(feature = "blas")]
+    #[test]
+    fn test_backends_agree() {
+        for _ in 0..10 {
+            let v = random_vec(300);
+            let u = random_vec(300);
+
+            assert!((portable::dot(&v, &u) - openblas::dot(&v, &u)).abs() < 1e-4);
+            assert!((portable::nrm2(&v) - openblas::nrm2(&v)).abs() < 1e-4);
+
+            let (mut p, mut o) = (v.clone(), v.clone());
+            portable::saxpy(0.3, &u, &mut p);
+            openblas::saxpy(0.3, &u, &mut o);
+            portable::scale(1.0 / portable::nrm2(&p), &mut p);
+            openblas::scale(1.0 / openblas::nrm2(&o), &mut o);
+            for i in 0..300 {
+                assert!((p[i] - o[i]).abs() < 1e-5);
+            }
+
+            // cosine distances of the normalized vectors
+            let mut q = u.clone();
+            portable::scale(1.0 / portable::nrm2(&q), &mut q);
+            let p_dist = 1.0 - portable::dot(&p, &q);
+            let o_dist = 1.0 - openblas::dot(&o, &q);
+            assert!((p_dist - o_dist).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,526 @@
+/*
+    Imports pretrained word vectors into the qpick format: a words file with a JSON string
+    per line and a word vectors file with a header (see word_vec), vectors in the same order.
+
+    Supported formats:
+        word2vec-bin  word2vec binary, a "<count> <dim>" line, then a word, a space and dim f32
+        word2vec      word2vec text, a "<count> <dim>" line, then a word and dim floats per line
+        fasttext      fastText .vec, same as the word2vec text format
+        glove         GloVe text, a word and dim floats per line, no header line
+*/
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use fnv::FnvHashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+use analyzer::Analyzer;
+use vecmath;
+use word_vec::{self, VecType};
+
+pub const FORMATS: [&str; 4] = ["word2vec-bin", "word2vec", "fasttext", "glove"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Word2VecBin,
+    Word2VecText,
+    FastText,
+    Glove,
+}
+
+impl Format {
+    pub fn get(name: &str) -> Option<Format> {
+        match name {
+            "word2vec-bin" => Some(Format::Word2VecBin),
+            "word2vec" => Some(Format::Word2VecText),
+            "fasttext" => Some(Format::FastText),
+            "glove" => Some(Format::Glove),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportStats {
+    pub dim: usize,
+    pub read: usize,       // words read from the input
+    pub imported: usize,   // words written to the output
+    pub filtered: usize,   // words not in the terms relevance map
+    pub duplicates: usize, // words equal to an already imported word after normalization
+    pub invalid: usize,    // words with a wrong dimension or not a single normalized word
+    pub terms: usize,      // words in the terms relevance map, 0 if not filtered
+}
+
+impl ImportStats {
+    // share of the terms relevance words that have a vector
+    pub fn coverage(&self) -> Option<f32> {
+        if self.terms == 0 {
+            return None;
+        }
+
+        Some(self.imported as f32 / self.terms as f32)
+    }
+}
+
+#[inline]
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+// parses the "<count> <dim>" header line of word2vec and fastText files
+fn parse_header(line: &str) -> Result<(usize, usize), Error> {
+    let values: Vec<usize> = line
+        .split_whitespace()
+        .filter_map(|v| v.parse::<usize>().ok())
+        .collect();
+    if values.len() != 2 {
+        return Err(invalid_data(&format!("Invalid header line: {:?}", line)));
+    }
+
+    Ok((values[0], values[1]))
+}
+
+fn read_text<R: BufRead>(
+    reader: R,
+    with_header: bool,
+    f: &mut dyn FnMut(String, Vec<f32>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut lines = reader.lines();
+    if with_header {
+        if let Some(line) = lines.next() {
+            parse_header(&line?)?;
+        }
+    }
+
+    for line in lines {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        let word = match tokens.next() {
+            Some(word) => word.to_string(),
+            None => continue,
+        };
+        // an unparsable value makes the vector invalid, it has less values than expected
+        let vec: Vec<f32> = tokens.map_while(|v| v.parse::<f32>().ok()).collect();
+        f(word, vec)?;
+    }
+
+    Ok(())
+}
+
+fn read_word2vec_bin<R: BufRead>(
+    mut reader: R,
+    f: &mut dyn FnMut(String, Vec<f32>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let (count, dim) = parse_header(&line)?;
+
+    let mut word: Vec<u8> = vec![];
+    let mut buf: Vec<u8> = vec![0; dim * 4];
+    for _ in 0..count {
+        word.clear();
+        if reader.read_until(b' ', &mut word)? == 0 {
+            break;
+        }
+        reader.read_exact(&mut buf)?;
+
+        // vectors may be followed by a new line
+        let word = String::from_utf8_lossy(&word).trim().to_string();
+        let vec: Vec<f32> = buf.chunks(4).map(LittleEndian::read_f32).collect();
+        f(word, vec)?;
+    }
+
+    Ok(())
+}
+
+pub fn import(
+    input: &Path,
+    format: Format,
+    words_output: &Path,
+    vecs_output: &Path,
+    vec_type: VecType,
+    normalize: bool,
+    terms_relevance: Option<&Path>,
+    analyzer: Option<&dyn Analyzer>,
+) -> Result<ImportStats, Error> {
+    let tr_map = match terms_relevance {
+        Some(path) => match fst::Map::from_path(path) {
+            Ok(tr_map) => Some(tr_map),
+            Err(err) => return Err(invalid_data(&format!("{:?}: {}", path, err))),
+        },
+        None => None,
+    };
+
+    let reader = BufReader::new(File::open(input)?);
+    let mut words_writer = BufWriter::new(File::create(words_output)?);
+    let mut vecs_writer = BufWriter::new(File::create(vecs_output)?);
+
+    let mut stats = ImportStats::default();
+    stats.terms = tr_map.as_ref().map(|tr| tr.len()).unwrap_or(0);
+    let mut seen_words: FnvHashSet<String> = FnvHashSet::default();
+
+    {
+        let mut write_vec = |word: String, mut vec: Vec<f32>| -> Result<(), Error> {
+            stats.read += 1;
+
+            // the dimension of the first vector is the one of the file
+            if stats.dim == 0 && !vec.is_empty() {
+                stats.dim = vec.len();
+                word_vec::write_header(&mut vecs_writer, stats.dim, vec_type)?;
+            }
+            if vec.len() != stats.dim {
+                stats.invalid += 1;
+                return Ok(());
+            }
+
+            let word = match analyzer {
+                Some(analyzer) => analyzer.normalize(&word),
+                None => word,
+            };
+            if word.is_empty() || word.contains(" ") {
+                stats.invalid += 1;
+                return Ok(());
+            }
+
+            if let Some(ref tr_map) = tr_map {
+                if !tr_map.contains_key(&word) {
+                    stats.filtered += 1;
+                    return Ok(());
+                }
+            }
+
+            // files are sorted by word frequency, the first vector of a word is kept
+            if seen_words.contains(&word) {
+                stats.duplicates += 1;
+                return Ok(());
+            }
+
+            if normalize {
+                let norm = vecmath::nrm2(&vec);
+                if norm > 0.0 {
+                    vecmath::scale(1.0 / norm, &mut vec);
+                }
+            }
+
+            writeln!(words_writer, "{}", serde_json::to_string(&word).unwrap())?;
+            word_vec::write_vec(&mut vecs_writer, &vec, vec_type)?;
+            seen_words.insert(word);
+            stats.imported += 1;
+
+            Ok(())
+        };
+
+        match format {
+            Format::Word2VecBin => read_word2vec_bin(reader, &mut write_vec)?,
+            Format::Word2VecText | Format::FastText => read_text(reader, true, &mut write_vec)?,
+            Format::Glove => read_text(reader, false, &mut write_vec)?,
+        }
+    }
+
+    words_writer.flush()?;
+    vecs_writer.flush()?;
+
+    Ok(stats)
+}
+
+// Converts a word vectors file to the given type, e.g. f32 vectors to int8 ones. config_dim is
+// the dimension of a file without a header, 0 for the default one. Returns the number of vectors.
+pub fn convert(
+    input: &Path,
+    output: &Path,
+    vec_type: VecType,
+    config_dim: usize,
+) -> Result<usize, Error> {
+    let file = File::open(input)?;
+    let data = unsafe { memmap::Mmap::map(&file)? };
+
+    let (dim, input_type, offset) = match word_vec::read_header(&data) {
+        Some((dim, input_type)) => (dim, input_type, word_vec::HEADER_SIZE),
+        None if config_dim > 0 => (config_dim, VecType::F32, 0),
+        None => (word_vec::DEFAULT_DIM, VecType::F32, 0),
+    };
+
+    let vec_size = input_type.vec_size(dim);
+    if dim == 0 || (data.len() - offset) % vec_size != 0 {
+        return Err(invalid_data(&format!(
+            "{:?} is not a file of {}-dimensional {:?} vectors",
+            input, dim, input_type
+        )));
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    word_vec::write_header(&mut writer, dim, vec_type)?;
+
+    let nr_vecs = (data.len() - offset) / vec_size;
+    for word_id in 0..nr_vecs {
+        let vec = word_vec::word_vec_at(&data[offset..], input_type, dim, word_id).to_vec(dim);
+        word_vec::write_vec(&mut writer, &vec, vec_type)?;
+    }
+    writer.flush()?;
+
+    Ok(nr_vecs)
+}
+
+const FASTTEXT_MAGIC: i32 = 793712314;
+const FASTTEXT_VERSION: i32 = 12;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SubwordStats {
+    pub dim: usize,
+    pub words: usize, // words of the model vocabulary, their vectors are not imported
+    pub buckets: usize, // imported n-gram bucket vectors
+    pub min_n: usize, // n-gram lengths to set as subword_min_n and subword_max_n
+    pub max_n: usize,
+}
+
+// Imports the character n-gram bucket vectors of a fastText .bin model, for the subword
+// vectors of the words out of the vocabulary. Pruned and quantized models are not supported.
+pub fn import_subwords(
+    input: &Path,
+    output: &Path,
+    vec_type: VecType,
+) -> Result<SubwordStats, Error> {
+    let mut reader = BufReader::new(File::open(input)?);
+
+    if reader.read_i32::<LittleEndian>()? != FASTTEXT_MAGIC {
+        return Err(invalid_data(&format!(
+            "{:?} is not a fastText model",
+            input
+        )));
+    }
+    let version = reader.read_i32::<LittleEndian>()?;
+    if version != FASTTEXT_VERSION {
+        return Err(invalid_data(&format!(
+            "Unsupported fastText model version: {}",
+            version
+        )));
+    }
+
+    // args: dim, ws, epoch, minCount, neg, wordNgrams, loss, model, bucket, minn, maxn,
+    // lrUpdateRate and t
+    let mut args = [0i32; 12];
+    for arg in args.iter_mut() {
+        *arg = reader.read_i32::<LittleEndian>()?;
+    }
+    reader.read_f64::<LittleEndian>()?;
+
+    let mut stats = SubwordStats {
+        dim: args[0] as usize,
+        buckets: args[8] as usize,
+        min_n: args[9] as usize,
+        max_n: args[10] as usize,
+        ..Default::default()
+    };
+    if stats.buckets == 0 || stats.min_n == 0 || stats.max_n < stats.min_n {
+        return Err(invalid_data("The fastText model has no subword vectors"));
+    }
+
+    // dictionary: size, nwords, nlabels, ntokens, pruneidx_size and the entries
+    let size = reader.read_i32::<LittleEndian>()?;
+    stats.words = reader.read_i32::<LittleEndian>()? as usize;
+    reader.read_i32::<LittleEndian>()?;
+    reader.read_i64::<LittleEndian>()?;
+    let pruneidx_size = reader.read_i64::<LittleEndian>()?;
+
+    let mut word: Vec<u8> = vec![];
+    for _ in 0..size {
+        word.clear();
+        reader.read_until(0, &mut word)?;
+        reader.read_i64::<LittleEndian>()?; // count
+        reader.read_i8()?; // entry type, a word or a label
+    }
+
+    if pruneidx_size > 0 {
+        return Err(invalid_data("Pruned fastText models are not supported"));
+    }
+    if reader.read_u8()? != 0 {
+        return Err(invalid_data("Quantized fastText models are not supported"));
+    }
+
+    // the input matrix, the word vectors followed by the bucket vectors
+    let nr_rows = reader.read_i64::<LittleEndian>()? as usize;
+    let nr_cols = reader.read_i64::<LittleEndian>()? as usize;
+    if nr_cols != stats.dim || nr_rows != stats.words + stats.buckets {
+        return Err(invalid_data(&format!(
+            "Invalid fastText input matrix: {}x{}",
+            nr_rows, nr_cols
+        )));
+    }
+
+    let words_bytes = (stats.words * stats.dim * 4) as u64;
+    if ::std::io::copy(&mut (&mut reader).take(words_bytes), &mut ::std::io::sink())? != words_bytes
+    {
+        return Err(invalid_data("Unexpected end of the fastText model"));
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    word_vec::write_header(&mut writer, stats.dim, vec_type)?;
+
+    let mut buf: Vec<u8> = vec![0; stats.dim * 4];
+    for _ in 0..stats.buckets {
+        reader.read_exact(&mut buf)?;
+        let vec: Vec<f32> = buf.chunks(4).map(LittleEndian::read_f32).collect();
+        word_vec::write_vec(&mut writer, &vec, vec_type)?;
+    }
+    writer.flush()?;
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyzer::DefaultAnalyzer;
+    use byteorder::WriteBytesExt;
+    use fst::MapBuilder;
+    use std::env::temp_dir;
+    use subwords::SubwordVecs;
+    use word_vec::WordVecs;
+
+    fn import_file(
+        name: &str,
+        content: &[u8],
+        format: Format,
+        terms_relevance: Option<&Path>,
+    ) -> (ImportStats, WordVecs<'static>) {
+        let dir = temp_dir();
+        let input = dir.join(format!("test_vectors_{}", name));
+        File::create(&input).unwrap().write_all(content).unwrap();
+
+        let words_output = dir.join(format!("test_vectors_{}.words", name));
+        let vecs_output = dir.join(format!("test_vectors_{}.vecs", name));
+        let stats = import(
+            &input,
+            format,
+            &words_output,
+            &vecs_output,
+            VecType::F32,
+            true,
+            terms_relevance,
+            Some(&DefaultAnalyzer),
+        )
+        .unwrap();
+
+        (stats, WordVecs::load(&words_output, &vecs_output, 0))
+    }
+
+    #[test]
+    fn test_import_text() {
+        let text = b"4 3\nparis 3 0 4\nParis 1 1 1\nberlin 0 2 0\nrome 1 x 0\n";
+        for format in vec![Format::Word2VecText, Format::FastText] {
+            let (stats, wv) = import_file("text", text, format, None);
+            assert_eq!(stats.dim, 3);
+            assert_eq!(stats.read, 4);
+            assert_eq!(stats.imported, 2);
+            assert_eq!(stats.duplicates, 1);
+            assert_eq!(stats.invalid, 1);
+            assert_eq!(wv.len(), 2);
+            assert_eq!(wv.get_vec("paris"), vec![0.6, 0.0, 0.8]);
+            assert_eq!(wv.get_vec("berlin"), vec![0.0, 1.0, 0.0]);
+        }
+
+        let glove = b"paris 3 0 4\nberlin 0 2 0\n";
+        let (stats, wv) = import_file("glove", glove, Format::Glove, None);
+        assert_eq!(stats.imported, 2);
+        assert_eq!(stats.coverage(), None);
+        assert_eq!(wv.get_vec("paris"), vec![0.6, 0.0, 0.8]);
+    }
+
+    #[test]
+    fn test_import_bin_filtered() {
+        let mut bin: Vec<u8> = b"3 2\n".to_vec();
+        for (word, vec) in vec![
+            ("paris", [3.0, 4.0]),
+            ("berlin", [0.0, 2.0]),
+            ("xyzzy", [1.0, 0.0]),
+        ] {
+            bin.extend(format!("{} ", word).into_bytes());
+            for v in vec.iter() {
+                bin.write_f32::<LittleEndian>(*v).unwrap();
+            }
+            bin.push(b'\n');
+        }
+
+        let tr_path = temp_dir().join("test_vectors_terms_relevance.fst");
+        let mut build = MapBuilder::new(BufWriter::new(File::create(&tr_path).unwrap())).unwrap();
+        build
+            .extend_iter(vec![
+                ("berlin", 50),
+                ("paris", 60),
+                ("rome", 40),
+                ("wien", 30),
+            ])
+            .unwrap();
+        build.finish().unwrap();
+
+        let (stats, wv) = import_file("bin", &bin, Format::Word2VecBin, Some(&tr_path));
+        assert_eq!(stats.dim, 2);
+        assert_eq!(stats.imported, 2);
+        assert_eq!(stats.filtered, 1);
+        assert_eq!(stats.coverage(), Some(0.5));
+        assert_eq!(wv.get_vec("paris"), vec![0.6, 0.8]);
+        assert!(wv.get_vec("xyzzy").is_empty());
+    }
+
+    #[test]
+    fn test_import_subwords() {
+        let (dim, buckets, words) = (2, 3, vec!["</s>", "paris"]);
+
+        let mut bin: Vec<u8> = vec![];
+        bin.write_i32::<LittleEndian>(FASTTEXT_MAGIC).unwrap();
+        bin.write_i32::<LittleEndian>(FASTTEXT_VERSION).unwrap();
+        for arg in [dim, 5, 5, 1, 5, 1, 1, 2, buckets, 3, 3, 100].iter() {
+            bin.write_i32::<LittleEndian>(*arg).unwrap();
+        }
+        bin.write_f64::<LittleEndian>(1e-4).unwrap();
+
+        bin.write_i32::<LittleEndian>(words.len() as i32).unwrap();
+        bin.write_i32::<LittleEndian>(words.len() as i32).unwrap();
+        bin.write_i32::<LittleEndian>(0).unwrap();
+        bin.write_i64::<LittleEndian>(100).unwrap();
+        bin.write_i64::<LittleEndian>(-1).unwrap();
+        for word in words.iter() {
+            bin.extend(word.as_bytes());
+            bin.push(0);
+            bin.write_i64::<LittleEndian>(50).unwrap();
+            bin.write_i8(0).unwrap();
+        }
+
+        bin.push(0);
+        bin.write_i64::<LittleEndian>(words.len() as i64 + buckets as i64)
+            .unwrap();
+        bin.write_i64::<LittleEndian>(dim as i64).unwrap();
+        for i in 0..(words.len() + buckets as usize) * dim as usize {
+            bin.write_f32::<LittleEndian>(i as f32).unwrap();
+        }
+
+        let input = temp_dir().join("test_vectors_fasttext.bin");
+        File::create(&input).unwrap().write_all(&bin).unwrap();
+        let output = temp_dir().join("test_vectors_fasttext.subwords");
+
+        let stats = import_subwords(&input, &output, VecType::F32).unwrap();
+        assert_eq!(
+            stats,
+            SubwordStats {
+                dim: 2,
+                words: 2,
+                buckets: 3,
+                min_n: 3,
+                max_n: 3,
+            }
+        );
+
+        // the bucket vectors follow the 2 word vectors
+        let sv = SubwordVecs::load(&output, stats.min_n, stats.max_n);
+        assert_eq!(sv.len(), 3);
+        let e_vec = sv.buckets("ab").iter().fold(vec![0.0, 0.0], |mut v, b| {
+            v[0] += (4 + 2 * b) as f32 / 2.0;
+            v[1] += (5 + 2 * b) as f32 / 2.0;
+            v
+        });
+        assert_eq!(sv.get_vec("ab"), e_vec);
+
+        assert!(import_subwords(&output, &output, VecType::F32).is_err());
+    }
+}
@@ -1,30 +1,29 @@
-use byteorder::{ByteOrder, LittleEndian};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use fnv::{FnvHashMap, FnvHashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Error, Write};
 
 use std::path::Path;
 
-use blas;
-
 use ngrams::MISS_WORD_REL;
 use std::mem::MaybeUninit;
+use stopwords::Stopwords;
+use subwords::SubwordVecs;
 use util;
+use vecmath;
 
-pub const DIM: usize = 300; // TODO move to the config
+pub const DEFAULT_DIM: usize = 300; // dimension of the word vectors files without a header
 pub const UPPER_COS_BOUND: f32 = 1.0;
 
 #[inline]
 pub fn normalize(v: &mut [f32]) {
-    unsafe {
-        let norm: f32 = blas::snrm2(DIM as i32, v, 1);
-        blas::sscal(DIM as i32, 1.0 / norm, v, 1)
-    }
+    let norm: f32 = vecmath::nrm2(v);
+    vecmath::scale(1.0 / norm, v)
 }
 
 #[inline]
 pub fn dot(v: &[f32], u: &[f32]) -> f32 {
-    unsafe { blas::sdot(DIM as i32, v, 1, u, 1) }
+    vecmath::dot(v, u)
 }
 
 #[inline]
@@ -33,8 +32,8 @@ pub fn cosine_distance(u: &Vec<f32>, v: &Vec<f32>) -> f32 {
 }
 
 #[inline]
-pub fn subtract(mut v: &mut [f32], u: &[f32]) {
-    unsafe { blas::saxpy(DIM as i32, -1f32, u, 1, &mut v, 1) }
+pub fn subtract(v: &mut [f32], u: &[f32]) {
+    vecmath::saxpy(-1f32, u, v)
 }
 
 // --- word dict and word vec
@@ -64,7 +63,7 @@ impl WordDict {
         self: &Self,
         word: &str,
         words_relevances: &fst::Map,
-        stopwords: &FnvHashSet<String>,
+        stopwords: &Stopwords,
     ) -> f32 {
         let word_rel: f32 = words_relevances.get(word).unwrap_or(MISS_WORD_REL) as f32;
 
@@ -85,7 +84,7 @@ impl WordDict {
         self: &Self,
         words: &Vec<String>,
         words_relevances: &fst::Map,
-        stopwords: &FnvHashSet<String>,
+        stopwords: &Stopwords,
     ) -> (Vec<(usize, f32)>, FnvHashSet<String>) {
         let mut not_found_words = FnvHashSet::default();
         let words_ids = words
@@ -135,43 +134,277 @@ impl Into<usize> for WordId {
     }
 }
 
-#[repr(C)]
-pub struct WordVec([f32; DIM]);
+/*
+    Word vectors file: an optional header, the magic bytes followed by the dimension
+    as u32 little endian, then the vectors, one per line of the words file. Files without
+    the header have f32 vectors of the dimension set in the config.
+
+    The last magic byte is the vectors type:
+        f32   dim f32 little endian values
+        f16   dim IEEE half precision values, u16 little endian
+        int8  a f32 little endian scale followed by dim i8 values, x = scale * q
+*/
+pub const MAGIC: &[u8; 3] = b"QWV";
+pub const HEADER_SIZE: usize = 8;
+pub const VEC_TYPES: [&str; 3] = ["f32", "f16", "int8"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VecType {
+    F32 = 1,
+    F16 = 2,
+    Int8 = 3,
+}
+
+impl VecType {
+    pub fn get(name: &str) -> Option<VecType> {
+        match name {
+            "f32" => Some(VecType::F32),
+            "f16" => Some(VecType::F16),
+            "int8" => Some(VecType::Int8),
+            _ => None,
+        }
+    }
+
+    pub fn from_byte(b: u8) -> Option<VecType> {
+        match b {
+            1 => Some(VecType::F32),
+            2 => Some(VecType::F16),
+            3 => Some(VecType::Int8),
+            _ => None,
+        }
+    }
+
+    // number of bytes of a vector
+    #[inline]
+    pub fn vec_size(self, dim: usize) -> usize {
+        match self {
+            VecType::F32 => 4 * dim,
+            VecType::F16 => 2 * dim,
+            VecType::Int8 => 4 + dim,
+        }
+    }
+}
+
+pub fn write_header<W: Write>(writer: &mut W, dim: usize, vec_type: VecType) -> Result<(), Error> {
+    writer.write_all(MAGIC)?;
+    writer.write_u8(vec_type as u8)?;
+    writer.write_u32::<LittleEndian>(dim as u32)
+}
+
+// returns the dimension and the vectors type from the header, None if the file has no header
+pub fn read_header(data: &[u8]) -> Option<(usize, VecType)> {
+    if data.len() < HEADER_SIZE || &data[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    let vec_type = VecType::from_byte(data[MAGIC.len()])?;
+    Some((
+        LittleEndian::read_u32(&data[MAGIC.len() + 1..HEADER_SIZE]) as usize,
+        vec_type,
+    ))
+}
+
+// writes a f32 vector as the given type
+pub fn write_vec<W: Write>(writer: &mut W, vec: &[f32], vec_type: VecType) -> Result<(), Error> {
+    match vec_type {
+        VecType::F32 => {
+            for v in vec.iter() {
+                writer.write_f32::<LittleEndian>(*v)?;
+            }
+        }
+        VecType::F16 => {
+            for v in vec.iter() {
+                writer.write_u16::<LittleEndian>(vecmath::f32_to_f16(*v))?;
+            }
+        }
+        VecType::Int8 => {
+            // symmetric scalar quantization, the largest absolute value maps to 127
+            let max = vec.iter().fold(0.0f32, |max, v| util::max(max, v.abs()));
+            let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+            writer.write_f32::<LittleEndian>(scale)?;
+            for v in vec.iter() {
+                writer.write_i8(util::min(127.0, util::max(-127.0, (v / scale).round())) as i8)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A word vector in the storage type of the word vectors file, dequantized on the fly
+pub enum WordVec<'a> {
+    F32(&'a [f32]),
+    F16(&'a [u8]),
+    Int8(f32, &'a [u8]),
+}
+
+impl<'a> WordVec<'a> {
+    // out = a * self + out
+    #[inline]
+    pub fn add_to(&self, a: f32, out: &mut [f32]) {
+        match *self {
+            WordVec::F32(v) => vecmath::saxpy(a, v, out),
+            WordVec::F16(v) => {
+                for (o, h) in out.iter_mut().zip(v.chunks(2)) {
+                    *o += a * vecmath::f16_to_f32(LittleEndian::read_u16(h));
+                }
+            }
+            WordVec::Int8(scale, v) => {
+                let a = a * scale;
+                for (o, q) in out.iter_mut().zip(v.iter()) {
+                    *o += a * (*q as i8) as f32;
+                }
+            }
+        }
+    }
+
+    // dot product with a f32 vector, w/o dequantizing the whole vector first
+    #[inline]
+    pub fn dot(&self, u: &[f32]) -> f32 {
+        match *self {
+            WordVec::F32(v) => vecmath::dot(v, u),
+            WordVec::F16(v) => v.chunks(2).zip(u.iter()).fold(0.0, |sum, (h, x)| {
+                sum + vecmath::f16_to_f32(LittleEndian::read_u16(h)) * x
+            }),
+            WordVec::Int8(scale, v) => {
+                scale
+                    * v.iter()
+                        .zip(u.iter())
+                        .fold(0.0, |sum, (q, x)| sum + (*q as i8) as f32 * x)
+            }
+        }
+    }
+
+    #[inline]
+    pub fn to_vec(&self, dim: usize) -> Vec<f32> {
+        let mut vec = vec![0.0; dim];
+        self.add_to(1.0, &mut vec);
+
+        vec
+    }
+}
+
+// the word vector at word_id of the vectors (w/o header) of the given type and dimension
+#[inline]
+pub fn word_vec_at(word_vecs: &[u8], vec_type: VecType, dim: usize, word_id: usize) -> WordVec {
+    let vec_size = vec_type.vec_size(dim);
+    let bytes = &word_vecs[word_id * vec_size..(word_id + 1) * vec_size];
+    match vec_type {
+        // the header and the vectors are 4 bytes aligned
+        VecType::F32 => {
+            WordVec::F32(unsafe { ::std::slice::from_raw_parts(bytes.as_ptr() as *const f32, dim) })
+        }
+        VecType::F16 => WordVec::F16(bytes),
+        VecType::Int8 => WordVec::Int8(LittleEndian::read_f32(&bytes[..4]), &bytes[4..]),
+    }
+}
 
 pub struct WordVecs<'a> {
     word_dict: WordDict,
-    word_vecs: &'a [WordVec],
+    word_vecs: &'a [u8],
+    vec_type: VecType,
+    dim: usize,
+    subwords: Option<SubwordVecs<'a>>, // vectors of the words out of the vocabulary
     _data: memmap::Mmap,
 }
 
 impl<'a> WordVecs<'a> {
-    pub fn load(words_file: &Path, word_vecs_file: &Path) -> Self {
+    // config_dim is the dimension set in the config, 0 if not set
+    pub fn load(words_file: &Path, word_vecs_file: &Path, config_dim: usize) -> Self {
         let word_dict = WordDict::load(words_file);
 
         let file = File::open(word_vecs_file).unwrap();
         let data = unsafe { memmap::Mmap::map(&file).unwrap() };
-        assert_eq!(0, data.len() % ::std::mem::size_of::<WordVec>());
-        let word_vecs: &[WordVec] = unsafe {
-            ::std::slice::from_raw_parts(
-                data.as_ptr() as *const WordVec,
-                data.len() / ::std::mem::size_of::<WordVec>(),
-            )
+
+        let (dim, vec_type, offset) = match read_header(&data) {
+            Some((dim, vec_type)) => {
+                if config_dim > 0 && config_dim != dim {
+                    panic!(
+                        "Word vectors dimension {} in {:?} differs from {} in the config!",
+                        dim, word_vecs_file, config_dim
+                    );
+                }
+                (dim, vec_type, HEADER_SIZE)
+            }
+            None if config_dim > 0 => (config_dim, VecType::F32, 0),
+            None => (DEFAULT_DIM, VecType::F32, 0),
         };
 
+        let vec_size = vec_type.vec_size(dim);
+        if dim == 0 || (data.len() - offset) % vec_size != 0 {
+            panic!(
+                "Invalid word vectors file {:?}, {} bytes is not a multiple of {}-dimensional {:?} vectors!",
+                word_vecs_file,
+                data.len() - offset,
+                dim,
+                vec_type
+            );
+        }
+
+        let word_vecs: &[u8] =
+            unsafe { ::std::slice::from_raw_parts(data[offset..].as_ptr(), data.len() - offset) };
+
+        let nr_vecs = word_vecs.len() / vec_size;
+        if nr_vecs != word_dict.len() {
+            panic!(
+                "Number of words {} in {:?} differs from the number of vectors {} in {:?}!",
+                word_dict.len(),
+                words_file,
+                nr_vecs,
+                word_vecs_file
+            );
+        }
+
         Self {
             word_dict: word_dict,
             word_vecs: word_vecs,
+            vec_type: vec_type,
+            dim: dim,
+            subwords: None,
             _data: data,
         }
     }
 
+    // loads the subword vectors, of the same dimension, used for the words out of the vocabulary
+    pub fn load_subwords(self: &mut Self, subword_vecs_file: &Path, min_n: usize, max_n: usize) {
+        let subwords = SubwordVecs::load(subword_vecs_file, min_n, max_n);
+        if subwords.dim() != self.dim {
+            panic!(
+                "Subword vectors dimension {} in {:?} differs from the word vectors dimension {}!",
+                subwords.dim(),
+                subword_vecs_file,
+                self.dim
+            );
+        }
+
+        self.subwords = Some(subwords);
+    }
+
+    #[inline]
+    pub fn dim(self: &Self) -> usize {
+        self.dim
+    }
+
+    #[inline]
+    pub fn vec_type(self: &Self) -> VecType {
+        self.vec_type
+    }
+
+    #[inline]
+    fn word_vec(self: &Self, word_id: usize) -> WordVec {
+        word_vec_at(self.word_vecs, self.vec_type, self.dim, word_id)
+    }
+
     #[inline]
     pub fn get_vec(self: &Self, word: &str) -> Vec<f32> {
         if let Some(word_id) = self.word_dict.get_word_id(word) {
-            return self.word_vecs[*word_id].0.to_vec();
+            return self.word_vec(*word_id).to_vec(self.dim);
         }
 
-        vec![]
+        match self.subwords {
+            Some(ref subwords) => subwords.get_vec(word),
+            None => vec![],
+        }
     }
 
     #[inline]
@@ -179,33 +412,35 @@ impl<'a> WordVecs<'a> {
         self: &Self,
         words: &Vec<String>,
         words_relevances: &fst::Map,
-        stopwords: &FnvHashSet<String>,
+        stopwords: &Stopwords,
     ) -> (Vec<f32>, usize, FnvHashSet<String>) {
-        let (word_ids_rels, not_found_words) =
+        let (word_ids_rels, mut not_found_words) =
             self.word_dict
                 .get_words_ids(words, words_relevances, stopwords);
 
-        let words_vec = word_ids_rels
-            .iter()
-            .fold([0.0; DIM], |mut data, (i, word_rel)| {
-                let WordVec(ref v) = self.word_vecs[*i];
-                unsafe {
-                    blas::saxpy(
-                        DIM as i32, // if mult { *word_rel } else { 1f32 },
-                        *word_rel, v, 1, &mut data, 1,
-                    )
-                }
-
-                data
-            })
-            .to_vec();
+        let mut words_vec =
+            word_ids_rels
+                .iter()
+                .fold(vec![0.0; self.dim], |mut data, (i, word_rel)| {
+                    self.word_vec(*i).add_to(*word_rel, &mut data);
+
+                    data
+                });
+
+        // words out of the vocabulary get the vectors of their character n-grams
+        if let Some(ref subwords) = self.subwords {
+            not_found_words.retain(|w| {
+                let word_rel = self.word_dict.get_word_rel(w, words_relevances, stopwords);
+                !subwords.add_to(w, word_rel, &mut words_vec)
+            });
+        }
 
         (words_vec, not_found_words.len(), not_found_words)
     }
 
     #[inline]
     pub fn len(self: &Self) -> usize {
-        self.word_vecs.len()
+        self.word_vecs.len() / self.vec_type.vec_size(self.dim)
     }
 }
 
@@ -239,25 +474,18 @@ mod tests {
             .collect()
     }
 
-    fn get_random_word_vecs(num: usize) -> Vec<WordVec> {
+    fn get_random_word_vecs(num: usize, dim: usize) -> Vec<f32> {
         let mut rng = rand::thread_rng();
 
-        (0..num)
-            .map(|_| {
-                let mut vec = [0.0f32; DIM];
-                for f in &mut vec[..] {
-                    *f = rng.gen();
-                }
-
-                WordVec(vec)
-            })
-            .collect()
+        (0..num * dim).map(|_| rng.gen()).collect()
     }
 
-    #[test]
-    fn test_words_write_load() {
-        let mut buffer: Vec<u8> = Vec::new();
-        let num_words = 3;
+    fn write_words_vecs(
+        name: &str,
+        num_words: usize,
+        dim: usize,
+        header: bool,
+    ) -> (PathBuf, PathBuf) {
         let words_len = 5;
 
         let mut rnd_bytes: Vec<u8> = Vec::with_capacity(num_words * words_len);
@@ -266,34 +494,148 @@ mod tests {
         }
 
         let mut tmp_words_file = temp_dir();
-        tmp_words_file.push("test_words");
+        tmp_words_file.push(format!("test_words_{}", name));
         {
             let mut file = File::create(&tmp_words_file).unwrap();
             file.write_all(&rnd_bytes[..]).unwrap();
         }
 
-        buffer.resize(::std::mem::size_of::<WordVec>() * num_words, 0u8);
-        let rnd_word_vecs = get_random_word_vecs(num_words);
-        // reinterpret as [u8] in order to write to buffer
-        buffer.copy_from_slice(unsafe {
-            ::std::slice::from_raw_parts(
-                rnd_word_vecs.as_ptr() as *const u8,
-                rnd_word_vecs.len() * ::std::mem::size_of::<WordVec>(),
-            )
-        });
+        let mut buffer: Vec<u8> = Vec::new();
+        if header {
+            write_header(&mut buffer, dim, VecType::F32).unwrap();
+        }
+        for f in get_random_word_vecs(num_words, dim).into_iter() {
+            buffer.write_f32::<LittleEndian>(f).unwrap();
+        }
 
         let mut tmp_word_vecs_file = temp_dir();
-        tmp_word_vecs_file.push("test_word_embeddings");
+        tmp_word_vecs_file.push(format!("test_word_embeddings_{}", name));
         {
             let mut file = File::create(&tmp_word_vecs_file).unwrap();
             file.write_all(&buffer).unwrap();
         }
 
-        let tmp_words_path = PathBuf::from(&tmp_words_file);
-        let tmp_word_vecs_path = PathBuf::from(&tmp_word_vecs_file);
-        let wv = WordVecs::load(&tmp_words_path, &tmp_word_vecs_path);
+        (tmp_words_file, tmp_word_vecs_file)
+    }
 
-        assert_eq!(num_words, wv.word_vecs.len());
+    #[test]
+    fn test_words_write_load() {
+        let num_words = 3;
+
+        // w/o header, the default or the config dimension
+        let (words_path, word_vecs_path) =
+            write_words_vecs("legacy", num_words, DEFAULT_DIM, false);
+        let wv = WordVecs::load(&words_path, &word_vecs_path, 0);
+        assert_eq!(num_words, wv.len());
         assert_eq!(num_words, wv.word_dict.len());
+        assert_eq!(DEFAULT_DIM, wv.dim());
+
+        let (words_path, word_vecs_path) = write_words_vecs("config", num_words, 50, false);
+        let wv = WordVecs::load(&words_path, &word_vecs_path, 50);
+        assert_eq!(num_words, wv.len());
+        assert_eq!(50, wv.dim());
+
+        // with header
+        let (words_path, word_vecs_path) = write_words_vecs("header", num_words, 100, true);
+        let wv = WordVecs::load(&words_path, &word_vecs_path, 0);
+        assert_eq!(num_words, wv.len());
+        assert_eq!(100, wv.dim());
+        assert_eq!(100, wv.word_vec(2).to_vec(wv.dim()).len());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_load_words_vecs_count_mismatch() {
+        let (words_path, _) = write_words_vecs("words_mismatch", 3, 10, true);
+        let (_, word_vecs_path) = write_words_vecs("vecs_mismatch", 4, 10, true);
+        WordVecs::load(&words_path, &word_vecs_path, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_load_dim_mismatch() {
+        let (words_path, word_vecs_path) = write_words_vecs("dim_mismatch", 3, 10, true);
+        WordVecs::load(&words_path, &word_vecs_path, 20);
+    }
+
+    #[test]
+    fn test_subwords() {
+        let (words_path, word_vecs_path) = write_words_vecs("subwords", 3, 10, true);
+        let mut wv = WordVecs::load(&words_path, &word_vecs_path, 0);
+
+        let subword_vecs_path = temp_dir().join("test_word_embeddings_subwords_buckets");
+        {
+            let mut file = File::create(&subword_vecs_path).unwrap();
+            write_header(&mut file, 10, VecType::F16).unwrap();
+            for vec in get_random_word_vecs(7, 10).chunks(10) {
+                write_vec(&mut file, vec, VecType::F16).unwrap();
+            }
+        }
+
+        let oov_words = vec!["hotelz".to_string()];
+        let words_relevances = fst::Map::from_iter(vec![("hotelz", 20)]).unwrap();
+        let stopwords = Stopwords::empty();
+
+        let (_, nf, _) = wv.get_combined_vec(&oov_words, &words_relevances, &stopwords);
+        assert_eq!(nf, 1);
+        assert!(wv.get_vec("hotelz").is_empty());
+
+        wv.load_subwords(&subword_vecs_path, 3, 6);
+        let (vec, nf, _) = wv.get_combined_vec(&oov_words, &words_relevances, &stopwords);
+        assert_eq!(nf, 0);
+        let e_vec: Vec<f32> = wv.get_vec("hotelz").iter().map(|v| 20.0 * v).collect();
+        for (v, e) in vec.iter().zip(e_vec.iter()) {
+            assert!((v - e).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_subwords_dim_mismatch() {
+        let (words_path, word_vecs_path) = write_words_vecs("subwords_mismatch", 3, 10, true);
+        let mut wv = WordVecs::load(&words_path, &word_vecs_path, 0);
+        let (_, subword_vecs_path) = write_words_vecs("subwords_mismatch_buckets", 3, 20, true);
+        wv.load_subwords(&subword_vecs_path, 3, 6);
+    }
+
+    #[test]
+    fn test_quantized_cosine() {
+        let (words_path, word_vecs_path) = write_words_vecs("quantized", 20, 100, true);
+        let wv = WordVecs::load(&words_path, &word_vecs_path, 0);
+
+        let mut words: Vec<String> = wv.word_dict.word_to_id.keys().cloned().collect();
+        words.sort();
+        let words_relevances =
+            fst::Map::from_iter(words.iter().enumerate().map(|(i, w)| (w, 10 + i as u64))).unwrap();
+        let stopwords = Stopwords::empty();
+
+        let combined_vec = |wv: &WordVecs, words: &[String]| {
+            let (mut vec, _, _) =
+                wv.get_combined_vec(&words.to_vec(), &words_relevances, &stopwords);
+            normalize(&mut vec);
+            vec
+        };
+
+        // cosine distances of the quantized vectors are within the tolerance of the f32 ones
+        for &(vec_type, tolerance) in [(VecType::F16, 1e-3), (VecType::Int8, 1e-2)].iter() {
+            let quantized_path = temp_dir().join(format!("test_word_embeddings_{:?}", vec_type));
+            ::vectors::convert(&word_vecs_path, &quantized_path, vec_type, 0).unwrap();
+            let qwv = WordVecs::load(&words_path, &quantized_path, 0);
+            assert_eq!(qwv.vec_type(), vec_type);
+            assert_eq!(qwv.len(), wv.len());
+
+            for i in 0..words.len() - 3 {
+                let (u, v) = (&words[i..i + 2], &words[i + 2..i + 4]);
+                let dist = cosine_distance(&combined_vec(&wv, u), &combined_vec(&wv, v));
+                let q_dist = cosine_distance(&combined_vec(&qwv, u), &combined_vec(&qwv, v));
+                assert!(
+                    (dist - q_dist).abs() < tolerance,
+                    "{:?}: {} {}",
+                    vec_type,
+                    dist,
+                    q_dist
+                );
+            }
+        }
     }
 }